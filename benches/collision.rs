@@ -0,0 +1,132 @@
+// build and query times for the collision trees against brute force, on bumpy grids of growing size.
+// run with 'cargo bench --bench collision', or e.g. 'cargo bench --bench collision -- ray/' for one query type
+
+use bevy::prelude::*;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+
+use spiderman::math;
+use spiderman::physics::collision::{build_collision_tree, collision_brute_force, collision_hits, sphere_cast, sweep_sphere_triangle, Triangles, TRIANGLE_LIMIT};
+
+// quads per side, each quad is two triangles. 44 is about the size of the model the numbers on 'collision_internal' came from
+const GRID_SIZES: [usize; 4] = [8, 22, 44, 100];
+const RAYS: usize = 256;
+const SPHERE_RADIUS: f32 = 0.5;
+
+// small lcg so runs are repeatable without pulling in rand
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> f32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next()
+    }
+}
+
+// a heightfield over [-size, size] on x and z, with random bumps so the boxes aren't flat
+fn grid(size: usize, rng: &mut Rng) -> Vec<Triangle3d> {
+    let heights: Vec<f32> = (0..(size + 1) * (size + 1)).map(|_| rng.range(-1.0, 1.0)).collect();
+    let point = |x: usize, z: usize| Vec3::new(x as f32 * 2.0 - size as f32, heights[z * (size + 1) + x], z as f32 * 2.0 - size as f32);
+
+    let mut triangles = Vec::with_capacity(size * size * 2);
+    for z in 0..size {
+        for x in 0..size {
+            triangles.push(Triangle3d::new(point(x, z), point(x + 1, z), point(x, z + 1)));
+            triangles.push(Triangle3d::new(point(x + 1, z), point(x + 1, z + 1), point(x, z + 1)));
+        }
+    }
+
+    triangles
+}
+
+// from above the grid down onto it, at random angles
+fn rays(size: usize, rng: &mut Rng) -> Vec<math::Ray3d> {
+    let extent = size as f32;
+    (0..RAYS)
+        .map(|_| {
+            let origin = Vec3::new(rng.range(-extent, extent), rng.range(5.0, 20.0), rng.range(-extent, extent));
+            let dir = Vec3::new(rng.range(-0.5, 0.5), -1.0, rng.range(-0.5, 0.5)).normalize();
+            math::Ray3d::new(origin, dir)
+        })
+        .collect()
+}
+
+fn brute_force_sphere(ray: math::Ray3d, radius: f32, triangles: &[Triangle3d], transform: &Mat4) -> Option<f32> {
+    triangles
+        .iter()
+        .filter_map(|triangle| sweep_sphere_triangle(ray, radius, &triangle.vertices.map(|v| transform.transform_point3(v))))
+        .map(|(t, _, _)| t)
+        .min_by(f32::total_cmp)
+}
+
+fn transform() -> Mat4 {
+    Mat4::from_scale_rotation_translation(Vec3::splat(1.5), Quat::from_rotation_y(0.3), Vec3::new(4.0, -2.0, 1.0))
+}
+
+fn bench_build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build");
+    let mut rng = Rng(1);
+
+    for size in GRID_SIZES {
+        let triangles = grid(size, &mut rng);
+        group.throughput(Throughput::Elements(triangles.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(triangles.len()), &triangles, |b, triangles| {
+            b.iter(|| build_collision_tree(black_box(triangles), TRIANGLE_LIMIT))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_ray(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ray");
+    let mut rng = Rng(2);
+    let transform = transform();
+
+    for size in GRID_SIZES {
+        let triangles = Triangles(grid(size, &mut rng));
+        let tree = build_collision_tree(&triangles.0, TRIANGLE_LIMIT);
+        let rays = rays(size, &mut rng);
+        let n = triangles.0.len();
+
+        group.throughput(Throughput::Elements(RAYS as u64));
+        group.bench_with_input(BenchmarkId::new("tree", n), &rays, |b, rays| {
+            b.iter(|| rays.iter().map(|ray| collision_hits(*ray, &tree, &triangles, &transform).len()).sum::<usize>())
+        });
+        group.bench_with_input(BenchmarkId::new("brute_force", n), &rays, |b, rays| {
+            b.iter(|| rays.iter().map(|ray| collision_brute_force(*ray, &triangles, &transform).len()).sum::<usize>())
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_sphere_sweep(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sphere_sweep");
+    let mut rng = Rng(3);
+    let transform = transform();
+
+    for size in GRID_SIZES {
+        let triangles = Triangles(grid(size, &mut rng));
+        let tree = build_collision_tree(&triangles.0, TRIANGLE_LIMIT);
+        let rays = rays(size, &mut rng);
+        let n = triangles.0.len();
+
+        group.throughput(Throughput::Elements(RAYS as u64));
+        group.bench_with_input(BenchmarkId::new("tree", n), &rays, |b, rays| {
+            b.iter(|| rays.iter().filter_map(|ray| sphere_cast(*ray, SPHERE_RADIUS, &tree, &triangles, &transform)).count())
+        });
+        group.bench_with_input(BenchmarkId::new("brute_force", n), &rays, |b, rays| {
+            b.iter(|| rays.iter().filter_map(|ray| brute_force_sphere(*ray, SPHERE_RADIUS, &triangles.0, &transform)).count())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_build, bench_ray, bench_sphere_sweep);
+criterion_main!(benches);
@@ -0,0 +1,217 @@
+use bevy::color::palettes::css::LIME;
+use bevy::prelude::*;
+
+use crate::anchors::AnchorIndex;
+use crate::camera::{CameraSet, CameraState};
+use crate::game::GameSet;
+use crate::math;
+use crate::physics::layers::{CollisionWorld, QueryFilter};
+use crate::physics::surfaces::SurfaceMaterials;
+use crate::tuning::TuningAppExt;
+
+// how the web picks what to stick to. every candidate is scored, the best one wins
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct AimConfig {
+    pub max_distance: f32, // longest web
+    pub min_distance: f32, // anything closer isn't worth swinging from
+    pub preferred_distance: f32, // scores best on distance
+    pub min_height: f32, // candidates further below the eye than this are dropped
+    pub cone_angle: f32, // degrees off the camera ray
+    pub rings: usize, // rays are cast on this many circles around the camera ray, plus the ray itself
+    pub rays_per_ring: usize,
+    pub angle_weight: f32,
+    pub distance_weight: f32,
+    pub height_weight: f32,
+    pub anchor_weight: f32, // times an 'AnchorPoint's quality, on top of its score
+    #[reflect(ignore)]
+    pub draw: bool, // highlight the target, needs the renderer
+}
+
+impl Default for AimConfig {
+    fn default() -> Self {
+        Self {
+            max_distance: 60.0,
+            min_distance: 2.0,
+            preferred_distance: 25.0,
+            min_height: -2.0,
+            cone_angle: 20.0,
+            rings: 3,
+            rays_per_ring: 12,
+            angle_weight: 1.0,
+            distance_weight: 0.5,
+            height_weight: 0.75,
+            anchor_weight: 0.5,
+            draw: true,
+        }
+    }
+}
+
+impl AimConfig {
+    pub fn headless() -> Self {
+        Self {
+            draw: false,
+            ..default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnchorCandidate {
+    pub entity: Entity,
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub score: f32,
+}
+
+// where the web would go if it was fired now, updated every frame by 'select_anchor'
+#[derive(Resource, Default, Debug)]
+pub struct AimTarget(pub Option<AnchorCandidate>);
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AimSet;
+
+// picks the web target for the player's view. runs after 'CameraSet' and before 'GameSet::Update', which reads 'AimTarget'
+#[derive(Default)]
+pub struct AimPlugin {
+    pub config: AimConfig,
+}
+
+impl Plugin for AimPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .init_resource::<AimTarget>()
+            .configure_sets(Update, AimSet.after(CameraSet).before(GameSet::Update))
+            .add_systems(Update, select_anchor.in_set(AimSet))
+            .add_tunable::<AimConfig>("aim");
+
+        if self.config.draw {
+            app.add_systems(Update, draw_aim_target.after(AimSet));
+        }
+    }
+}
+
+// higher is better, 'None' if it's out of range, outside the cone or too low
+pub fn score_anchor(config: &AimConfig, eye: Vec3, forward: Vec3, point: Vec3) -> Option<f32> {
+    let offset = point - eye;
+    let distance = offset.length();
+    if distance < config.min_distance || distance > config.max_distance {
+        return None;
+    }
+
+    let height = offset.y;
+    if height < config.min_height {
+        return None;
+    }
+
+    let angle = forward.angle_between(offset).to_degrees();
+    if angle > config.cone_angle {
+        return None;
+    }
+
+    let angle_score = 1.0 - angle / config.cone_angle.max(f32::EPSILON);
+    let distance_score = 1.0 - (distance - config.preferred_distance).abs() / config.max_distance;
+    let height_score = (height / config.preferred_distance).clamp(-1.0, 1.0);
+
+    Some(config.angle_weight * angle_score + config.distance_weight * distance_score + config.height_weight * height_score)
+}
+
+// 'forward' first, then 'rings' circles out to 'cone_angle' (degrees)
+pub fn cone_directions(forward: Vec3, cone_angle: f32, rings: usize, rays_per_ring: usize) -> Vec<Vec3> {
+    let forward = forward.normalize();
+    let (right, up) = forward.any_orthonormal_pair();
+    let mut directions = vec![forward];
+
+    for ring in 1..=rings {
+        let theta = (cone_angle * ring as f32 / rings as f32).to_radians();
+        for i in 0..rays_per_ring {
+            let phi = std::f32::consts::TAU * i as f32 / rays_per_ring as f32;
+            let around = right * phi.cos() + up * phi.sin();
+            directions.push(forward * theta.cos() + around * theta.sin());
+        }
+    }
+
+    directions
+}
+
+// nothing between 'eye' and 'point' but whatever 'point' is on
+pub fn has_line_of_sight(world: &CollisionWorld, eye: Vec3, point: Vec3) -> bool {
+    // distances are in multiples of the whole offset, so anything short of 1 is in the way
+    const SLACK: f32 = 1e-3;
+    world.cast_ray_closest(math::Ray3d::new(eye, point - eye), &QueryFilter::line_of_sight())
+        .is_none_or(|hit| hit.distance >= 1.0 - SLACK)
+}
+
+// the first thing each ray of the cone sees, if a web sticks to it
+fn cone_candidates(world: &CollisionWorld, surfaces: &SurfaceMaterials, config: &AimConfig, eye: Vec3, forward: Vec3) -> Vec<(Entity, Vec3, Vec3)> {
+    let sight = QueryFilter::line_of_sight();
+    let web = QueryFilter::web_swing();
+
+    cone_directions(forward, config.cone_angle, config.rings, config.rays_per_ring).into_iter().filter_map(|dir| {
+        let ray = math::Ray3d::new(eye, dir);
+        let seen = world.cast_ray_closest(ray, &sight)?;
+        // the web can only go there if it's the same surface the eye sees
+        let hit = world.cast_ray_closest(ray, &web).filter(|hit| hit.entity == seen.entity && (hit.distance - seen.distance).abs() < 1e-3)?;
+        surfaces.get(hit.surface).web_stickable.then_some((hit.entity, hit.point, hit.normal))
+    }).collect()
+}
+
+// the best of what the cone of rays hit and the extracted anchors in the cone that can be seen
+pub fn select_anchor(
+    world: CollisionWorld,
+    cam: Single<&CameraState>,
+    config: Res<AimConfig>,
+    surfaces: Res<SurfaceMaterials>,
+    anchors: Res<AnchorIndex>,
+    mut target: ResMut<AimTarget>,
+) {
+    let hit = cone_candidates(&world, &surfaces, &config, cam.pos, cam.forward).into_iter().filter_map(|(entity, point, normal)| {
+        let score = score_anchor(&config, cam.pos, cam.forward, point)?;
+        Some(AnchorCandidate { entity, point, normal, score })
+    }).max_by(|a, b| a.score.total_cmp(&b.score));
+
+    let mut extracted: Vec<AnchorCandidate> = anchors.within_cone(cam.pos, cam.forward, config.cone_angle, config.max_distance).filter_map(|anchor| {
+        let score = score_anchor(&config, cam.pos, cam.forward, anchor.anchor.point)? + config.anchor_weight * anchor.anchor.quality;
+        Some(AnchorCandidate { entity: anchor.entity, point: anchor.anchor.point, normal: anchor.anchor.normal, score })
+    }).collect();
+
+    // line of sight is a ray against everything, so only check the ones that could win
+    extracted.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let extracted = extracted.into_iter()
+        .take_while(|candidate| hit.is_none_or(|hit| candidate.score > hit.score))
+        .find(|candidate| has_line_of_sight(&world, cam.pos, candidate.point));
+
+    target.0 = extracted.or(hit);
+}
+
+const TARGET_RADIUS: f32 = 0.3;
+
+pub fn draw_aim_target(target: Res<AimTarget>, mut gizmos: Gizmos) {
+    if let Some(target) = target.0 {
+        gizmos.sphere(Isometry3d::from_translation(target.point), TARGET_RADIUS, LIME);
+        gizmos.arrow(target.point, target.point + target.normal, LIME);
+    }
+}
+
+#[test]
+fn test_score_anchor() {
+    let config = AimConfig::default();
+    let score = |point: Vec3| score_anchor(&config, Vec3::ZERO, Vec3::X, point);
+
+    // straight ahead beats off to the side, above beats below
+    assert!(score(Vec3::new(25.0, 0.0, 0.0)) > score(Vec3::new(25.0, 0.0, 5.0)));
+    assert!(score(Vec3::new(25.0, 2.0, 0.0)) > score(Vec3::new(25.0, -2.0, 0.0)));
+    assert!(score(Vec3::new(25.0, 0.0, 0.0)) > score(Vec3::new(55.0, 0.0, 0.0)));
+
+    assert_eq!(score(Vec3::new(1.0, 0.0, 0.0)), None);
+    assert_eq!(score(Vec3::new(70.0, 0.0, 0.0)), None);
+    assert_eq!(score(Vec3::new(20.0, -5.0, 0.0)), None);
+    assert_eq!(score(Vec3::new(10.0, 0.0, 10.0)), None);
+
+    let directions = cone_directions(Vec3::X, 20.0, 3, 12);
+    assert_eq!(directions.len(), 1 + 3 * 12);
+    for dir in directions {
+        assert!((dir.length() - 1.0).abs() < 1e-5);
+        assert!(dir.angle_between(Vec3::X).to_degrees() < 20.0 + 1e-3);
+    }
+    assert_eq!(cone_directions(Vec3::X, 20.0, 0, 12), [Vec3::X]);
+}
@@ -0,0 +1,151 @@
+use bevy::prelude::*;
+
+use crate::camera::CameraState;
+use crate::input::PlayerInput;
+use crate::player::{fall_movement, PlayerConfig, PlayerMotion, PlayerSet, PlayerState};
+use crate::tuning::TuningAppExt;
+
+// how the player moves through the air. everything's in units and seconds
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct AirConfig {
+    pub steer_accel: f32, // horizontal, from WASD
+    pub steer_max_speed: f32, // steering won't push past this, but doesn't slow anything already faster
+    pub drag: f32, // fraction of the speed lost every second
+    pub max_fall_speed: f32,
+    pub dive_gravity: f32, // times 'PlayerConfig::gravity' while diving
+    pub dive_accel: f32, // horizontal, the way the camera's looking
+    pub dive_max_fall_speed: f32,
+    pub trick_boost: f32, // added along the velocity
+    pub trick_style: f32,
+    pub trick_cooldown: f32,
+    pub combo_bonus: f32, // each trick in one jump is worth this much more style than the last
+}
+
+impl Default for AirConfig {
+    fn default() -> Self {
+        Self {
+            steer_accel: 8.0,
+            steer_max_speed: 8.0,
+            drag: 0.05,
+            max_fall_speed: 40.0,
+            dive_gravity: 2.0,
+            dive_accel: 4.0,
+            dive_max_fall_speed: 70.0,
+            trick_boost: 3.0,
+            trick_style: 100.0,
+            trick_cooldown: 0.6,
+            combo_bonus: 0.5,
+        }
+    }
+}
+
+// the current time in the air, reset on landing
+#[derive(Resource, Default, Debug)]
+pub struct AirState {
+    pub diving: bool,
+    pub combo: u32, // tricks since leaving the ground
+    pub pending: f32, // style from them, banked on landing
+    pub cooldown: f32, // until the next trick
+}
+
+// style banked from every landing so far
+#[derive(Resource, Default, Debug)]
+pub struct Style(pub f32);
+
+// sent for every trick, for animation, audio and the HUD
+#[derive(Event, Clone, Copy, Debug)]
+pub struct AirTrick {
+    pub combo: u32, // 1 for the first since leaving the ground
+    pub style: f32,
+}
+
+// steering, diving and tricks while 'PlayerState::Falling'. velocity from whatever the player left (the ground, a web)
+// is kept. E does a trick, holding down (left shift) dives. runs in 'PlayerSet' before the fall itself, needs 'PlayerPlugin'
+#[derive(Default)]
+pub struct AirPlugin {
+    pub config: AirConfig,
+}
+
+impl Plugin for AirPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .init_resource::<AirState>()
+            .init_resource::<Style>()
+            .add_event::<AirTrick>()
+            .add_systems(Update, air_control.run_if(in_state(PlayerState::Falling)).before(fall_movement).in_set(PlayerSet))
+            .add_systems(OnEnter(PlayerState::Walking), bank_style)
+            .add_tunable::<AirConfig>("air");
+    }
+}
+
+// 'horizontal' pushed 'accel' along 'dir', without going past 'max_speed' or its own speed, whichever is higher
+pub fn steer(horizontal: Vec3, dir: Vec3, accel: f32, max_speed: f32) -> Vec3 {
+    let limit = horizontal.length().max(max_speed);
+    (horizontal + dir * accel).clamp_length_max(limit)
+}
+
+pub fn air_control(
+    input: Res<PlayerInput>,
+    time: Res<Time>,
+    config: Res<AirConfig>,
+    player_config: Res<PlayerConfig>,
+    mut air: ResMut<AirState>,
+    mut tricks: EventWriter<AirTrick>,
+    player: Single<(&CameraState, &mut PlayerMotion)>,
+) {
+    let (camera_state, mut motion) = player.into_inner();
+    let dt = time.delta().as_secs_f32();
+    let forward = Vec3::new(camera_state.forward.x, 0.0, camera_state.forward.z).normalize_or_zero();
+    let right = Vec3::new(camera_state.right.x, 0.0, camera_state.right.z).normalize_or_zero();
+
+    let dir = (forward * input.movement.z + right * input.movement.x).clamp_length_max(1.0);
+    let horizontal = Vec3::new(motion.velocity.x, 0.0, motion.velocity.z);
+    let mut horizontal = steer(horizontal, dir, config.steer_accel * dt, config.steer_max_speed);
+
+    air.diving = input.movement.y < 0.0;
+    let max_fall_speed = if air.diving {
+        motion.velocity.y -= (config.dive_gravity - 1.0) * player_config.gravity * dt;
+        horizontal += forward * config.dive_accel * dt;
+        config.dive_max_fall_speed
+    } else {
+        config.max_fall_speed
+    };
+
+    motion.velocity = Vec3::new(horizontal.x, motion.velocity.y.max(-max_fall_speed), horizontal.z);
+    motion.velocity *= (1.0 - config.drag * dt).max(0.0);
+
+    air.cooldown = (air.cooldown - dt).max(0.0);
+    if input.trick && air.cooldown == 0.0 {
+        air.combo += 1;
+        air.cooldown = config.trick_cooldown;
+        let style = config.trick_style * (1.0 + config.combo_bonus * (air.combo - 1) as f32);
+        air.pending += style;
+        let boost = motion.velocity.normalize_or_zero() * config.trick_boost;
+        motion.velocity += boost;
+        tricks.send(AirTrick { combo: air.combo, style });
+    }
+}
+
+fn bank_style(mut air: ResMut<AirState>, mut style: ResMut<Style>) {
+    if air.combo > 0 {
+        info!("Landed {} tricks for {:.0} style", air.combo, air.pending);
+    }
+
+    style.0 += air.pending;
+    *air = AirState::default();
+}
+
+#[test]
+fn test_steer() {
+    // speeds up to the limit and no further
+    let v = steer(Vec3::ZERO, Vec3::X, 5.0, 8.0);
+    assert_eq!(v, Vec3::new(5.0, 0.0, 0.0));
+    assert_eq!(steer(v, Vec3::X, 5.0, 8.0), Vec3::new(8.0, 0.0, 0.0));
+
+    // anything faster keeps its speed, but can still be turned or slowed
+    let fast = Vec3::new(20.0, 0.0, 0.0);
+    assert_eq!(steer(fast, Vec3::X, 5.0, 8.0), fast);
+    let turned = steer(fast, Vec3::Z, 5.0, 8.0);
+    assert!((turned.length() - 20.0).abs() < 1e-4 && turned.z > 0.0, "{turned}");
+    assert_eq!(steer(fast, Vec3::NEG_X, 5.0, 8.0), Vec3::new(15.0, 0.0, 0.0));
+}
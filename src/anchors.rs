@@ -0,0 +1,482 @@
+use bevy::color::palettes::css::{AQUA, FUCHSIA, GOLD, ORANGE};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::physics::collision::Triangles;
+use crate::physics::debug::ColliderDebug;
+use crate::physics::dynamic::DeformableCollider;
+use crate::physics::layers::{CollisionLayers, Layers};
+use crate::physics::surfaces::{SurfaceMaterials, TriangleSurfaces};
+use crate::tuning::TuningAppExt;
+
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct AnchorConfig {
+    pub spacing: f32, // distance between anchors along an edge, and the closest two anchors can be
+    pub min_edge_angle: f32, // degrees between the faces of an edge before it counts
+    pub up_threshold: f32, // how far a face normal has to point up (or down) to be a roof (or an overhang)
+    pub cell_size: f32, // of the 'AnchorIndex' grid
+    #[reflect(ignore)]
+    pub draw: bool, // show them with the collider overlay, needs the renderer
+}
+
+impl Default for AnchorConfig {
+    fn default() -> Self {
+        Self {
+            spacing: 2.0,
+            min_edge_angle: 30.0,
+            up_threshold: 0.7,
+            cell_size: 8.0,
+            draw: true,
+        }
+    }
+}
+
+impl AnchorConfig {
+    pub fn headless() -> Self {
+        Self {
+            draw: false,
+            ..default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AnchorKind {
+    Corner, // where two rooftop edges meet
+    Ledge, // the edge of a roof or anything else flat on top
+    Overhang, // the underside of something
+    Edge, // any other sharp convex edge
+}
+
+impl AnchorKind {
+    pub fn base_quality(self) -> f32 {
+        match self {
+            AnchorKind::Corner => 1.0,
+            AnchorKind::Ledge => 0.8,
+            AnchorKind::Overhang => 0.6,
+            AnchorKind::Edge => 0.4,
+        }
+    }
+
+    fn color(self) -> Srgba {
+        match self {
+            AnchorKind::Corner => GOLD,
+            AnchorKind::Ledge => ORANGE,
+            AnchorKind::Overhang => AQUA,
+            AnchorKind::Edge => FUCHSIA,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnchorPoint {
+    pub point: Vec3,
+    pub normal: Vec3, // away from the surface
+    pub kind: AnchorKind,
+    pub quality: f32, // 0 to 1
+}
+
+// a collider's anchors in its local space, found once by 'extract_collider_anchors'
+#[derive(Component, Debug, Default)]
+pub struct Anchors(pub Vec<AnchorPoint>);
+
+// vertices closer than this are the same vertex, glTF meshes split them at every uv seam
+const WELD: f32 = 1e-4;
+// how far behind a face the other face of an edge has to go to be convex
+const CONVEX_EPSILON: f32 = 1e-4;
+
+fn face_normal(t: &Triangle3d) -> Vec3 {
+    let [a, b, c] = t.vertices;
+    (b - a).cross(c - a).normalize_or_zero()
+}
+
+// finds anchors in 'triangles', with +y as up. 'stickable' says which triangles webs stick to, empty for all of them
+pub fn extract_anchors(triangles: &[Triangle3d], stickable: &[bool], config: &AnchorConfig) -> Vec<AnchorPoint> {
+    let stickable = |i: usize| stickable.get(i).copied().unwrap_or(true);
+
+    // weld the vertices, then find the triangles on either side of every edge
+    let mut ids: HashMap<IVec3, u32> = HashMap::new();
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut weld = |p: Vec3| *ids.entry((p / WELD).round().as_ivec3()).or_insert_with(|| {
+        positions.push(p);
+        positions.len() as u32 - 1
+    });
+
+    let mut faces: Vec<([u32; 3], Vec3)> = Vec::with_capacity(triangles.len());
+    let mut edges: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+    for t in triangles {
+        let face = [weld(t.vertices[0]), weld(t.vertices[1]), weld(t.vertices[2])];
+        let i = faces.len();
+        faces.push((face, face_normal(t)));
+
+        if face[0] == face[1] || face[1] == face[2] || face[2] == face[0] {
+            continue;
+        }
+        for (a, b) in [(face[0], face[1]), (face[1], face[2]), (face[2], face[0])] {
+            edges.entry((a.min(b), a.max(b))).or_default().push(i);
+        }
+    }
+
+    let third = |face: usize, (a, b): (u32, u32)| faces[face].0.into_iter().find(|v| *v != a && *v != b).unwrap();
+    let is_up = |n: Vec3| n.y > config.up_threshold;
+
+    let mut anchors = Vec::new();
+    let mut ledges_at: HashMap<u32, Vec<(Vec3, Vec3)>> = HashMap::new(); // vertex, (direction along the ledge, normal)
+
+    // sorted, so the same mesh always gives the same anchors in the same order
+    let mut edges: Vec<((u32, u32), Vec<usize>)> = edges.into_iter().collect();
+    edges.sort_unstable_by_key(|(edge, _)| *edge);
+
+    for ((a, b), on) in &edges {
+        let (a, b) = (*a, *b);
+        let (pa, pb) = (positions[a as usize], positions[b as usize]);
+
+        let (kind, normal, sharpness) = match *on.as_slice() {
+            // the open edge of something flat on top, e.g. a plane
+            [i] if is_up(faces[i].1) && stickable(i) => {
+                let mut outward = (pb - pa).cross(faces[i].1).normalize_or_zero();
+                if outward.dot(positions[third(i, (a, b)) as usize] - pa) > 0.0 {
+                    outward = -outward;
+                }
+                (AnchorKind::Ledge, (faces[i].1 + outward).normalize_or_zero(), 1.0)
+            }
+            [i, j] if stickable(i) || stickable(j) => {
+                let (ni, nj) = (faces[i].1, faces[j].1);
+                let convex = ni.dot(positions[third(j, (a, b)) as usize] - pa) < -CONVEX_EPSILON;
+                let angle = ni.angle_between(nj).to_degrees();
+                if !convex || angle < config.min_edge_angle || ni == Vec3::ZERO || nj == Vec3::ZERO {
+                    continue;
+                }
+
+                let kind = if is_up(ni) != is_up(nj) { AnchorKind::Ledge } else { AnchorKind::Edge };
+                (kind, (ni + nj).normalize_or_zero(), (angle / 90.0).min(1.0))
+            }
+            _ => continue,
+        };
+
+        let quality = kind.base_quality() * (0.5 + 0.5 * sharpness);
+        let count = ((pa.distance(pb) / config.spacing).ceil() as usize).max(1);
+        for k in 0..count {
+            let point = pa.lerp(pb, (k as f32 + 0.5) / count as f32);
+            anchors.push(AnchorPoint { point, normal, kind, quality });
+        }
+
+        if kind == AnchorKind::Ledge {
+            ledges_at.entry(a).or_default().push(((pb - pa).normalize(), normal));
+            ledges_at.entry(b).or_default().push(((pa - pb).normalize(), normal));
+        }
+    }
+
+    // rooftop corners, where ledges meet at an angle rather than carrying straight on
+    let mut ledges_at: Vec<(u32, Vec<(Vec3, Vec3)>)> = ledges_at.into_iter().collect();
+    ledges_at.sort_unstable_by_key(|(vertex, _)| *vertex);
+    for (vertex, ledges) in &ledges_at {
+        let turns = ledges.iter().enumerate().any(|(i, (d1, _))| {
+            ledges[i + 1..].iter().any(|(d2, _)| d1.dot(*d2).abs() < 0.9)
+        });
+        if turns {
+            let normal = ledges.iter().map(|(_, n)| *n).sum::<Vec3>().normalize_or_zero();
+            anchors.push(AnchorPoint { point: positions[*vertex as usize], normal, kind: AnchorKind::Corner, quality: 1.0 });
+        }
+    }
+
+    // undersides
+    for (i, t) in triangles.iter().enumerate() {
+        let normal = faces[i].1;
+        if normal.y < -config.up_threshold && stickable(i) {
+            let [a, b, c] = t.vertices;
+            anchors.push(AnchorPoint { point: (a + b + c) / 3.0, normal, kind: AnchorKind::Overhang, quality: AnchorKind::Overhang.base_quality() });
+        }
+    }
+
+    // no two closer than about half the spacing, the best one stays
+    anchors.sort_by(|a, b| b.quality.total_cmp(&a.quality));
+    let mut taken: HashMap<IVec3, Vec<Vec3>> = HashMap::new();
+    let cell = config.spacing * 0.5;
+    anchors.retain(|anchor| {
+        let key = (anchor.point / cell).floor().as_ivec3();
+        let too_close = (-1..=1).flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| IVec3::new(x, y, z)))).any(|offset| {
+            taken.get(&(key + offset)).is_some_and(|points| points.iter().any(|p| p.distance(anchor.point) < cell))
+        });
+        if !too_close {
+            taken.entry(key).or_default().push(anchor.point);
+        }
+        !too_close
+    });
+
+    anchors
+}
+
+// an anchor where it is right now
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorldAnchor {
+    pub entity: Entity,
+    pub anchor: AnchorPoint, // in world space
+}
+
+// every collider's anchors in world space, bucketed in a grid. 'update_anchor_index' takes a collider's anchors out and
+// puts them back wherever it moved to
+#[derive(Resource, Debug)]
+pub struct AnchorIndex {
+    cell_size: f32,
+    cells: HashMap<IVec3, Vec<WorldAnchor>>,
+    entity_cells: HashMap<Entity, Vec<IVec3>>, // the cells each entity has anchors in, so they can be found again
+    len: usize,
+}
+
+impl Default for AnchorIndex {
+    fn default() -> Self {
+        Self::new(AnchorConfig::default().cell_size)
+    }
+}
+
+impl AnchorIndex {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            entity_cells: HashMap::new(),
+            len: 0,
+        }
+    }
+
+    fn cell(&self, point: Vec3) -> IVec3 {
+        (point / self.cell_size).floor().as_ivec3()
+    }
+
+    pub fn insert(&mut self, anchor: WorldAnchor) {
+        let cell = self.cell(anchor.anchor.point);
+        self.cells.entry(cell).or_default().push(anchor);
+        self.len += 1;
+
+        let cells = self.entity_cells.entry(anchor.entity).or_default();
+        if !cells.contains(&cell) {
+            cells.push(cell);
+        }
+    }
+
+    // all of an entity's anchors
+    pub fn remove(&mut self, entity: Entity) {
+        for cell in self.entity_cells.remove(&entity).unwrap_or_default() {
+            let Some(anchors) = self.cells.get_mut(&cell) else {
+                continue;
+            };
+
+            let before = anchors.len();
+            anchors.retain(|anchor| anchor.entity != entity);
+            self.len -= before - anchors.len();
+            if anchors.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+    }
+
+    pub fn clear(&mut self, cell_size: f32) {
+        *self = Self::new(cell_size);
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &WorldAnchor> {
+        self.cells.values().flatten()
+    }
+
+    pub fn within_radius(&self, center: Vec3, radius: f32) -> impl Iterator<Item = &WorldAnchor> {
+        let (min, max) = (self.cell(center - Vec3::splat(radius)), self.cell(center + Vec3::splat(radius)));
+
+        (min.x..=max.x).flat_map(move |x| (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| IVec3::new(x, y, z))))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .filter(move |anchor| anchor.anchor.point.distance_squared(center) <= radius * radius)
+    }
+
+    // within 'angle' degrees of 'dir' and 'max_distance' of 'origin'
+    pub fn within_cone(&self, origin: Vec3, dir: Vec3, angle: f32, max_distance: f32) -> impl Iterator<Item = &WorldAnchor> {
+        let dir = dir.normalize();
+        let cos = angle.to_radians().cos();
+
+        // the sphere around the cone: centered half way along it, reaching the tip and the rim
+        let center = origin + dir * max_distance * 0.5;
+        let rim = origin + (dir * cos + dir.any_orthogonal_vector() * (1.0 - cos * cos).sqrt()) * max_distance;
+        let radius = (max_distance * 0.5).max(rim.distance(center));
+
+        self.within_radius(center, radius).filter(move |anchor| {
+            let offset = anchor.anchor.point - origin;
+            let distance = offset.length();
+            distance <= max_distance && (distance == 0.0 || offset.dot(dir) >= distance * cos)
+        })
+    }
+}
+
+// finds anchors and keeps them in 'AnchorIndex'. runs in PostUpdate after transforms are propagated,
+// so new colliders are classified with their real orientation
+#[derive(Default)]
+pub struct AnchorPlugin {
+    pub config: AnchorConfig,
+}
+
+impl Plugin for AnchorPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .insert_resource(AnchorIndex::new(self.config.cell_size))
+            .add_systems(PostUpdate, (extract_collider_anchors, update_anchor_index).chain().after(TransformSystem::TransformPropagate))
+            .add_tunable::<AnchorConfig>("anchors");
+
+        if self.config.draw {
+            app.add_systems(Update, draw_anchors);
+        }
+    }
+}
+
+// once per collider, deformable ones are skipped since their triangles don't stay put.
+// the triangles are classified in world space (up has to be up) and the anchors stored in local space
+#[allow(clippy::type_complexity)]
+pub fn extract_collider_anchors(
+    colliders: Query<(Entity, &Triangles, &GlobalTransform, Option<&CollisionLayers>, Option<&TriangleSurfaces>), (Without<Anchors>, Without<DeformableCollider>)>,
+    config: Res<AnchorConfig>,
+    surfaces: Res<SurfaceMaterials>,
+    mut commands: Commands,
+) {
+    for (entity, triangles, transform, layers, triangle_surfaces) in &colliders {
+        let layers = layers.copied().unwrap_or_default().0;
+        if !layers.intersects(Layers::WEB_ATTACHABLE) || layers.intersects(Layers::NO_WEB) {
+            commands.entity(entity).insert(Anchors::default());
+            continue;
+        }
+
+        let matrix = transform.compute_matrix();
+        let world: Vec<Triangle3d> = triangles.0.iter().map(|t| {
+            Triangle3d::new(matrix.transform_point3(t.vertices[0]), matrix.transform_point3(t.vertices[1]), matrix.transform_point3(t.vertices[2]))
+        }).collect();
+        let stickable: Vec<bool> = triangle_surfaces.map(|s| s.0.iter().map(|id| surfaces.get(*id).web_stickable).collect()).unwrap_or_default();
+
+        // normals go back with the transpose, the inverse of the inverse transpose
+        let inverse = matrix.inverse();
+        let normal_matrix = Mat3::from_mat4(matrix).transpose();
+        let anchors = extract_anchors(&world, &stickable, &config).into_iter().map(|anchor| AnchorPoint {
+            point: inverse.transform_point3(anchor.point),
+            normal: (normal_matrix * anchor.normal).normalize_or_zero(),
+            ..anchor
+        }).collect();
+
+        commands.entity(entity).insert(Anchors(anchors));
+    }
+}
+
+// only colliders that moved, or whose anchors changed, are taken out and put back
+#[allow(clippy::type_complexity)]
+pub fn update_anchor_index(
+    colliders: Query<(Entity, &Anchors, &GlobalTransform)>,
+    changed: Query<(Entity, &Anchors, &GlobalTransform), Or<(Changed<GlobalTransform>, Changed<Anchors>)>>,
+    mut removed: RemovedComponents<Anchors>,
+    config: Res<AnchorConfig>,
+    mut index: ResMut<AnchorIndex>,
+) {
+    // every anchor's cell depends on the cell size
+    if config.is_changed() {
+        removed.clear();
+        index.clear(config.cell_size);
+        for (entity, anchors, transform) in &colliders {
+            insert_anchors(&mut index, entity, anchors, transform);
+        }
+        return;
+    }
+
+    for entity in removed.read() {
+        index.remove(entity);
+    }
+
+    for (entity, anchors, transform) in &changed {
+        index.remove(entity);
+        insert_anchors(&mut index, entity, anchors, transform);
+    }
+}
+
+fn insert_anchors(index: &mut AnchorIndex, entity: Entity, anchors: &Anchors, transform: &GlobalTransform) {
+    let matrix = transform.compute_matrix();
+    let normal_matrix = Mat3::from_mat4(matrix).inverse().transpose();
+
+    for anchor in &anchors.0 {
+        index.insert(WorldAnchor {
+            entity,
+            anchor: AnchorPoint {
+                point: matrix.transform_point3(anchor.point),
+                normal: (normal_matrix * anchor.normal).normalize_or_zero(),
+                ..*anchor
+            },
+        });
+    }
+}
+
+const ANCHOR_RADIUS: f32 = 0.1;
+
+// with the collider overlay (F1)
+pub fn draw_anchors(index: Res<AnchorIndex>, debug: Res<ColliderDebug>, mut gizmos: Gizmos) {
+    if !debug.enabled {
+        return;
+    }
+
+    for anchor in index.iter() {
+        let AnchorPoint { point, normal, kind, quality } = anchor.anchor;
+        gizmos.sphere(Isometry3d::from_translation(point), ANCHOR_RADIUS * (0.5 + quality), kind.color());
+        gizmos.line(point, point + normal * 0.5, kind.color());
+    }
+}
+
+#[test]
+fn test_extract_anchors() {
+    // a 10x4x10 box from y = -2 to 2, with its faces split like a glTF export's
+    let triangles: Vec<Triangle3d> = Mesh::from(Cuboid::new(10.0, 4.0, 10.0)).triangles().unwrap().collect();
+    let anchors = extract_anchors(&triangles, &[], &AnchorConfig::default());
+
+    let of_kind = |kind: AnchorKind| anchors.iter().filter(move |a| a.kind == kind);
+    let corners: Vec<_> = of_kind(AnchorKind::Corner).collect();
+    assert_eq!(corners.len(), 4);
+    for corner in corners {
+        assert_eq!(corner.point.abs(), Vec3::new(5.0, 2.0, 5.0));
+        assert!(corner.normal.y > 0.0 && corner.normal.x * corner.point.x > 0.0 && corner.normal.z * corner.point.z > 0.0);
+    }
+
+    assert!(of_kind(AnchorKind::Ledge).count() >= 4 * 4);
+    assert!(of_kind(AnchorKind::Ledge).all(|a| a.point.y == 2.0 && a.normal.y > 0.0));
+    assert!(of_kind(AnchorKind::Overhang).all(|a| a.point.y == -2.0 && a.normal == Vec3::NEG_Y));
+    // the bottom edges and the vertical ones
+    assert!(of_kind(AnchorKind::Edge).any(|a| a.point.y == -2.0));
+    assert!(of_kind(AnchorKind::Edge).any(|a| a.point.y.abs() < 2.0));
+
+    // nothing at all on a box webs don't stick to
+    assert!(extract_anchors(&triangles, &vec![false; triangles.len()], &AnchorConfig::default()).is_empty());
+
+    let mut index = AnchorIndex::new(4.0);
+    for anchor in &anchors {
+        index.insert(WorldAnchor { entity: Entity::PLACEHOLDER, anchor: *anchor });
+    }
+    assert_eq!(index.len(), anchors.len());
+
+    // taking another entity's anchors out leaves these alone
+    let other = Entity::from_raw(1);
+    index.insert(WorldAnchor { entity: other, anchor: anchors[0] });
+    index.remove(other);
+    assert_eq!(index.len(), anchors.len());
+    assert_eq!(index.iter().count(), anchors.len());
+
+    let near = |center: Vec3, radius: f32| anchors.iter().filter(|a| a.point.distance(center) <= radius).count();
+    assert_eq!(index.within_radius(Vec3::new(5.0, 2.0, 5.0), 3.0).count(), near(Vec3::new(5.0, 2.0, 5.0), 3.0));
+    assert_eq!(index.within_radius(Vec3::ZERO, 100.0).count(), anchors.len());
+
+    // looking at one top corner from outside the box
+    let eye = Vec3::new(20.0, 2.0, 20.0);
+    let dir = Vec3::new(5.0, 2.0, 5.0) - eye;
+    let cone: Vec<_> = index.within_cone(eye, dir, 5.0, 50.0).collect();
+    assert!(cone.iter().any(|a| a.anchor.kind == AnchorKind::Corner && a.anchor.point == Vec3::new(5.0, 2.0, 5.0)));
+    assert!(cone.iter().all(|a| (a.anchor.point - eye).angle_between(dir).to_degrees() <= 5.0 + 1e-3));
+    // it doesn't know about line of sight, the far corner is on the same line
+    assert!(cone.iter().any(|a| a.anchor.point == Vec3::new(-5.0, 2.0, -5.0)));
+    assert_eq!(index.within_cone(eye, Vec3::X, 5.0, 50.0).count(), 0);
+}
@@ -0,0 +1,67 @@
+use bevy::app::PluginGroupBuilder;
+use bevy::prelude::*;
+use bevy::render::mesh::skinning::SkinnedMeshInverseBindposes;
+use bevy::time::TimeUpdateStrategy;
+use std::time::Duration;
+
+use crate::aim::{AimConfig, AimPlugin};
+use crate::air::AirPlugin;
+use crate::anchors::{AnchorConfig, AnchorPlugin};
+use crate::camera::CameraPlugin;
+use crate::combat::{CombatConfig, CombatPlugin};
+use crate::console::{ConsoleConfig, ConsolePlugin};
+use crate::enemy::{EnemyConfig, EnemyPlugin};
+use crate::game::{GameConfig, GamePlugin};
+use crate::health::HealthPlugin;
+use crate::input::InputPlugin;
+use crate::mission::{MissionConfig, MissionPlugin};
+use crate::nav::{NavConfig, NavPlugin};
+use crate::physics::{CollisionConfig, CollisionPlugin};
+use crate::player::{PlayerConfig, PlayerPlugin};
+use crate::race::{RaceConfig, RacePlugin};
+use crate::tuning::{TuningConfig, TuningPlugin};
+
+// how far time moves on every 'App::update' of a 'headless_app'
+pub const TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// the simulation on its own, for tests and tools. nothing is spawned, so add a player with 'game::spawn_player' and
+// colliders or a 'CurrentLevel' as needed. every 'App::update' is one 'TICK' long whatever the wall clock says,
+// except the first which only starts the clock.
+// assets come from the usual 'assets' directory, glTF scenes aren't supported since they need the renderer
+pub fn headless_app() -> App {
+    let mut app = simulation_app(MinimalPlugins.build(), GameConfig::headless());
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(TICK));
+
+    app
+}
+
+// the game without a window or renderer, on top of 'base' (some form of 'MinimalPlugins'). shared by 'headless_app'
+// and 'spiderman --headless', which runs it in real time and loads the level
+pub fn simulation_app(base: PluginGroupBuilder, game: GameConfig) -> App {
+    let mut app = App::new();
+    app.add_plugins((base, AssetPlugin::default(), TransformPlugin, HierarchyPlugin, bevy::input::InputPlugin, bevy::state::app::StatesPlugin))
+        .init_asset::<Mesh>()
+        .init_asset::<SkinnedMeshInverseBindposes>()
+        .add_plugins((
+            CollisionPlugin { config: CollisionConfig::headless() },
+            InputPlugin::default(),
+            PlayerPlugin { config: PlayerConfig::headless() },
+            AirPlugin::default(),
+            HealthPlugin::default(),
+            (
+                EnemyPlugin { config: EnemyConfig::headless() },
+                CombatPlugin { config: CombatConfig::headless() },
+                MissionPlugin { config: MissionConfig::headless() },
+                RacePlugin { config: RaceConfig::headless() },
+                NavPlugin { config: NavConfig::headless() },
+            ),
+            CameraPlugin::default(),
+            ConsolePlugin { config: ConsoleConfig::headless() },
+            AimPlugin { config: AimConfig::headless() },
+            AnchorPlugin { config: AnchorConfig::headless() },
+            TuningPlugin { config: TuningConfig::headless() },
+            GamePlugin { config: game },
+        ));
+
+    app
+}
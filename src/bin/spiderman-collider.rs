@@ -0,0 +1,150 @@
+// bakes and inspects collision data for a glTF file without starting the game
+//
+// usage: spiderman-collider <file.gltf> <mesh name>... [--out <assets dir>] [--dry-run]
+//
+// mesh names are the same ones given to 'Collidable', i.e. the glTF mesh name, with '.<primitive index>' appended when a mesh has more than one primitive
+
+use bevy::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Instant;
+
+use spiderman::physics::cache::{self, CollisionTreeCache};
+use spiderman::physics::collision::{build_collision_tree, TRIANGLE_LIMIT};
+use spiderman::physics::stats::TreeStats;
+
+struct Args {
+    file: PathBuf,
+    meshes: Vec<String>,
+    out: PathBuf,
+    dry_run: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut file = None;
+    let mut meshes = Vec::new();
+    let mut out = PathBuf::from("assets");
+    let mut dry_run = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => out = args.next().ok_or("--out needs a directory")?.into(),
+            "--dry-run" => dry_run = true,
+            "--help" | "-h" => return Err(String::new()),
+            _ if file.is_none() => file = Some(PathBuf::from(arg)),
+            _ => meshes.push(arg),
+        }
+    }
+
+    let file = file.ok_or("no glTF file given")?;
+    if meshes.is_empty() {
+        return Err("no mesh names given".to_string());
+    }
+
+    Ok(Args { file, meshes, out, dry_run })
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            if !err.is_empty() {
+                eprintln!("error: {err}");
+            }
+            eprintln!("usage: spiderman-collider <file.gltf> <mesh name>... [--out <assets dir>] [--dry-run]");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let primitives = match load_primitives(&args.file) {
+        Ok(primitives) => primitives,
+        Err(err) => {
+            eprintln!("error: failed to read {}: {err}", args.file.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut failed = false;
+    for name in &args.meshes {
+        let Some((_, triangles)) = primitives.iter().find(|(n, _)| n == name) else {
+            let available: Vec<&str> = primitives.iter().map(|(n, _)| n.as_str()).collect();
+            eprintln!("error: no mesh named '{name}', available: {available:?}");
+            failed = true;
+            continue;
+        };
+
+        let start = Instant::now();
+        let tree = build_collision_tree(triangles, TRIANGLE_LIMIT);
+        let build_time = start.elapsed();
+
+        let hash = cache::mesh_hash(triangles);
+        println!("== {name} ({hash:016x}) ==");
+        println!("build time:        {:.2?}", build_time);
+        print!("{}", TreeStats::new(&tree, triangles.len()));
+
+        if !args.dry_run {
+            let baked = CollisionTreeCache { mesh_hash: hash, tree, triangles: triangles.clone() };
+            match cache::write_cache_in(&baked, &args.out) {
+                Ok(path) => println!("wrote {}", path.display()),
+                Err(err) => {
+                    eprintln!("error: failed to write cache: {err}");
+                    failed = true;
+                }
+            }
+        }
+
+        println!();
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+// reads every triangle list primitive, named the way bevy names the mesh entities it spawns.
+// the triangles come out in the same order as 'Mesh::triangles', so the hashes match the ones computed in game
+fn load_primitives(path: &Path) -> Result<Vec<(String, Vec<Triangle3d>)>, gltf::Error> {
+    let gltf = gltf::Gltf::open(path)?;
+    let buffers = gltf::import_buffers(&gltf.document, path.parent(), gltf.blob.clone())?;
+
+    let mut primitives = Vec::new();
+    for mesh in gltf.document.meshes() {
+        let mesh_name = mesh.name().unwrap_or("Mesh");
+        let count = mesh.primitives().len();
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let Some(positions) = reader.read_positions() else {
+                continue;
+            };
+            let positions: Vec<Vec3> = positions.map(Vec3::from).collect();
+
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+
+            let triangles = indices
+                .chunks_exact(3)
+                .map(|i| Triangle3d::new(positions[i[0] as usize], positions[i[1] as usize], positions[i[2] as usize]))
+                .collect();
+
+            let name = if count > 1 {
+                format!("{}.{}", mesh_name, primitive.index())
+            } else {
+                mesh_name.to_string()
+            };
+
+            primitives.push((name, triangles));
+        }
+    }
+
+    Ok(primitives)
+}
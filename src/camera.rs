@@ -0,0 +1,69 @@
+use bevy::prelude::*;
+
+use crate::input::InputSet;
+use crate::tuning::TuningAppExt;
+
+// the player's view. input changes these, 'sync_camera_transform' puts them on the camera's 'Transform'
+#[derive(Component, Default)]
+pub struct CameraState {
+    pub yaw: f32, // degrees
+    pub pitch: f32, // degrees
+    pub pos: Vec3,
+    pub forward: Vec3,
+    pub right: Vec3,
+}
+
+impl CameraState {
+    // recompute 'forward' and 'right' from yaw and pitch
+    pub fn update_vectors(&mut self) {
+        let forward = Vec3::new(
+            self.yaw.to_radians().cos() * self.pitch.to_radians().cos(),
+            self.pitch.to_radians().sin(),
+            self.yaw.to_radians().sin() * self.pitch.to_radians().cos(),
+        ).normalize();
+
+        self.forward = forward;
+        self.right = forward.cross(Vec3::Y);
+    }
+}
+
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct CameraConfig {
+    pub max_pitch: f32, // degrees either way, just short of 90 so looking straight up doesn't flip the view
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            max_pitch: 89.9,
+        }
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CameraSet;
+
+// keeps every 'CameraState' entity's 'Transform' in line with it. runs in 'CameraSet', after 'InputSet'
+#[derive(Default)]
+pub struct CameraPlugin {
+    pub config: CameraConfig,
+}
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .configure_sets(Update, CameraSet.after(InputSet))
+            .add_systems(Update, sync_camera_transform.in_set(CameraSet))
+            .add_tunable::<CameraConfig>("camera");
+    }
+}
+
+pub fn sync_camera_transform(mut cameras: Query<(&mut CameraState, &mut Transform)>, config: Res<CameraConfig>) {
+    for (mut camera_state, mut transform) in &mut cameras {
+        camera_state.pitch = camera_state.pitch.clamp(-config.max_pitch, config.max_pitch);
+        camera_state.update_vectors();
+
+        transform.translation = camera_state.pos;
+        transform.look_to(camera_state.forward, Vec3::Y);
+    }
+}
@@ -0,0 +1,393 @@
+use bevy::prelude::*;
+
+use crate::aim::AimTarget;
+use crate::camera::CameraState;
+use crate::enemy::{Enemy, EnemyConfig, EnemySet, Webbed};
+use crate::health::{Damage, DamageKind, HealthSet};
+use crate::input::PlayerInput;
+use crate::math;
+use crate::physics::layers::{CollisionLayers, CollisionWorld, Layers, QueryFilter};
+use crate::player::{clear_player_input, PlayerMotion, PlayerSet};
+use crate::tuning::TuningAppExt;
+
+const BOUNCE_OFFSET: f32 = 1e-3;
+
+// web shots and melee. everything's in units and seconds
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct CombatConfig {
+    pub web_speed: f32,
+    pub web_gravity: f32,
+    pub web_radius: f32,
+    pub web_lifetime: f32, // a shot that hasn't hit anything by now is gone
+    pub web_cooldown: f32,
+    pub web_bounce: f32, // fraction of the speed kept bouncing off 'NO_WEB'
+    pub stuck_time: f32, // how long a web stays where it stuck
+    pub melee_reach: f32, // how far in front of the eye a swing's centered
+    pub melee_radius: f32,
+    pub melee_damage: f32, // for the first swing of a combo
+    pub combo_length: u32, // swings, the last is the finisher
+    pub combo_window: f32, // the next swing has to come this soon after the last to carry the combo on
+    pub combo_bonus: f32, // each swing in a combo does this much more damage than the last, as a fraction of 'melee_damage'
+    pub melee_cooldown: f32,
+    pub finisher_cooldown: f32,
+    #[reflect(ignore)]
+    pub render: bool, // give web shots a mesh, needs the renderer
+}
+
+impl Default for CombatConfig {
+    fn default() -> Self {
+        Self {
+            web_speed: 40.0,
+            web_gravity: 10.0,
+            web_radius: 0.1,
+            web_lifetime: 3.0,
+            web_cooldown: 0.25,
+            web_bounce: 0.5,
+            stuck_time: 10.0,
+            melee_reach: 1.0,
+            melee_radius: 0.8,
+            melee_damage: 10.0,
+            combo_length: 3,
+            combo_window: 0.8,
+            combo_bonus: 0.5,
+            melee_cooldown: 0.25,
+            finisher_cooldown: 0.8,
+            render: true,
+        }
+    }
+}
+
+impl CombatConfig {
+    pub fn headless() -> Self {
+        Self {
+            render: false,
+            ..default()
+        }
+    }
+}
+
+#[derive(Resource, Debug)]
+pub struct CombatState {
+    pub web_cooldown: f32, // until the next shot
+    pub combo: u32, // swings in the current combo
+    pub since_swing: f32,
+    pub swing_cooldown: f32, // until the next swing
+}
+
+impl Default for CombatState {
+    fn default() -> Self {
+        Self {
+            web_cooldown: 0.0,
+            combo: 0,
+            since_swing: f32::INFINITY,
+            swing_cooldown: 0.0,
+        }
+    }
+}
+
+impl CombatState {
+    pub fn tick(&mut self, dt: f32) {
+        self.web_cooldown = (self.web_cooldown - dt).max(0.0);
+        self.swing_cooldown = (self.swing_cooldown - dt).max(0.0);
+        self.since_swing += dt;
+    }
+
+    // which swing of the combo this is, 0 for the first. 'None' if it's too soon after the last
+    pub fn swing(&mut self, config: &CombatConfig) -> Option<u32> {
+        if self.swing_cooldown > 0.0 {
+            return None;
+        }
+
+        if self.since_swing > config.combo_window || self.combo >= config.combo_length {
+            self.combo = 0;
+        }
+
+        let step = self.combo;
+        self.combo += 1;
+        self.since_swing = 0.0;
+        self.swing_cooldown = if self.combo >= config.combo_length { config.finisher_cooldown } else { config.melee_cooldown };
+        Some(step)
+    }
+}
+
+pub fn melee_damage(config: &CombatConfig, step: u32) -> f32 {
+    config.melee_damage * (1.0 + step as f32 * config.combo_bonus)
+}
+
+// in flight, see 'fly_webs'
+#[derive(Component, Debug)]
+#[require(Transform)]
+pub struct WebShot {
+    pub velocity: Vec3,
+    pub age: f32,
+    pub bounced_off: Option<Entity>, // left out of the sweep until the shot's clear of it, so it isn't hit again on the way off
+}
+
+// a shot that hit something, following it around until it runs out
+#[derive(Component, Debug)]
+pub struct StuckWeb {
+    pub to: Entity,
+    pub local: Vec3, // where on 'to', in its space
+    pub normal: Vec3, // of the surface, in world space when it stuck
+    pub remaining: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HitKind {
+    Web { stuck: bool }, // doesn't stick to 'NO_WEB'
+    Melee { step: u32 }, // of the combo, 0 for the first
+}
+
+// for effects, audio and damage. 'normal' is the surface's, facing whatever hit it
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Hit {
+    pub entity: Entity,
+    pub kind: HitKind,
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CombatSet;
+
+// F shoots a web at the aim assist's target, or along the camera without one (or 'AimPlugin'), which sticks to the
+// world and webs enemies. Q punches whatever's in front of the
+// player, in combos that hit harder each swing. reading input happens in 'PlayerSet', shots fly in 'CombatSet' after
+// it and before 'EnemySet' so a webbed enemy stops the frame it's hit. needs 'EnemyPlugin' and 'HealthPlugin'
+#[derive(Default)]
+pub struct CombatPlugin {
+    pub config: CombatConfig,
+}
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .init_resource::<CombatState>()
+            .add_event::<Hit>()
+            .configure_sets(Update, CombatSet.after(PlayerSet).before(EnemySet).before(HealthSet))
+            .add_systems(Update, (shoot_webs, melee).chain().before(clear_player_input).in_set(PlayerSet))
+            .add_systems(Update, (fly_webs, follow_stuck_webs).chain().in_set(CombatSet))
+            .add_tunable::<CombatConfig>("combat");
+
+        if self.config.render {
+            app.add_systems(Update, add_web_meshes.after(CombatSet));
+        }
+    }
+}
+
+// the arc the shot drops along is aimed high enough to land on the target
+pub fn shoot_webs(
+    input: Res<PlayerInput>,
+    player: Option<Single<&CameraState, With<PlayerMotion>>>,
+    aim: Option<Res<AimTarget>>,
+    config: Res<CombatConfig>,
+    mut state: ResMut<CombatState>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    state.tick(time.delta_secs());
+
+    let Some(cam) = player.filter(|_| input.shoot && state.web_cooldown == 0.0) else {
+        return;
+    };
+
+    // last frame's target, 'AimSet' runs after the camera's moved. it's what the player saw when they pressed fire
+    let target = aim.and_then(|aim| aim.0).map(|target| target.point);
+    let dir = target.and_then(|target| launch_direction(target - cam.pos, config.web_speed, config.web_gravity)).unwrap_or(cam.forward);
+
+    state.web_cooldown = config.web_cooldown;
+    commands.spawn((
+        WebShot { velocity: dir * config.web_speed, age: 0.0, bounced_off: None },
+        Transform::from_translation(cam.pos),
+    ));
+}
+
+// which way to fire at 'speed' to reach 'offset' away while falling at 'gravity', on the lower of the two arcs.
+// 'None' if it's out of reach
+pub fn launch_direction(offset: Vec3, speed: f32, gravity: f32) -> Option<Vec3> {
+    let across = Vec2::new(offset.x, offset.z);
+    let (distance, height) = (across.length(), offset.y);
+    if gravity <= f32::EPSILON || distance <= f32::EPSILON {
+        return Some(offset.normalize_or_zero()).filter(|dir| *dir != Vec3::ZERO);
+    }
+
+    let v2 = speed * speed;
+    let discriminant = v2 * v2 - gravity * (gravity * distance * distance + 2.0 * height * v2);
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let angle = ((v2 - discriminant.sqrt()) / (gravity * distance)).atan();
+    let across = across / distance;
+    Some(Vec3::new(across.x * angle.cos(), angle.sin(), across.y * angle.cos()))
+}
+
+pub fn melee(
+    input: Res<PlayerInput>,
+    player: Option<Single<&CameraState, With<PlayerMotion>>>,
+    config: Res<CombatConfig>,
+    mut state: ResMut<CombatState>,
+    world: CollisionWorld,
+    mut hits: EventWriter<Hit>,
+    mut damage: EventWriter<Damage>,
+) {
+    let Some(cam) = player.filter(|_| input.melee) else {
+        return;
+    };
+    let Some(step) = state.swing(&config) else {
+        return;
+    };
+
+    let center = cam.pos + cam.forward * config.melee_reach;
+    let targets = world.overlap_sphere(center, config.melee_radius, &QueryFilter::melee());
+
+    // a miss drops the combo
+    if targets.is_empty() {
+        state.combo = 0;
+    }
+
+    for target in targets {
+        hits.send(Hit { entity: target.entity, kind: HitKind::Melee { step }, point: target.point, normal: target.normal });
+        damage.send(Damage { entity: target.entity, amount: melee_damage(&config, step), kind: DamageKind::Melee });
+    }
+}
+
+// a sphere swept along each shot's move this frame, so fast shots can't pass through anything thin
+#[allow(clippy::too_many_arguments)]
+pub fn fly_webs(
+    mut shots: Query<(Entity, &mut WebShot, &mut Transform)>,
+    layers: Query<&CollisionLayers>,
+    transforms: Query<&GlobalTransform>,
+    enemies: Query<(), With<Enemy>>,
+    world: CollisionWorld,
+    config: Res<CombatConfig>,
+    enemy_config: Res<EnemyConfig>,
+    time: Res<Time>,
+    mut hits: EventWriter<Hit>,
+    mut commands: Commands,
+) {
+    let dt = time.delta_secs();
+
+    for (entity, mut shot, mut transform) in &mut shots {
+        shot.age += dt;
+        if shot.age > config.web_lifetime {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        shot.velocity.y -= config.web_gravity * dt;
+        let step = shot.velocity * dt;
+        let ray = math::Ray3d::new(transform.translation, step);
+
+        let mut filter = QueryFilter::web_shot();
+        if let Some(bounced_off) = shot.bounced_off {
+            let only = |entity| entity == bounced_off;
+            if world.overlap_sphere(transform.translation, config.web_radius + BOUNCE_OFFSET * 2.0, &QueryFilter::default().with_predicate(&only)).is_empty() {
+                shot.bounced_off = None;
+            } else {
+                filter = filter.exclude_entity(bounced_off);
+            }
+        }
+
+        let Some(hit) = world.cast_sphere(ray, config.web_radius, &filter).filter(|hit| hit.distance <= 1.0) else {
+            transform.translation += step;
+            continue;
+        };
+
+        let no_web = layers.get(hit.entity).copied().unwrap_or_default().0.intersects(Layers::NO_WEB);
+        hits.send(Hit { entity: hit.entity, kind: HitKind::Web { stuck: !no_web }, point: hit.point, normal: hit.normal });
+
+        if no_web {
+            // off the surface, so the next cast doesn't start touching it
+            transform.translation = ray.at(hit.distance) + hit.normal * BOUNCE_OFFSET;
+            shot.velocity = shot.velocity.reflect(hit.normal) * config.web_bounce;
+            shot.bounced_off = Some(hit.entity);
+            continue;
+        }
+
+        let local = transforms.get(hit.entity).map_or(hit.point, |to| to.affine().inverse().transform_point3(hit.point));
+        transform.translation = hit.point;
+        commands.entity(entity).remove::<WebShot>().insert(StuckWeb { to: hit.entity, local, normal: hit.normal, remaining: config.stuck_time });
+
+        if enemies.contains(hit.entity) {
+            commands.entity(hit.entity).insert(Webbed { remaining: enemy_config.web_time });
+        }
+    }
+}
+
+pub fn follow_stuck_webs(
+    mut webs: Query<(Entity, &mut StuckWeb, &mut Transform)>,
+    transforms: Query<&GlobalTransform>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for (entity, mut web, mut transform) in &mut webs {
+        web.remaining -= time.delta_secs();
+
+        // gone with whatever it was stuck to
+        let Some(to) = transforms.get(web.to).ok().filter(|_| web.remaining > 0.0) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        transform.translation = to.transform_point(web.local);
+    }
+}
+
+pub fn add_web_meshes(
+    shots: Query<Entity, Added<WebShot>>,
+    config: Res<CombatConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut commands: Commands,
+) {
+    for entity in &shots {
+        commands.entity(entity).insert((
+            Mesh3d(meshes.add(Sphere::new(config.web_radius))),
+            MeshMaterial3d(materials.add(StandardMaterial::from_color(Color::WHITE))),
+        ));
+    }
+}
+
+#[test]
+fn test_melee_combo() {
+    let config = CombatConfig::default();
+    let mut state = CombatState::default();
+
+    // each swing as soon as it can, the combo goes round once the finisher's done
+    let mut steps = Vec::new();
+    for _ in 0..200 {
+        if let Some(step) = state.swing(&config) {
+            steps.push(step);
+        }
+        state.tick(0.01);
+    }
+    assert_eq!(&steps[..5], &[0, 1, 2, 0, 1]);
+    assert!(melee_damage(&config, 2) > melee_damage(&config, 0));
+
+    // waiting too long starts again
+    let mut state = CombatState::default();
+    assert_eq!(state.swing(&config), Some(0));
+    assert_eq!(state.swing(&config), None);
+    state.tick(config.combo_window + 0.1);
+    assert_eq!(state.swing(&config), Some(0));
+}
+
+#[test]
+fn test_launch_direction() {
+    // following the arc in small steps passes through the target
+    for offset in [Vec3::new(20.0, 3.0, 5.0), Vec3::new(-10.0, -4.0, 0.0), Vec3::new(0.0, 1.0, 30.0)] {
+        let dir = launch_direction(offset, 40.0, 10.0).unwrap();
+        let (mut pos, mut velocity) = (Vec3::ZERO, dir * 40.0);
+        let mut closest = f32::MAX;
+        for _ in 0..2000 {
+            velocity.y -= 10.0 * 0.001;
+            pos += velocity * 0.001;
+            closest = closest.min(pos.distance(offset));
+        }
+        assert!(closest < 0.05, "{offset} {closest}");
+    }
+
+    assert_eq!(launch_direction(Vec3::X * 5.0, 40.0, 0.0), Some(Vec3::X));
+    assert_eq!(launch_direction(Vec3::X * 1000.0, 40.0, 10.0), None);
+}
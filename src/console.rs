@@ -0,0 +1,411 @@
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::input::InputSet;
+use crate::tuning::{set_field, tunable_field, tunable_field_mut, Tunables};
+
+// runs a command with whatever followed its name, returning a line for the console or an error
+pub type CommandFn = Arc<dyn Fn(&mut World, &[&str]) -> Result<String, String> + Send + Sync>;
+
+pub struct ConsoleCommand {
+    pub usage: String, // arguments, shown by 'help'
+    pub help: String,
+    run: CommandFn,
+}
+
+// every command the plugins registered. commands may be several words, e.g. 'level load',
+// the longest registered name at the start of a line wins
+#[derive(Resource, Default)]
+pub struct ConsoleCommands {
+    commands: BTreeMap<String, ConsoleCommand>,
+}
+
+impl ConsoleCommands {
+    pub fn add(&mut self, name: &str, usage: &str, help: &str, run: impl Fn(&mut World, &[&str]) -> Result<String, String> + Send + Sync + 'static) {
+        self.commands.insert(name.to_string(), ConsoleCommand {
+            usage: usage.to_string(),
+            help: help.to_string(),
+            run: Arc::new(run),
+        });
+    }
+
+    // the command a line starts with and the words after it
+    pub fn find<'a>(&self, line: &'a str) -> Option<(&str, &ConsoleCommand, Vec<&'a str>)> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        (1..=words.len()).rev().find_map(|n| {
+            let (name, command) = self.commands.get_key_value(&words[..n].join(" "))?;
+            Some((name.as_str(), command, words[n..].to_vec()))
+        })
+    }
+
+    // everything that can be typed, as whole lines without arguments. 'set' and 'get' take any tunable
+    fn candidates(&self, tunables: &Tunables) -> Vec<String> {
+        let mut candidates: Vec<String> = self.commands.keys().cloned().collect();
+        for path in tunables.paths() {
+            candidates.push(format!("set {path}"));
+            candidates.push(format!("get {path}"));
+        }
+        candidates
+    }
+
+    // extends 'input' as far as every candidate starting with it agrees, with a space after a unique match.
+    // returns the candidates too so they can be listed
+    pub fn complete(&self, tunables: &Tunables, input: &str) -> (String, Vec<String>) {
+        let matches: Vec<String> = self.candidates(tunables).into_iter().filter(|candidate| candidate.starts_with(input)).collect();
+
+        let completed = match matches.as_slice() {
+            [] => input.to_string(),
+            [only] => format!("{only} "),
+            [first, rest @ ..] => {
+                let common = rest.iter().fold(first.len(), |len, other| {
+                    first.bytes().zip(other.bytes()).take(len).take_while(|(a, b)| a == b).count()
+                });
+                first[..common].to_string()
+            }
+        };
+
+        (completed, matches)
+    }
+}
+
+// how plugins add to the console, e.g. 'app.add_console_command("noclip", "", "toggle collision", noclip)'
+pub trait ConsoleAppExt {
+    fn add_console_command(
+        &mut self,
+        name: &str,
+        usage: &str,
+        help: &str,
+        run: impl Fn(&mut World, &[&str]) -> Result<String, String> + Send + Sync + 'static,
+    ) -> &mut Self;
+}
+
+impl ConsoleAppExt for App {
+    fn add_console_command(
+        &mut self,
+        name: &str,
+        usage: &str,
+        help: &str,
+        run: impl Fn(&mut World, &[&str]) -> Result<String, String> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.init_resource::<ConsoleCommands>();
+        self.world_mut().resource_mut::<ConsoleCommands>().add(name, usage, help, run);
+        self
+    }
+}
+
+// parses the arguments of a command, naming the one that didn't parse
+pub fn parse_arg<T: std::str::FromStr>(args: &[&str], i: usize, name: &str) -> Result<T, String> {
+    let arg = args.get(i).ok_or(format!("missing {name}"))?;
+    arg.parse().map_err(|_| format!("invalid {name} '{arg}'"))
+}
+
+// runs one line as if it was typed into the console
+pub fn run_console_command(world: &mut World, line: &str) -> Result<String, String> {
+    world.init_resource::<ConsoleCommands>();
+    // cloned out so commands can look at the registry too
+    let (run, args) = {
+        let (_, command, args) = world.resource::<ConsoleCommands>().find(line).ok_or_else(|| {
+            format!("unknown command '{}', try 'help'", line.split_whitespace().next().unwrap_or_default())
+        })?;
+        (command.run.clone(), args)
+    };
+    run(world, &args)
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct ConsoleConfig {
+    pub ui: bool, // draw it, needs the renderer
+    pub max_lines: usize, // output kept around
+    pub max_history: usize,
+}
+
+impl Default for ConsoleConfig {
+    fn default() -> Self {
+        Self {
+            ui: true,
+            max_lines: 200,
+            max_history: 100,
+        }
+    }
+}
+
+impl ConsoleConfig {
+    pub fn headless() -> Self {
+        Self {
+            ui: false,
+            ..default()
+        }
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct ConsoleState {
+    pub open: bool,
+    pub input: String,
+    pub output: Vec<String>,
+    pub history: Vec<String>, // oldest first
+    history_cursor: Option<usize>, // where up and down have got to in 'history'
+    pending: Vec<String>, // entered lines, run by 'run_pending_commands'
+}
+
+impl ConsoleState {
+    pub fn submit(&mut self, line: impl Into<String>) {
+        self.pending.push(line.into());
+    }
+
+    fn print(&mut self, line: impl Into<String>, max_lines: usize) {
+        self.output.push(line.into());
+        let excess = self.output.len().saturating_sub(max_lines);
+        self.output.drain(..excess);
+    }
+}
+
+pub fn console_closed(console: Option<Res<ConsoleState>>) -> bool {
+    !console.is_some_and(|console| console.open)
+}
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConsoleSet;
+
+#[derive(Component)]
+pub struct ConsoleText;
+
+// ` opens and closes it. gameplay input ('InputSet') is ignored while it's open, so the console runs after it
+// to see the key that closes it last
+#[derive(Default)]
+pub struct ConsolePlugin {
+    pub config: ConsoleConfig,
+}
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .init_resource::<ConsoleState>()
+            .init_resource::<ConsoleCommands>()
+            .init_resource::<Tunables>()
+            .configure_sets(Update, (InputSet.run_if(console_closed), ConsoleSet.after(InputSet)))
+            .add_systems(Update, (console_input, run_pending_commands).chain().in_set(ConsoleSet))
+            .add_console_command("help", "[command]", "list the commands, or show how to use one", help)
+            .add_console_command("clear", "", "clear the console", |world, _| {
+                world.resource_mut::<ConsoleState>().output.clear();
+                Ok(String::new())
+            })
+            .add_console_command("set", "<var> <value>", "change a setting, e.g. 'set player.fly_speed 10'", set_var)
+            .add_console_command("get", "<var>", "show a setting", get_var);
+
+        if self.config.ui {
+            app.add_systems(Startup, setup_console)
+                .add_systems(Update, update_console_text.after(run_pending_commands).in_set(ConsoleSet));
+        }
+    }
+}
+
+fn help(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let commands = world.resource::<ConsoleCommands>();
+
+    if args.is_empty() {
+        return Ok(commands.commands.keys().cloned().collect::<Vec<_>>().join(", "));
+    }
+
+    let (name, command, _) = commands.find(&args.join(" ")).ok_or(format!("unknown command '{}'", args.join(" ")))?;
+    Ok(format!("{name} {}: {}", command.usage, command.help))
+}
+
+fn get_var(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let path = args.first().ok_or("missing var")?;
+    Ok(format!("{path} = {:?}", tunable_field(world, path)?))
+}
+
+fn set_var(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let path = args.first().ok_or("missing var")?;
+    let value = args[1..].join(" ");
+    if value.is_empty() {
+        return Err("missing value".to_string());
+    }
+
+    let field = tunable_field_mut(world, path)?;
+    set_field(field, &value)?;
+    Ok(format!("{path} = {:?}", field))
+}
+
+pub fn setup_console(mut commands: Commands) {
+    commands.spawn((
+        Text::default(),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(0.0),
+            left: Val::Px(0.0),
+            width: Val::Percent(100.0),
+            padding: UiRect::all(Val::Px(5.0)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+        Visibility::Hidden,
+        ConsoleText,
+    ));
+}
+
+// typing, enter, tab completion and up and down through the history
+pub fn console_input(
+    mut keys: EventReader<KeyboardInput>,
+    mut console: ResMut<ConsoleState>,
+    commands: Res<ConsoleCommands>,
+    tunables: Res<Tunables>,
+    config: Res<ConsoleConfig>,
+) {
+    for key in keys.read() {
+        if key.state != ButtonState::Pressed {
+            continue;
+        }
+
+        if key.key_code == KeyCode::Backquote {
+            console.open = !console.open;
+            continue;
+        }
+
+        if !console.open {
+            continue;
+        }
+
+        match &key.logical_key {
+            Key::Escape => console.open = false,
+            Key::Enter => {
+                let line = std::mem::take(&mut console.input);
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                console.print(format!("> {line}"), config.max_lines);
+                if console.history.last() != Some(&line) {
+                    console.history.push(line.clone());
+                }
+                let excess = console.history.len().saturating_sub(config.max_history);
+                console.history.drain(..excess);
+                console.history_cursor = None;
+                console.submit(line);
+            }
+            Key::Backspace => {
+                console.input.pop();
+            }
+            Key::Tab => {
+                let (completed, matches) = commands.complete(&tunables, &console.input);
+                if matches.len() > 1 && completed == console.input {
+                    console.print(matches.join("  "), config.max_lines);
+                }
+                console.input = completed;
+            }
+            Key::ArrowUp => {
+                let cursor = match console.history_cursor {
+                    Some(cursor) => cursor.saturating_sub(1),
+                    None => console.history.len().saturating_sub(1),
+                };
+                if let Some(line) = console.history.get(cursor).cloned() {
+                    console.input = line;
+                    console.history_cursor = Some(cursor);
+                }
+            }
+            Key::ArrowDown => {
+                if let Some(cursor) = console.history_cursor {
+                    let cursor = cursor + 1;
+                    console.input = console.history.get(cursor).cloned().unwrap_or_default();
+                    console.history_cursor = (cursor < console.history.len()).then_some(cursor);
+                }
+            }
+            Key::Space => console.input.push(' '),
+            Key::Character(text) => console.input.extend(text.chars().filter(|c| !c.is_control())),
+            _ => {}
+        }
+    }
+}
+
+// commands get the whole world, so they run here rather than in 'console_input'
+pub fn run_pending_commands(world: &mut World) {
+    let pending = std::mem::take(&mut world.resource_mut::<ConsoleState>().pending);
+    let max_lines = world.resource::<ConsoleConfig>().max_lines;
+
+    for line in pending {
+        let result = run_console_command(world, &line);
+        let mut console = world.resource_mut::<ConsoleState>();
+        match result {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => {
+                info!("{output}");
+                console.print(output, max_lines);
+            }
+            Err(err) => {
+                warn!("{line}: {err}");
+                console.print(format!("error: {err}"), max_lines);
+            }
+        }
+    }
+}
+
+const VISIBLE_LINES: usize = 12;
+
+pub fn update_console_text(console: Res<ConsoleState>, mut text: Single<(&mut Text, &mut Visibility), With<ConsoleText>>) {
+    if !console.is_changed() {
+        return;
+    }
+
+    let (text, visibility) = &mut *text;
+    **visibility = if console.open { Visibility::Visible } else { Visibility::Hidden };
+
+    let start = console.output.len().saturating_sub(VISIBLE_LINES);
+    let mut lines = console.output[start..].to_vec();
+    lines.push(format!("> {}_", console.input));
+    text.0 = lines.join("\n");
+}
+
+#[test]
+fn test_console_commands() {
+    #[derive(Resource, Reflect, Default)]
+    struct Tuning {
+        speed: f32,
+        name: String,
+        enabled: bool,
+    }
+
+    let mut world = World::new();
+    world.init_resource::<Tuning>();
+
+    let mut commands = ConsoleCommands::default();
+    commands.add("level load", "<name>", "", |_, args| Ok(format!("loading {}", args.join(" "))));
+    commands.add("level", "", "", |_, _| Ok("level".to_string()));
+    commands.add("noclip", "", "", |_, _| Ok(String::new()));
+    commands.add("set", "<var> <value>", "", set_var);
+    commands.add("get", "<var>", "", get_var);
+    world.insert_resource(commands);
+    let mut tunables = Tunables::default();
+    tunables.add::<Tuning>("tuning");
+    world.insert_resource(tunables);
+
+    // the longest name wins
+    assert_eq!(run_console_command(&mut world, "level load island1"), Ok("loading island1".to_string()));
+    assert_eq!(run_console_command(&mut world, "level"), Ok("level".to_string()));
+    assert!(run_console_command(&mut world, "teleport 1 2 3").is_err());
+
+    assert_eq!(run_console_command(&mut world, "set tuning.speed 2.5"), Ok("tuning.speed = 2.5".to_string()));
+    run_console_command(&mut world, "set tuning.name fast one").unwrap();
+    run_console_command(&mut world, "set tuning.enabled true").unwrap();
+    assert!(run_console_command(&mut world, "set tuning.speed fast").is_err());
+    assert!(run_console_command(&mut world, "set tuning.missing 1").is_err());
+    assert!(run_console_command(&mut world, "get other.speed").is_err());
+
+    let tuning = world.resource::<Tuning>();
+    assert_eq!((tuning.speed, tuning.name.as_str(), tuning.enabled), (2.5, "fast one", true));
+
+    let (commands, tunables) = (world.resource::<ConsoleCommands>(), world.resource::<Tunables>());
+    assert_eq!(commands.complete(tunables, "no").0, "noclip ");
+    assert_eq!(commands.complete(tunables, "lev").0, "level");
+    assert_eq!(commands.complete(tunables, "set tuning.e").0, "set tuning.enabled ");
+    assert_eq!(commands.complete(tunables, "set tuning.").1.len(), 3);
+    assert_eq!(commands.complete(tunables, "fly").1.len(), 0);
+}
@@ -0,0 +1,225 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+// utility AI: every 'Behaviour' of an enemy's type is scored from its 'Senses' each frame and the best one's action is
+// what it does. types live in 'assets/enemies.ron' so new ones don't need code
+
+// what an enemy knows this frame
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Senses {
+    pub sees_player: bool,
+    pub since_seen: Option<f32>, // seconds since the player was last seen, 'None' if never
+    pub player_distance: f32, // to where the player was last seen, infinite if never
+    pub health: f32, // fraction of max
+    pub attack_ready: bool,
+}
+
+impl Default for Senses {
+    fn default() -> Self {
+        Self {
+            sees_player: false,
+            since_seen: None,
+            player_distance: f32::INFINITY,
+            health: 1.0,
+            attack_ready: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Hash)]
+pub enum Action {
+    #[default]
+    Idle,
+    Patrol, // round its waypoints
+    Chase, // straight at the player
+    Search, // to where the player was last seen
+    Attack,
+    Flee, // away from the player
+}
+
+// each scores 0 to 1, and a behaviour's score is its weight times all of them
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub enum Consideration {
+    SeesPlayer,
+    LostPlayer, // knew where they were but can't see them now
+    RemembersPlayer(f32), // seen within this many seconds, fading out over them
+    PlayerCloserThan(f32),
+    PlayerFurtherThan(f32),
+    HealthBelow(f32), // fraction of max
+    AttackReady,
+}
+
+impl Consideration {
+    pub fn score(&self, senses: &Senses) -> f32 {
+        let yes = |b: bool| if b { 1.0 } else { 0.0 };
+
+        match *self {
+            Consideration::SeesPlayer => yes(senses.sees_player),
+            Consideration::LostPlayer => yes(!senses.sees_player && senses.since_seen.is_some()),
+            Consideration::RemembersPlayer(memory) => senses.since_seen.map_or(0.0, |since| (1.0 - since / memory.max(f32::EPSILON)).max(0.0)),
+            Consideration::PlayerCloserThan(distance) => yes(senses.player_distance < distance),
+            Consideration::PlayerFurtherThan(distance) => yes(senses.player_distance > distance),
+            Consideration::HealthBelow(fraction) => yes(senses.health < fraction),
+            Consideration::AttackReady => yes(senses.attack_ready),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Behaviour {
+    pub action: Action,
+    #[serde(default = "one")]
+    pub weight: f32,
+    #[serde(default)]
+    pub considerations: Vec<Consideration>,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+impl Behaviour {
+    pub fn new(action: Action, weight: f32, considerations: Vec<Consideration>) -> Self {
+        Self { action, weight, considerations }
+    }
+
+    pub fn score(&self, senses: &Senses) -> f32 {
+        self.considerations.iter().fold(self.weight, |score, consideration| score * consideration.score(senses))
+    }
+}
+
+// the best scoring action, earlier behaviours win ties. 'Idle' if nothing scores above 0
+pub fn choose_action(behaviours: &[Behaviour], senses: &Senses) -> Action {
+    let mut best = (Action::Idle, 0.0);
+    for behaviour in behaviours {
+        let score = behaviour.score(senses);
+        if score > best.1 {
+            best = (behaviour.action, score);
+        }
+    }
+
+    best.0
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct EnemyType {
+    pub health: f32,
+    pub walk_speed: f32, // units per second, patrolling and searching
+    pub run_speed: f32, // chasing and fleeing
+    pub size: Vec3, // of its collider, standing on its origin
+    pub eye_height: f32,
+    pub view_distance: f32,
+    pub view_angle: f32, // degrees either side of where it's facing
+    pub attack_range: f32,
+    pub attack_damage: f32,
+    pub attack_cooldown: f32, // seconds
+    pub behaviours: Vec<Behaviour>,
+}
+
+impl Default for EnemyType {
+    fn default() -> Self {
+        use Consideration::*;
+
+        Self {
+            health: 50.0,
+            walk_speed: 2.0,
+            run_speed: 4.5,
+            size: Vec3::new(0.8, 1.8, 0.8),
+            eye_height: 1.6,
+            view_distance: 25.0,
+            view_angle: 60.0,
+            attack_range: 2.0,
+            attack_damage: 10.0,
+            attack_cooldown: 1.0,
+            behaviours: vec![
+                Behaviour::new(Action::Patrol, 0.1, vec![]),
+                Behaviour::new(Action::Chase, 0.6, vec![SeesPlayer]),
+                Behaviour::new(Action::Search, 0.5, vec![LostPlayer, RemembersPlayer(5.0)]),
+                Behaviour::new(Action::Attack, 0.9, vec![SeesPlayer, PlayerCloserThan(2.0), AttackReady]),
+                Behaviour::new(Action::Flee, 0.8, vec![SeesPlayer, HealthBelow(0.25)]),
+            ],
+        }
+    }
+}
+
+// every enemy type by name. the resource is what enemies use, it's copied from the asset loaded from 'ENEMIES_FILE'
+// whenever that's (re)loaded. until then, or without the file, there's only the built in thug
+#[derive(Asset, Resource, TypePath, Clone, Debug, Deserialize, PartialEq)]
+pub struct EnemyTypes(pub BTreeMap<String, EnemyType>);
+
+pub const ENEMIES_FILE: &str = "enemies.ron";
+
+impl Default for EnemyTypes {
+    fn default() -> Self {
+        Self(BTreeMap::from([(String::from("thug"), EnemyType::default())]))
+    }
+}
+
+impl EnemyTypes {
+    pub fn get(&self, kind: &str) -> Option<&EnemyType> {
+        self.0.get(kind)
+    }
+}
+
+#[derive(Debug)]
+pub enum EnemyTypesError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for EnemyTypesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnemyTypesError::Io(err) => write!(f, "io error: {err}"),
+            EnemyTypesError::Ron(err) => write!(f, "invalid enemies file: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for EnemyTypesError {}
+
+// picked by asset type, 'ENEMIES_FILE' is plain ".ron"
+#[derive(Default)]
+pub struct EnemyTypesLoader;
+
+impl AssetLoader for EnemyTypesLoader {
+    type Asset = EnemyTypes;
+    type Settings = ();
+    type Error = EnemyTypesError;
+
+    async fn load(&self, reader: &mut dyn Reader, _settings: &(), _load_context: &mut LoadContext<'_>) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await.map_err(EnemyTypesError::Io)?;
+        ron::de::from_bytes(&bytes).map(EnemyTypes).map_err(EnemyTypesError::Ron)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["enemies.ron"]
+    }
+}
+
+#[test]
+fn test_choose_action() {
+    let thug = EnemyType::default();
+    let choose = |senses: Senses| choose_action(&thug.behaviours, &senses);
+
+    assert_eq!(choose(Senses::default()), Action::Patrol);
+    assert_eq!(choose(Senses { sees_player: true, since_seen: Some(0.0), player_distance: 10.0, ..default() }), Action::Chase);
+    assert_eq!(choose(Senses { sees_player: true, since_seen: Some(0.0), player_distance: 1.0, ..default() }), Action::Attack);
+    assert_eq!(choose(Senses { sees_player: true, since_seen: Some(0.0), player_distance: 1.0, attack_ready: false, ..default() }), Action::Chase);
+    assert_eq!(choose(Senses { sees_player: true, since_seen: Some(0.0), player_distance: 10.0, health: 0.1, ..default() }), Action::Flee);
+
+    // searching fades out until patrolling wins again
+    let lost = |since: f32| Senses { since_seen: Some(since), player_distance: 10.0, ..default() };
+    assert_eq!(choose(lost(1.0)), Action::Search);
+    assert_eq!(choose(lost(4.9)), Action::Patrol);
+
+    let types: BTreeMap<String, EnemyType> = ron::de::from_str(include_str!("../../assets/enemies.ron")).unwrap();
+    assert_eq!(types["thug"], thug);
+    assert!(types.len() > 1);
+}
@@ -0,0 +1,444 @@
+use bevy::asset::AssetLoadFailedEvent;
+use bevy::color::palettes::css::{ORANGE, RED, WHITE, YELLOW};
+use bevy::prelude::*;
+
+pub mod behaviour;
+
+use behaviour::{choose_action, Action, EnemyTypes, EnemyTypesLoader, Senses, ENEMIES_FILE};
+use crate::aim::AimTarget;
+use crate::anchors::Anchors;
+use crate::camera::CameraState;
+use crate::console::ConsoleAppExt;
+use crate::health::{Damage, DamageKind, Died, Health, HealthSet};
+use crate::math;
+use crate::nav::{NavAgent, NavSet};
+use crate::physics::cache::default_assets_dir;
+use crate::physics::collision::{build_collision_tree, Triangles, TRIANGLE_LIMIT};
+use crate::physics::debug::ColliderDebug;
+use crate::physics::layers::{CollisionLayers, CollisionWorld, Layers, QueryFilter};
+use crate::player::{PlayerMotion, PlayerSet};
+use crate::tuning::TuningAppExt;
+
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct EnemyConfig {
+    pub web_time: f32, // seconds a webbed enemy can't move
+    pub waypoint_radius: f32, // close enough to a waypoint to go on to the next
+    pub step_height: f32, // spawns snap down to ground this far above them
+    pub spawn_drop: f32, // spawns snap down to the ground up to this far below
+    pub flee_distance: f32, // how far ahead a fleeing enemy aims
+    #[reflect(ignore)]
+    pub render: bool, // give enemies a mesh and draw their view cones with the collider overlay, needs the renderer
+}
+
+impl Default for EnemyConfig {
+    fn default() -> Self {
+        Self {
+            web_time: 5.0,
+            waypoint_radius: 0.5,
+            step_height: 0.5,
+            spawn_drop: 50.0,
+            flee_distance: 5.0,
+            render: true,
+        }
+    }
+}
+
+impl EnemyConfig {
+    pub fn headless() -> Self {
+        Self {
+            render: false,
+            ..default()
+        }
+    }
+}
+
+// where an enemy should be. turned into the enemy itself by 'spawn_enemies', so levels and the console don't need
+// to know what one is made of
+#[derive(Component, Clone, Debug)]
+#[require(Transform)]
+pub struct EnemySpawn {
+    pub kind: String, // in 'EnemyTypes'
+    pub waypoints: Vec<Vec3>,
+}
+
+#[derive(Component, Debug)]
+pub struct Enemy {
+    pub kind: String,
+    pub action: Action,
+    pub facing: Vec3, // flat
+    pub waypoints: Vec<Vec3>,
+    pub next_waypoint: usize,
+    pub last_seen: Option<(Vec3, f32)>, // where the player was and when, in seconds since startup
+    pub cooldown: f32, // until it can attack again
+}
+
+// stuck in a web, it can't move or attack until this runs out
+#[derive(Component, Debug)]
+pub struct Webbed {
+    pub remaining: f32, // seconds
+}
+
+// for animation and audio
+#[derive(Event, Clone, Copy, Debug)]
+pub struct EnemyActionChanged {
+    pub entity: Entity,
+    pub from: Action,
+    pub to: Action,
+}
+
+// where 'EnemyTypes' comes from. 'None' without an 'assets/enemies.ron'
+#[derive(Resource, Default)]
+pub struct EnemyTypesFile {
+    pub handle: Option<Handle<EnemyTypes>>,
+    pub loading: bool, // spawns wait until it's done, so a level's enemies aren't thrown away as an unknown type
+}
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EnemySet;
+
+// enemies that patrol, see the player and go after them, see 'behaviour' for how they decide what to do.
+// runs in 'EnemySet', after 'PlayerSet' so they react to where the player is now, before 'NavSet' which moves them and
+// before 'HealthSet' so attacks land the same frame. enemy types are loaded from 'assets/enemies.ron' with the asset
+// server, and picked up again when it changes if that's watching for changes. needs 'NavPlugin'
+#[derive(Default)]
+pub struct EnemyPlugin {
+    pub config: EnemyConfig,
+}
+
+impl Plugin for EnemyPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .init_resource::<EnemyTypes>()
+            .init_resource::<EnemyTypesFile>()
+            .init_asset::<EnemyTypes>()
+            .init_asset_loader::<EnemyTypesLoader>()
+            .add_event::<EnemyActionChanged>()
+            .configure_sets(Update, EnemySet.after(PlayerSet).before(NavSet).before(HealthSet))
+            .add_systems(Startup, load_enemy_types)
+            .add_systems(Update, (update_enemy_types, spawn_enemies, perceive, think, act, untangle).chain().in_set(EnemySet))
+            .add_systems(Update, remove_dead_enemies.after(HealthSet))
+            .add_console_command("spawn enemy", "<kind>", "an enemy in front of the player", spawn_enemy_command)
+            .add_console_command("web", "", "web the enemy being aimed at", web_command)
+            .add_tunable::<EnemyConfig>("enemy");
+
+        if self.config.render {
+            app.add_systems(Update, (
+                add_enemy_meshes.after(EnemySet),
+                draw_enemies.after(EnemySet).run_if(|debug: Res<ColliderDebug>| debug.enabled),
+            ));
+        }
+    }
+}
+
+// the highest ground within 'reach' below 'from', not counting 'entity'
+fn ground_below(world: &CollisionWorld, entity: Entity, from: Vec3, reach: f32) -> Option<Vec3> {
+    let filter = QueryFilter::character().exclude_entity(entity);
+    let hit = world.cast_ray_closest(math::Ray3d::new(from, Vec3::NEG_Y), &filter)?;
+    (hit.distance <= reach).then_some(hit.point)
+}
+
+// an upright box standing on the origin
+fn enemy_collider(size: Vec3) -> Vec<Triangle3d> {
+    let offset = Vec3::Y * size.y * 0.5;
+    Mesh::from(Cuboid::from_size(size)).triangles().unwrap().map(|t| {
+        Triangle3d::new(t.vertices[0] + offset, t.vertices[1] + offset, t.vertices[2] + offset)
+    }).collect()
+}
+
+// checked up front so a missing file doesn't show up as an asset server error
+pub fn load_enemy_types(server: Res<AssetServer>, mut file: ResMut<EnemyTypesFile>) {
+    if default_assets_dir().join(ENEMIES_FILE).is_file() {
+        *file = EnemyTypesFile { handle: Some(server.load(ENEMIES_FILE)), loading: true };
+    }
+}
+
+pub fn update_enemy_types(
+    mut events: EventReader<AssetEvent<EnemyTypes>>,
+    mut failed: EventReader<AssetLoadFailedEvent<EnemyTypes>>,
+    assets: Res<Assets<EnemyTypes>>,
+    mut file: ResMut<EnemyTypesFile>,
+    mut types: ResMut<EnemyTypes>,
+) {
+    let Some(handle) = file.handle.clone() else {
+        return;
+    };
+
+    for event in events.read() {
+        if event.is_loaded_with_dependencies(&handle) || event.is_modified(&handle) {
+            if let Some(loaded) = assets.get(&handle) {
+                info!("Loaded {} enemy types", loaded.0.len());
+                *types = loaded.clone();
+                file.loading = false;
+            }
+        }
+    }
+
+    for event in failed.read().filter(|event| event.id == handle.id()) {
+        warn!("Failed to load {}, using the built in enemies: {}", event.path, event.error);
+        file.loading = false;
+    }
+}
+
+pub fn spawn_enemies(
+    spawns: Query<(Entity, &EnemySpawn, &Transform), Without<Enemy>>,
+    types: Res<EnemyTypes>,
+    file: Res<EnemyTypesFile>,
+    config: Res<EnemyConfig>,
+    world: CollisionWorld,
+    mut commands: Commands,
+) {
+    if file.loading {
+        return;
+    }
+
+    for (entity, spawn, transform) in &spawns {
+        let Some(kind) = types.get(&spawn.kind) else {
+            warn!("Unknown enemy type '{}'", spawn.kind);
+            commands.entity(entity).remove::<EnemySpawn>();
+            continue;
+        };
+
+        let mut pos = transform.translation;
+        if let Some(ground) = ground_below(&world, entity, pos + Vec3::Y * config.step_height, config.spawn_drop) {
+            pos = ground;
+        }
+
+        let triangles = enemy_collider(kind.size);
+        let forward = transform.forward();
+        let facing = Vec3::new(forward.x, 0.0, forward.z).normalize_or(Vec3::X);
+        commands.entity(entity).insert((
+            Enemy {
+                kind: spawn.kind.clone(),
+                action: Action::Idle,
+                facing,
+                waypoints: spawn.waypoints.clone(),
+                next_waypoint: 0,
+                last_seen: None,
+                cooldown: 0.0,
+            },
+            Senses::default(),
+            Health::new(kind.health),
+            Transform::from_translation(pos).looking_to(facing, Vec3::Y),
+            build_collision_tree(&triangles, TRIANGLE_LIMIT),
+            Triangles(triangles),
+            CollisionLayers(Layers::ENEMY | Layers::WEB_ATTACHABLE),
+            NavAgent::new(0.0, kind.size.x.max(kind.size.z) * 0.5),
+            Anchors::default(), // anchors are for the level, not something that walks off with them
+        ));
+    }
+}
+
+// nothing but the player between its eyes and them
+fn can_see(world: &CollisionWorld, entity: Entity, eye: Vec3, target: Vec3) -> bool {
+    let filter = QueryFilter::line_of_sight().exclude_entity(entity);
+    world.cast_ray_closest(math::Ray3d::new(eye, target - eye), &filter).is_none_or(|hit| hit.distance >= 1.0)
+}
+
+pub fn perceive(
+    mut enemies: Query<(Entity, &mut Enemy, &mut Senses, &Transform, &Health)>,
+    player: Option<Single<&CameraState, With<PlayerMotion>>>,
+    types: Res<EnemyTypes>,
+    world: CollisionWorld,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    let player = player.map(|player| player.pos);
+
+    for (entity, mut enemy, mut senses, transform, health) in &mut enemies {
+        let Some(kind) = types.get(&enemy.kind) else {
+            continue;
+        };
+
+        let eye = transform.translation + Vec3::Y * kind.eye_height;
+        let sees_player = player.is_some_and(|player| {
+            let offset = player - eye;
+            offset.length() <= kind.view_distance
+                && enemy.facing.angle_between(offset).to_degrees() <= kind.view_angle
+                && can_see(&world, entity, eye, player)
+        });
+
+        if sees_player {
+            enemy.last_seen = Some((player.unwrap(), now));
+        }
+
+        enemy.cooldown = (enemy.cooldown - time.delta_secs()).max(0.0);
+        *senses = Senses {
+            sees_player,
+            since_seen: enemy.last_seen.map(|(_, at)| now - at),
+            player_distance: enemy.last_seen.map_or(f32::INFINITY, |(pos, _)| pos.distance(eye)),
+            health: health.current / health.max,
+            attack_ready: enemy.cooldown == 0.0,
+        };
+    }
+}
+
+pub fn think(
+    mut enemies: Query<(Entity, &mut Enemy, &Senses), Without<Webbed>>,
+    types: Res<EnemyTypes>,
+    mut changed: EventWriter<EnemyActionChanged>,
+) {
+    for (entity, mut enemy, senses) in &mut enemies {
+        let Some(kind) = types.get(&enemy.kind) else {
+            continue;
+        };
+
+        let action = choose_action(&kind.behaviours, senses);
+        if action != enemy.action {
+            changed.send(EnemyActionChanged { entity, from: enemy.action, to: action });
+            enemy.action = action;
+        }
+    }
+}
+
+// where to go, for its 'NavAgent', and attacks
+pub fn act(
+    mut enemies: Query<(&mut Enemy, &mut NavAgent, &mut Transform, Has<Webbed>)>,
+    player: Option<Single<(Entity, &CameraState), With<PlayerMotion>>>,
+    types: Res<EnemyTypes>,
+    config: Res<EnemyConfig>,
+    mut damage: EventWriter<Damage>,
+) {
+    let player = player.map(|player| (player.0, player.1.pos));
+
+    for (mut enemy, mut agent, mut transform, webbed) in &mut enemies {
+        let Some(kind) = types.get(&enemy.kind) else {
+            continue;
+        };
+
+        let pos = transform.translation;
+        let flat = |to: Vec3| Vec3::new(to.x - pos.x, 0.0, to.z - pos.z);
+        let (goal, speed) = match enemy.action {
+            _ if webbed => (None, 0.0),
+            Action::Idle => (None, 0.0),
+            Action::Patrol => match enemy.waypoints.get(enemy.next_waypoint) {
+                Some(&waypoint) => {
+                    if flat(waypoint).length() < config.waypoint_radius {
+                        enemy.next_waypoint = (enemy.next_waypoint + 1) % enemy.waypoints.len();
+                    }
+                    (Some(enemy.waypoints[enemy.next_waypoint]), kind.walk_speed)
+                }
+                None => (None, 0.0),
+            },
+            Action::Chase => (player.map(|(_, player)| player), kind.run_speed),
+            Action::Search => (enemy.last_seen.map(|(seen, _)| seen), kind.walk_speed),
+            Action::Flee => (player.map(|(_, player)| pos - flat(player).normalize_or_zero() * config.flee_distance), kind.run_speed),
+            Action::Attack => {
+                if let Some((player, player_pos)) = player {
+                    let eye = pos + Vec3::Y * kind.eye_height;
+                    if enemy.cooldown == 0.0 && eye.distance(player_pos) <= kind.attack_range {
+                        damage.send(Damage { entity: player, amount: kind.attack_damage, kind: DamageKind::Attack });
+                        enemy.cooldown = kind.attack_cooldown;
+                    }
+
+                    // face them while it's at it
+                    enemy.facing = flat(player_pos).normalize_or(enemy.facing);
+                }
+                (None, 0.0)
+            }
+        };
+
+        // already there
+        agent.target = goal.filter(|&goal| flat(goal).length() >= config.waypoint_radius);
+        agent.speed = speed;
+
+        let moving = Vec3::new(agent.velocity.x, 0.0, agent.velocity.z);
+        if moving.length() > 1e-3 {
+            enemy.facing = moving.normalize();
+        }
+        transform.look_to(enemy.facing, Vec3::Y);
+    }
+}
+
+pub fn untangle(mut webbed: Query<(Entity, &mut Webbed)>, time: Res<Time>, mut commands: Commands) {
+    for (entity, mut web) in &mut webbed {
+        web.remaining -= time.delta_secs();
+        if web.remaining <= 0.0 {
+            commands.entity(entity).remove::<Webbed>();
+        }
+    }
+}
+
+pub fn remove_dead_enemies(mut died: EventReader<Died>, enemies: Query<(), With<Enemy>>, mut commands: Commands) {
+    for died in died.read() {
+        if enemies.contains(died.entity) {
+            commands.entity(died.entity).despawn_recursive();
+        }
+    }
+}
+
+pub fn add_enemy_meshes(
+    enemies: Query<(Entity, &Enemy), Added<Enemy>>,
+    types: Res<EnemyTypes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut commands: Commands,
+) {
+    for (entity, enemy) in &enemies {
+        let Some(kind) = types.get(&enemy.kind) else {
+            continue;
+        };
+
+        commands.entity(entity).with_child((
+            Mesh3d(meshes.add(Cuboid::from_size(kind.size))),
+            MeshMaterial3d(materials.add(StandardMaterial::from_color(Color::srgb(0.6, 0.1, 0.1)))),
+            Transform::from_translation(Vec3::Y * kind.size.y * 0.5),
+        ));
+    }
+}
+
+// view cones, coloured by what each enemy is doing, and patrol routes
+pub fn draw_enemies(enemies: Query<(&Enemy, &Transform, Option<&Webbed>)>, types: Res<EnemyTypes>, mut gizmos: Gizmos) {
+    for (enemy, transform, webbed) in &enemies {
+        let Some(kind) = types.get(&enemy.kind) else {
+            continue;
+        };
+
+        let color = match (enemy.action, webbed) {
+            (_, Some(_)) => WHITE,
+            (Action::Idle | Action::Patrol, _) => YELLOW,
+            (Action::Search, _) => ORANGE,
+            _ => RED,
+        };
+
+        let eye = transform.translation + Vec3::Y * kind.eye_height;
+        for angle in [-kind.view_angle, 0.0, kind.view_angle] {
+            let dir = Quat::from_rotation_y(angle.to_radians()) * enemy.facing;
+            gizmos.line(eye, eye + dir * kind.view_distance, color);
+        }
+
+        for (i, &waypoint) in enemy.waypoints.iter().enumerate() {
+            let next = enemy.waypoints[(i + 1) % enemy.waypoints.len()];
+            gizmos.line(waypoint, next, YELLOW);
+        }
+    }
+}
+
+pub fn spawn_enemy_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let kind = args.first().ok_or("missing enemy type")?;
+    if world.resource::<EnemyTypes>().get(kind).is_none() {
+        let known: Vec<&str> = world.resource::<EnemyTypes>().0.keys().map(String::as_str).collect();
+        return Err(format!("unknown enemy type '{kind}', try one of {}", known.join(", ")));
+    }
+
+    let cam = world.query::<&CameraState>().get_single(world).map_err(|_| "there's no player")?;
+    let forward = Vec3::new(cam.forward.x, 0.0, cam.forward.z).normalize_or(Vec3::X);
+    let pos = cam.pos + forward * 5.0;
+
+    // facing the player
+    world.spawn((
+        EnemySpawn { kind: kind.to_string(), waypoints: Vec::new() },
+        Transform::from_translation(pos).looking_to(-forward, Vec3::Y),
+    ));
+    Ok(format!("spawned a {kind} at {pos}"))
+}
+
+pub fn web_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let target = world.resource::<AimTarget>().0.ok_or("not aiming at anything")?;
+    if world.get::<Enemy>(target.entity).is_none() {
+        return Err("not aiming at an enemy".to_string());
+    }
+
+    let remaining = world.resource::<EnemyConfig>().web_time;
+    world.entity_mut(target.entity).insert(Webbed { remaining });
+    Ok(format!("webbed {} for {remaining}s", target.entity))
+}
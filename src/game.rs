@@ -5,44 +5,235 @@ use bevy::window::PrimaryWindow;
 use bevy::pbr::PointLightShadowMap;
 
 use crate::{physics, math};
-use crate::physics::collision::{ShouldRenderCollider, collision, RecursiveAABB, Triangles};
+use crate::aim::AimTarget;
+use crate::camera::{CameraSet, CameraState};
+use crate::console::{parse_arg, ConsoleAppExt};
+use crate::tuning::TuningAppExt;
+use crate::input::InputSet;
+use crate::level::{self, CurrentLevel, LevelEntity};
+use crate::physics::CollisionSet;
+use crate::physics::collision::{ShouldRenderCollider, collision_traced};
+use crate::physics::debug::{ColliderDebug, RayTrace};
+use crate::physics::dynamic::{DynamicCollidable, DynamicCollider};
+use crate::physics::layers::{CollisionWorld, QueryFilter};
+use crate::physics::triggers::{Trigger, TriggerEntered, TriggerExited, TriggerTracked};
+use crate::health::{Checkpoint, Health};
+use crate::player::{NoClip, PlayerMotion, PlayerState, RequestPlayerState};
+use crate::race::RaceState;
+use crate::settings::Settings;
 
 #[derive(Component)]
 pub struct Island1;
 
-#[derive(Component, Default)]
-pub struct CameraState {
-    pub yaw: f32,
-    pub pitch: f32,
-    pub pos: Vec3,
-    pub forward: Vec3,
-    pub right: Vec3,
-}
-
 #[derive(Component)]
 pub struct Light1;
 
 #[derive(Component)]
 pub struct Light2;
 
-const ROT_SPEED: f32 = 0.2;
+// a player less than this far above a dynamic collider is carried along with it
+const STAND_DISTANCE: f32 = 2.0;
+
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct GameConfig {
+    #[reflect(ignore)]
+    pub level: Option<String>, // asset path of the level loaded on startup
+    #[reflect(ignore)]
+    pub island: bool, // spawn the island, the player and the lights. needs the renderer and a window
+    pub island_rotation_speed: f32, // radians per second
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            level: Some(Settings::default().level),
+            island: true,
+            island_rotation_speed: 0.2,
+        }
+    }
+}
+
+impl GameConfig {
+    // nothing is spawned, tests bring their own player and colliders
+    pub fn headless() -> Self {
+        Self {
+            level: None,
+            island: false,
+            ..default()
+        }
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameSet {
+    Carry, // before 'InputSet', moves the player along with whatever it stands on
+    Update, // after 'CameraSet' and before 'CollisionSet::Debug', so the debug ray is this frame's
+}
+
+// the island, levels and the player's interactions with the world
+#[derive(Default)]
+pub struct GamePlugin {
+    pub config: GameConfig,
+}
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .init_resource::<Settings>() // defaults, main replaces them with the loaded ones
+            .init_asset::<level::Level>()
+            .init_asset_loader::<level::LevelLoader>()
+            .configure_sets(Update, (
+                GameSet::Carry.before(InputSet),
+                GameSet::Update.after(CameraSet).before(CollisionSet::Debug),
+            ))
+            .add_systems(Startup, load_level)
+            .add_systems(Update, carry_player.in_set(GameSet::Carry))
+            .add_systems(Update, (update, log_triggers, level::spawn_level.run_if(resource_exists::<CurrentLevel>)).in_set(GameSet::Update))
+            .add_console_command("teleport", "<x> <y> <z>", "move the player", teleport_command)
+            .add_console_command("noclip", "", "fly through everything, without gravity or being carried", noclip_command)
+            .add_console_command("timescale", "<scale>", "speed time up or slow it down, 1 is normal", timescale_command)
+            .add_console_command("level load", "<name>", "replace the level with 'levels/<name>.level.ron'", level_load_command)
+            .add_tunable::<GameConfig>("game");
+
+        if self.config.island {
+            app.add_systems(Startup, (setup, setup_graphics));
+        }
+        // app.add_systems(Update, debug_ecs);
+    }
+}
+
+pub fn load_level(config: Res<GameConfig>, server: Res<AssetServer>, mut commands: Commands) {
+    if let Some(level) = &config.level {
+        commands.insert_resource(CurrentLevel::new(server.load(level)));
+    }
+}
 
 pub fn update(
     mut islands: Query<&mut Transform, With<Island1>>,
-    collision_data: Query<(&RecursiveAABB, &Triangles, &GlobalTransform)>,
+    world: CollisionWorld,
     cam: Single<&CameraState>,
     time: Res<Time>,
+    config: Res<GameConfig>,
+    aim: Res<AimTarget>,
+    mut debug: ResMut<ColliderDebug>,
 ) {
     for mut island in &mut islands {
-        *island = island.with_rotation(Quat::from_rotation_y(time.elapsed_secs() * ROT_SPEED));
+        *island = island.with_rotation(Quat::from_rotation_y(time.elapsed_secs() * config.island_rotation_speed));
     }
 
+    // the raw camera ray, for the overlay. the web itself goes wherever aim assist picked
     let ray = math::Ray3d::new(cam.pos, cam.forward);
+    let filter = QueryFilter::web_swing();
 
-    for (recursive_aabb, triangle_data, transform) in &collision_data {
-        let collisions = collision(ray, recursive_aabb, triangle_data, &transform.compute_matrix());
-        if collisions.len() > 0 {
-            debug!("{:?}", collisions);
+    if debug.enabled {
+        for (_, recursive_aabb, triangle_data, transform) in world.iter(&filter) {
+            let mut trace = RayTrace::default();
+            collision_traced(ray, recursive_aabb, triangle_data, &transform.compute_matrix(), &mut trace);
+            // traces are cleared every frame, don't let that count as a settings change
+            debug.bypass_change_detection().traces.push(trace);
+        }
+    }
+
+    if let Some(target) = aim.0 {
+        debug!("web would stick to {:?} at {:?}", target.entity, target.point);
+    }
+}
+
+// keeps the player on top of moving colliders, e.g. the rotating island.
+// uses last frame's movement, so this has to run before the player's own movement is applied
+pub fn carry_player(
+    world: CollisionWorld,
+    dynamic_colliders: Query<&DynamicCollider>,
+    mut cam: Single<&mut CameraState>,
+    noclip: Res<NoClip>,
+) {
+    if noclip.0 {
+        return;
+    }
+
+    let down = math::Ray3d::new(cam.pos, Vec3::NEG_Y);
+    let filter = QueryFilter::character();
+
+    let Some(ground) = world.cast_ray_closest(down, &filter) else {
+        return;
+    };
+
+    if ground.distance >= STAND_DISTANCE {
+        return;
+    }
+
+    if let Ok(dynamic) = dynamic_colliders.get(ground.entity) {
+        cam.pos = dynamic.carry(cam.pos);
+        // turn with it too. yaw is in degrees and grows clockwise when looking down
+        cam.yaw -= dynamic.delta_rotation().to_euler(EulerRot::YXZ).0.to_degrees();
+    }
+}
+
+pub fn teleport_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let pos = Vec3::new(parse_arg(args, 0, "x")?, parse_arg(args, 1, "y")?, parse_arg(args, 2, "z")?);
+    let (mut cam, mut motion) = world.query::<(&mut CameraState, &mut PlayerMotion)>().get_single_mut(world).map_err(|_| "there's no player")?;
+    cam.pos = pos;
+    // arriving at a standstill, like a respawn, so a fast fall doesn't carry over into a hard landing
+    motion.velocity = Vec3::ZERO;
+    world.send_event(RequestPlayerState(PlayerState::Falling));
+    if let Some(mut race) = world.get_resource_mut::<RaceState>() {
+        race.teleported();
+    }
+    Ok(format!("teleported to {pos}"))
+}
+
+pub fn noclip_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let mut noclip = world.resource_mut::<NoClip>();
+    noclip.0 = !noclip.0;
+    Ok(format!("noclip {}", if noclip.0 { "on" } else { "off" }))
+}
+
+pub fn timescale_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let scale: f32 = parse_arg(args, 0, "scale")?;
+    if !(scale >= 0.0 && scale.is_finite()) {
+        return Err(format!("invalid scale '{scale}'"));
+    }
+
+    world.resource_mut::<Time<Virtual>>().set_relative_speed(scale);
+    Ok(format!("timescale {scale}"))
+}
+
+// a bare name is looked for in 'assets/levels', anything with a '/' or an extension is an asset path
+pub fn level_load_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let name = args.first().ok_or("missing level name")?;
+    let path = if name.contains('/') || name.contains('.') { name.to_string() } else { format!("levels/{name}.level.ron") };
+
+    let old: Vec<Entity> = world.query_filtered::<Entity, With<LevelEntity>>().iter(world).collect();
+    for entity in old {
+        world.entity_mut(entity).despawn_recursive();
+    }
+
+    // the checkpoints were the old level's, until the player reaches a new one they come back wherever they are now
+    let pos = world.query::<&CameraState>().get_single(world).ok().map(|cam| cam.pos);
+    if let Some(mut checkpoint) = world.get_resource_mut::<Checkpoint>() {
+        *checkpoint = Checkpoint { trigger: None, pos };
+    }
+
+    let handle = world.resource::<AssetServer>().load(path.clone());
+    world.insert_resource(CurrentLevel::new(handle));
+    world.resource_mut::<GameConfig>().level = Some(path.clone());
+    Ok(format!("loading {path}"))
+}
+
+pub fn log_triggers(
+    mut entered: EventReader<TriggerEntered>,
+    mut exited: EventReader<TriggerExited>,
+    triggers: Query<&Trigger>,
+) {
+    for event in entered.read() {
+        if let Ok(trigger) = triggers.get(event.trigger) {
+            debug!("{:?} entered trigger '{}'", event.entity, trigger.name);
+        }
+    }
+
+    for event in exited.read() {
+        if let Ok(trigger) = triggers.get(event.trigger) {
+            debug!("{:?} exited trigger '{}'", event.entity, trigger.name);
         }
     }
 }
@@ -57,23 +248,25 @@ pub fn debug_ecs(entities: Query<(Entity, &ShouldRenderCollider)>, mut commands:
     debug!("------------------------------------------------------\n\n");
 }
 
-pub fn setup(
-    mut commands: Commands,
-    mut clear_color: ResMut<ClearColor>,
-    mut window: Single<&mut Window, With<PrimaryWindow>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    server: Res<AssetServer>,
-) {
-    window.title = "Spiderman".to_string();
-    window.cursor_options.visible = false;
-    clear_color.0 = Color::srgb(115.0/255.0, 121.0/255.0, 121.0/255.0);
-    commands.insert_resource(PointLightShadowMap { size: 2048 });
-    commands.insert_resource(WireframeConfig {
-        global: false,
-        default_color: RED.into(),
-    });
+// the player, a camera that starts out flying, see 'PlayerState'
+pub fn spawn_player(commands: &mut Commands, pos: Vec3) -> Entity {
+    let mut camera_state = CameraState {
+        pos,
+        ..default()
+    };
+    camera_state.update_vectors();
+
+    commands.spawn((
+        Camera3d::default(),
+        Transform::from_translation(pos),
+        camera_state,
+        PlayerMotion::default(),
+        Health::default(),
+        TriggerTracked,
+    )).id()
+}
 
+pub fn setup(mut commands: Commands, server: Res<AssetServer>) {
     let island_handle = server.load(GltfAssetLabel::Scene(0).from_asset("island1/Island1Export.gltf"));
 
     commands.spawn((
@@ -81,8 +274,11 @@ pub fn setup(
         Transform::from_scale(Vec3::new(0.1, 0.1, 0.1)),
         Island1,
         physics::collision::Collidable(vec![String::from("Cube.002")]),
+        DynamicCollidable,
     ));
 
+    spawn_player(&mut commands, Vec3::ZERO);
+
     // let cube_handle = server.load(GltfAssetLabel::Scene(0).from_asset("cube/untitled.gltf"));
 
     // commands.spawn((
@@ -91,6 +287,25 @@ pub fn setup(
     //     physics::collision::Collidable(vec![String::from("Cube")]),
     // ));
 
+}
+
+// everything that's only there to be looked at
+pub fn setup_graphics(
+    mut commands: Commands,
+    mut clear_color: ResMut<ClearColor>,
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    settings: Res<Settings>,
+) {
+    window.cursor_options.visible = false;
+    clear_color.0 = settings.clear_color();
+    commands.insert_resource(PointLightShadowMap { size: settings.shadow_map_size });
+    commands.insert_resource(WireframeConfig {
+        global: false,
+        default_color: RED.into(),
+    });
+
     let bruh = Cuboid::new(50.0, 1.0, 50.0);
 
     commands.spawn((
@@ -116,11 +331,4 @@ pub fn setup(
         Transform::from_xyz(3.0, 4.0, -3.0),
         Light2
     ));
-
-    commands.spawn((
-        Camera3d::default(),
-        CameraState {
-            ..default()
-        }
-    ));
 }
\ No newline at end of file
@@ -0,0 +1,226 @@
+use bevy::prelude::*;
+
+use crate::air::AirState;
+use crate::camera::CameraState;
+use crate::console::ConsoleAppExt;
+use crate::physics::triggers::{Trigger, TriggerEntered};
+use crate::player::{Landed, PlayerMotion, PlayerSet, PlayerState, RequestPlayerState};
+use crate::tuning::TuningAppExt;
+
+// the start of a trigger's name says what it does. kill planes kill whatever goes in, and the player respawns at the
+// last checkpoint they went through
+pub const KILL_PREFIX: &str = "kill";
+pub const CHECKPOINT_PREFIX: &str = "checkpoint";
+
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct HealthConfig {
+    pub safe_impact_speed: f32, // landing any slower than this, into the ground, doesn't hurt
+    pub damage_per_speed: f32, // for every unit per second over it
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            safe_impact_speed: 20.0,
+            damage_per_speed: 4.0,
+        }
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new(100.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DamageKind {
+    Impact,
+    KillPlane,
+    Attack,
+    Melee,
+    Console,
+}
+
+// anything that hurts goes through this, see 'apply_damage'
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Damage {
+    pub entity: Entity,
+    pub amount: f32,
+    pub kind: DamageKind,
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Died {
+    pub entity: Entity,
+    pub kind: DamageKind,
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Respawned {
+    pub entity: Entity,
+    pub pos: Vec3,
+}
+
+// where the player comes back after dying
+#[derive(Resource, Default, Debug)]
+pub struct Checkpoint {
+    pub trigger: Option<Entity>, // the last one entered, 'None' for where the player first spawned
+    pub pos: Option<Vec3>,
+}
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HealthSet;
+
+// damage, death and respawning at checkpoints. 'Health' goes on anything that can be hurt, and the player gets one
+// from 'game::spawn_player'. runs in 'HealthSet' after 'PlayerSet', with the level's triggers named by 'KILL_PREFIX'
+// and 'CHECKPOINT_PREFIX'
+#[derive(Default)]
+pub struct HealthPlugin {
+    pub config: HealthConfig,
+}
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .init_resource::<Checkpoint>()
+            .add_event::<Damage>()
+            .add_event::<Died>()
+            .add_event::<Respawned>()
+            .configure_sets(Update, HealthSet.after(PlayerSet))
+            .add_systems(Update, (
+                (impact_damage, level_triggers, record_spawn_point),
+                apply_damage,
+                respawn_player,
+            ).chain().in_set(HealthSet))
+            .add_console_command("kill", "", "kill the player, who respawns at the last checkpoint", kill_command)
+            .add_tunable::<HealthConfig>("health");
+    }
+}
+
+// only the speed into the ground counts, sliding along a slope doesn't hurt
+pub fn impact_damage_amount(config: &HealthConfig, velocity: Vec3, normal: Vec3) -> f32 {
+    let impact = -velocity.dot(normal);
+    (impact - config.safe_impact_speed).max(0.0) * config.damage_per_speed
+}
+
+pub fn impact_damage(config: Res<HealthConfig>, mut landed: EventReader<Landed>, mut damage: EventWriter<Damage>) {
+    for landed in landed.read() {
+        let amount = impact_damage_amount(&config, landed.velocity, landed.normal);
+        if amount > 0.0 {
+            damage.send(Damage { entity: landed.entity, amount, kind: DamageKind::Impact });
+        }
+    }
+}
+
+// kill planes and checkpoints
+pub fn level_triggers(
+    mut entered: EventReader<TriggerEntered>,
+    triggers: Query<(&Trigger, &GlobalTransform)>,
+    players: Query<(), With<PlayerMotion>>,
+    mut checkpoint: ResMut<Checkpoint>,
+    mut damage: EventWriter<Damage>,
+) {
+    for entered in entered.read() {
+        let Ok((trigger, transform)) = triggers.get(entered.trigger) else {
+            continue;
+        };
+
+        if trigger.name.starts_with(KILL_PREFIX) {
+            damage.send(Damage { entity: entered.entity, amount: f32::INFINITY, kind: DamageKind::KillPlane });
+        } else if trigger.name.starts_with(CHECKPOINT_PREFIX) && players.contains(entered.entity) && checkpoint.trigger != Some(entered.trigger) {
+            info!("Checkpoint '{}'", trigger.name);
+            *checkpoint = Checkpoint { trigger: Some(entered.trigger), pos: Some(transform.translation()) };
+        }
+    }
+}
+
+// until there's a checkpoint the player comes back where they started
+pub fn record_spawn_point(added: Query<&CameraState, Added<PlayerMotion>>, mut checkpoint: ResMut<Checkpoint>) {
+    if let Some(camera_state) = added.iter().next().filter(|_| checkpoint.pos.is_none()) {
+        checkpoint.pos = Some(camera_state.pos);
+    }
+}
+
+pub fn apply_damage(mut damage: EventReader<Damage>, mut health: Query<&mut Health>, mut died: EventWriter<Died>) {
+    for damage in damage.read() {
+        let Ok(mut health) = health.get_mut(damage.entity) else {
+            continue;
+        };
+
+        // already dead this frame
+        if health.is_dead() {
+            continue;
+        }
+
+        health.current = (health.current - damage.amount).max(0.0);
+        debug!("{:?} took {} {:?} damage, {} left", damage.entity, damage.amount, damage.kind, health.current);
+        if health.is_dead() {
+            died.send(Died { entity: damage.entity, kind: damage.kind });
+        }
+    }
+}
+
+// back at the last checkpoint, or where they first spawned, at full health and falling with no velocity
+pub fn respawn_player(
+    mut died: EventReader<Died>,
+    checkpoint: Res<Checkpoint>,
+    mut players: Query<(Entity, &mut CameraState, &mut PlayerMotion, &mut Health)>,
+    mut air: Option<ResMut<AirState>>,
+    mut requests: EventWriter<RequestPlayerState>,
+    mut respawned: EventWriter<Respawned>,
+) {
+    for died in died.read() {
+        let Ok((entity, mut camera_state, mut motion, mut health)) = players.get_mut(died.entity) else {
+            continue;
+        };
+
+        let pos = checkpoint.pos.unwrap_or(camera_state.pos);
+        info!("Died from {:?}, respawning at {pos}", died.kind);
+        camera_state.pos = pos;
+        motion.velocity = Vec3::ZERO;
+        health.current = health.max;
+        if let Some(air) = air.as_mut() {
+            **air = AirState::default();
+        }
+
+        requests.send(RequestPlayerState(PlayerState::Falling));
+        respawned.send(Respawned { entity, pos });
+    }
+}
+
+pub fn kill_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let player = world.query_filtered::<Entity, With<PlayerMotion>>().get_single(world).map_err(|_| "there's no player")?;
+    world.send_event(Damage { entity: player, amount: f32::INFINITY, kind: DamageKind::Console });
+    Ok("killed".to_string())
+}
+
+#[test]
+fn test_impact_damage() {
+    let config = HealthConfig::default();
+
+    // straight down, just over the safe speed
+    assert_eq!(impact_damage_amount(&config, Vec3::new(0.0, -10.0, 0.0), Vec3::Y), 0.0);
+    assert_eq!(impact_damage_amount(&config, Vec3::new(0.0, -22.0, 0.0), Vec3::Y), 2.0 * config.damage_per_speed);
+
+    // fast along a 45 degree slope is mostly sliding
+    let slope = Vec3::new(-1.0, 1.0, 0.0).normalize();
+    assert_eq!(impact_damage_amount(&config, Vec3::new(-25.0, -25.0, 0.0), slope), 0.0);
+    assert!(impact_damage_amount(&config, Vec3::new(25.0, -25.0, 0.0), slope) > 0.0);
+}
@@ -1,111 +1,170 @@
 use bevy::input::mouse::MouseMotion;
 use bevy::math::DVec2;
+use bevy::pbr::wireframe::WireframeConfig;
 use bevy::prelude::*;
 use bevy::window::{PrimaryWindow, WindowCloseRequested};
-use super::game::{CameraState, Light1, Light2};
+use super::camera::{CameraConfig, CameraState};
+use super::tuning::TuningAppExt;
+use super::game::{Light1, Light2};
+use super::physics::debug::{ColliderDebug, WireframeSupport};
+use super::player::PlayerDebug;
+
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct InputConfig {
+    pub sensitivity: f32, // degrees per pixel of mouse movement
+    pub center_cursor: bool, // keep the cursor in the middle of the window so it never leaves it
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.05,
+            center_cursor: true,
+        }
+    }
+}
+
+// what the player wants to do this frame, for whichever 'PlayerState' is moving them. cleared after 'PlayerSet'
+#[derive(Resource, Default, Debug)]
+pub struct PlayerInput {
+    pub movement: Vec3, // x right, y up, z forward, each -1 to 1
+    pub jump: bool,
+    pub trick: bool,
+    pub shoot: bool, // a web shot
+    pub melee: bool,
+}
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InputSet;
+
+// mouse look applied to the 'CameraState', WASD into 'PlayerInput', and the debug keys. needs bevy's own 'InputPlugin'
+// and 'CameraPlugin'. the debug keys do nothing for the overlays of 'CollisionPlugin' or 'PlayerPlugin' if they're missing
+#[derive(Default)]
+pub struct InputPlugin {
+    pub config: InputConfig,
+}
 
-const SENSITVITY: f32 = 0.05;
-const SPEED: f32 = 5.0;
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .init_resource::<PlayerInput>()
+            .add_event::<WindowCloseRequested>() // there's no window, or anyone to send it to, when headless
+            .add_systems(Update, (mouse_input, keyboard_input).chain().in_set(InputSet))
+            .add_systems(Update, (close_on_escape, debug_input).in_set(InputSet))
+            .add_tunable::<InputConfig>("input");
+    }
+}
 
 pub fn mouse_input(
     mut delta_mouse: EventReader<MouseMotion>,
     mut camera_state: Single<&mut CameraState>,
-    mut camera_transform: Single<&mut Transform, With<CameraState>>,
     window: Option<Single<&mut Window, With<PrimaryWindow>>>,
+    config: Res<InputConfig>,
+    camera_config: Res<CameraConfig>,
 ) {
     for d in delta_mouse.read() {
-        camera_state.yaw += d.delta.x * SENSITVITY;
-        camera_state.pitch -= d.delta.y * SENSITVITY;
+        camera_state.yaw += d.delta.x * config.sensitivity;
+        camera_state.pitch -= d.delta.y * config.sensitivity;
 
-        camera_state.pitch = camera_state.pitch.clamp(-89.9, 89.9);
+        camera_state.pitch = camera_state.pitch.clamp(-camera_config.max_pitch, camera_config.max_pitch);
     }
 
-    if let Some(mut window) = window {
+    if let Some(mut window) = window.filter(|_| config.center_cursor) {
         let x = window.resolution.width() as f64 * 0.5;
         let y = window.resolution.height() as f64 * 0.5;
         window.set_physical_cursor_position(Some(DVec2::new(x, y)));
     }
 
-    eval_camera_vecs(&mut camera_state);
-    camera_transform.look_to(camera_state.forward, Vec3::Y);
+    // so movement this frame already goes the new way
+    camera_state.update_vectors();
 }
 
-fn eval_camera_vecs(camera_state: &mut CameraState) {
-    let forward = Vec3::new(
-        camera_state.yaw.to_radians().cos() * camera_state.pitch.to_radians().cos(),
-        camera_state.pitch.to_radians().sin(),
-        camera_state.yaw.to_radians().sin() * camera_state.pitch.to_radians().cos(),
-    ).normalize();
-
-    let right = forward.cross(Vec3::Y);
-
-    camera_state.forward = forward;
-    camera_state.right = right;
-}
-
-pub fn keyboard_input(
+pub fn close_on_escape(
     input: Res<ButtonInput<KeyCode>>,
-    time: Res<Time>,
     window: Option<Single<Entity, With<PrimaryWindow>>>,
     mut writer: EventWriter<WindowCloseRequested>,
-    mut camera_state: Single<&mut CameraState>,
-    mut set: ParamSet<(
-        Single<&mut Transform, With<CameraState>>,
-        Single<&mut Transform, With<Light1>>,
-        Single<&mut Transform, With<Light2>>,
-    )>,
 ) {
     if input.just_pressed(KeyCode::Escape) {
         if let Some(window) = window {
             writer.send(WindowCloseRequested { window: *window });
         }
     }
+}
 
-    let speed = SPEED * time.delta().as_secs_f32();
+// the lights are only there with a renderer, see 'game::setup_graphics'
+#[allow(clippy::type_complexity)]
+pub fn keyboard_input(
+    input: Res<ButtonInput<KeyCode>>,
+    mut player_input: ResMut<PlayerInput>,
+    camera_state: Single<&CameraState>,
+    mut lights: ParamSet<(
+        Option<Single<&mut Transform, With<Light1>>>,
+        Option<Single<&mut Transform, With<Light2>>>,
+    )>,
+) {
+    let axis = |positive: KeyCode, negative: KeyCode| input.pressed(positive) as i32 as f32 - input.pressed(negative) as i32 as f32;
+
+    player_input.movement = Vec3::new(
+        axis(KeyCode::KeyD, KeyCode::KeyA),
+        axis(KeyCode::Space, KeyCode::ShiftLeft),
+        axis(KeyCode::KeyW, KeyCode::KeyS),
+    );
+    player_input.jump = input.just_pressed(KeyCode::Space);
+    player_input.trick = input.just_pressed(KeyCode::KeyE);
+    player_input.shoot = input.just_pressed(KeyCode::KeyF);
+    player_input.melee = input.just_pressed(KeyCode::KeyQ);
 
-    if input.pressed(KeyCode::KeyW) {
-        camera_state.pos.x += camera_state.forward.x * speed;
-        camera_state.pos.y += camera_state.forward.y * speed;
-        camera_state.pos.z += camera_state.forward.z * speed;
+    if input.pressed(KeyCode::KeyV) {
+        debug!("{:?}", camera_state.pos);
     }
 
-    if input.pressed(KeyCode::KeyS) {
-        camera_state.pos.x -= camera_state.forward.x * speed;
-        camera_state.pos.y -= camera_state.forward.y * speed;
-        camera_state.pos.z -= camera_state.forward.z * speed;
+    if input.pressed(KeyCode::Digit1) {
+        if let Some(mut light) = lights.p0() {
+            light.translation = camera_state.pos;
+        }
     }
 
-    if input.pressed(KeyCode::KeyD) {
-        camera_state.pos.x += camera_state.right.x * speed;
-        camera_state.pos.y += camera_state.right.y * speed;
-        camera_state.pos.z += camera_state.right.z * speed;
+    if input.pressed(KeyCode::Digit2) {
+        if let Some(mut light) = lights.p1() {
+            light.translation = camera_state.pos;
+        }
     }
+}
 
-    if input.pressed(KeyCode::KeyA) {
-        camera_state.pos.x -= camera_state.right.x * speed;
-        camera_state.pos.y -= camera_state.right.y * speed;
-        camera_state.pos.z -= camera_state.right.z * speed;
-    }
+// F1: collider overlay, F2: leaf AABB wireframes, F3: wireframe everything, [ and ]: overlay depth, F6: player state
+pub fn debug_input(
+    input: Res<ButtonInput<KeyCode>>,
+    debug: Option<ResMut<ColliderDebug>>,
+    player_debug: Option<ResMut<PlayerDebug>>,
+    wireframe_config: Option<ResMut<WireframeConfig>>,
+    wireframe_support: Option<Res<WireframeSupport>>,
+) {
+    if let Some(mut debug) = debug {
+        if input.just_pressed(KeyCode::F1) {
+            debug.enabled = !debug.enabled;
+        }
 
-    if input.pressed(KeyCode::Space) {
-        camera_state.pos.y += speed;
-    }
+        if input.just_pressed(KeyCode::F2) {
+            debug.aabb_wireframes = !debug.aabb_wireframes;
+        }
 
-    if input.pressed(KeyCode::ShiftLeft) {
-        camera_state.pos.y -= speed;
-    }
+        if input.just_pressed(KeyCode::BracketLeft) {
+            debug.depth = debug.depth.saturating_sub(1);
+        }
 
-    if input.pressed(KeyCode::KeyV) {
-        debug!("{:?}", camera_state.pos);
+        if input.just_pressed(KeyCode::BracketRight) {
+            debug.depth += 1;
+        }
     }
 
-    if input.pressed(KeyCode::Digit1) {
-        set.p1().translation = set.p0().translation.clone();
+    if input.just_pressed(KeyCode::F3) {
+        match (wireframe_config, wireframe_support) {
+            (Some(mut wireframe_config), Some(support)) if support.0 => wireframe_config.global = !wireframe_config.global,
+            _ => info!("Wireframes aren't available with this adapter or render setup."),
+        }
     }
 
-    if input.pressed(KeyCode::Digit2) {
-        set.p2().translation = set.p0().translation.clone();
+    if let Some(mut player_debug) = player_debug.filter(|_| input.just_pressed(KeyCode::F6)) {
+        player_debug.enabled = !player_debug.enabled;
     }
-
-    set.p0().translation = camera_state.pos;
-}
\ No newline at end of file
+}
@@ -0,0 +1,172 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::fmt;
+
+use crate::enemy::EnemySpawn;
+use crate::physics::triggers::{Trigger, TriggerShape};
+use crate::race::RaceRing;
+
+// everything placed in a level that isn't part of its glTF scenes. stored as RON in 'assets/levels/<name>.level.ron'
+#[derive(Asset, TypePath, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Level {
+    pub triggers: Vec<TriggerDef>,
+    pub enemies: Vec<EnemyDef>,
+    pub races: Vec<RaceDef>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TriggerDef {
+    pub name: String,
+    pub shape: TriggerShape,
+    pub translation: Vec3,
+    #[serde(default)]
+    pub rotation: Quat,
+    #[serde(default = "one")]
+    pub scale: Vec3,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct EnemyDef {
+    pub kind: String, // in 'enemies.ron'
+    pub translation: Vec3,
+    #[serde(default)]
+    pub rotation: Quat,
+    #[serde(default)]
+    pub waypoints: Vec<Vec3>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RaceDef {
+    pub name: String,
+    pub rings: Vec<RingDef>, // in order, the first starts the race and the last finishes it
+}
+
+// faces along its rotation's forward, the way it's flown through
+#[derive(Deserialize, Debug, Clone)]
+pub struct RingDef {
+    pub translation: Vec3,
+    #[serde(default)]
+    pub rotation: Quat,
+    #[serde(default = "ring_radius")]
+    pub radius: f32,
+}
+
+fn ring_radius() -> f32 {
+    3.0
+}
+
+fn one() -> Vec3 {
+    Vec3::ONE
+}
+
+// the level currently being played
+#[derive(Resource)]
+pub struct CurrentLevel {
+    pub handle: Handle<Level>,
+    spawned: bool,
+}
+
+impl CurrentLevel {
+    pub fn new(handle: Handle<Level>) -> Self {
+        Self {
+            handle,
+            spawned: false,
+        }
+    }
+}
+
+// marks everything spawned from a level file
+#[derive(Component)]
+pub struct LevelEntity;
+
+pub fn spawn_level(mut current: ResMut<CurrentLevel>, levels: Res<Assets<Level>>, mut commands: Commands) {
+    if current.spawned {
+        return;
+    }
+
+    let Some(level) = levels.get(&current.handle) else {
+        return;
+    };
+
+    for trigger in &level.triggers {
+        commands.spawn((
+            Trigger::new(trigger.name.clone(), trigger.shape.clone()),
+            Transform {
+                translation: trigger.translation,
+                rotation: trigger.rotation,
+                scale: trigger.scale,
+            },
+            LevelEntity,
+        ));
+    }
+
+    for enemy in &level.enemies {
+        commands.spawn((
+            EnemySpawn { kind: enemy.kind.clone(), waypoints: enemy.waypoints.clone() },
+            Transform::from_translation(enemy.translation).with_rotation(enemy.rotation),
+            LevelEntity,
+        ));
+    }
+
+    for race in &level.races {
+        for (index, ring) in race.rings.iter().enumerate() {
+            commands.spawn((
+                RaceRing { race: race.name.clone(), index, radius: ring.radius },
+                Transform::from_translation(ring.translation).with_rotation(ring.rotation),
+                LevelEntity,
+            ));
+        }
+    }
+
+    current.spawned = true;
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Io(err) => write!(f, "io error: {err}"),
+            LevelError::Ron(err) => write!(f, "invalid level file: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    type Asset = Level;
+    type Settings = ();
+    type Error = LevelError;
+
+    async fn load(&self, reader: &mut dyn Reader, _settings: &(), _load_context: &mut LoadContext<'_>) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await.map_err(LevelError::Io)?;
+        ron::de::from_bytes(&bytes).map_err(LevelError::Ron)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+#[test]
+fn test_island1_level() {
+    let level: Level = ron::de::from_str(include_str!("../assets/levels/island1.level.ron")).unwrap();
+    assert_eq!(level.triggers[0].name, "kill_plane");
+    assert_eq!(level.triggers[0].shape, TriggerShape::Box { half_extents: Vec3::new(1000.0, 5.0, 1000.0) });
+    assert_eq!(level.triggers[0].translation, Vec3::new(0.0, -60.0, 0.0));
+    assert_eq!(level.triggers[0].scale, Vec3::ONE);
+    assert_eq!(level.races[0].rings.len(), 5);
+    assert_eq!(level.races[0].rings[0].radius, 3.0);
+}
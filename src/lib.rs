@@ -0,0 +1,20 @@
+pub mod aim;
+pub mod air;
+pub mod anchors;
+pub mod app;
+pub mod camera;
+pub mod combat;
+pub mod console;
+pub mod enemy;
+pub mod game;
+pub mod health;
+pub mod input;
+pub mod level;
+pub mod math;
+pub mod mission;
+pub mod nav;
+pub mod physics;
+pub mod player;
+pub mod race;
+pub mod settings;
+pub mod tuning;
@@ -1,34 +1,100 @@
-use bevy::log::{Level, LogPlugin};
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::log::LogPlugin;
+use bevy::pbr::wireframe::WireframePlugin;
 use bevy::prelude::*;
-use bevy::render::RenderPlugin;
-use bevy::render::settings::{RenderCreation, WgpuSettings, WgpuFeatures};
 
-mod game;
-mod input;
-mod physics;
-mod math;
+use spiderman::app::{simulation_app, TICK};
+use spiderman::aim::AimPlugin;
+use spiderman::air::AirPlugin;
+use spiderman::anchors::AnchorPlugin;
+use spiderman::camera::CameraPlugin;
+use spiderman::combat::CombatPlugin;
+use spiderman::console::ConsolePlugin;
+use spiderman::enemy::EnemyPlugin;
+use spiderman::game::{spawn_player, GameConfig, GamePlugin};
+use spiderman::health::HealthPlugin;
+use spiderman::input::InputPlugin;
+use spiderman::mission::MissionPlugin;
+use spiderman::nav::NavPlugin;
+use spiderman::physics::{CollisionConfig, CollisionPlugin};
+use spiderman::player::PlayerPlugin;
+use spiderman::race::RacePlugin;
+use spiderman::settings::{Settings, USAGE};
+use spiderman::tuning::TuningPlugin;
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins
-            .set(LogPlugin {
-                level: Level::TRACE,
-                filter: "wgpu=warn,naga=warn,wgpu_hal=warn,bevy_app=warn,offset_allocator=error,bevy_render=info".to_string(),
-                ..default()
-            })
-            .set(RenderPlugin {
-                render_creation: RenderCreation::Automatic(WgpuSettings {
-                    // WARN this is a native only feature. It will not work with webgl or webgpu
-                    features: WgpuFeatures::POLYGON_MODE_LINE,
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return;
+    }
+
+    let settings = match Settings::load(args) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            std::process::exit(1);
+        }
+    };
+
+    let log = LogPlugin {
+        level: settings.log_level().unwrap(), // checked when loading
+        filter: settings.log_filter.clone(),
+        ..default()
+    };
+
+    let mut app = if settings.headless {
+        // the level and nothing that needs a window, in real time
+        let base = MinimalPlugins.build().set(ScheduleRunnerPlugin::run_loop(TICK)).add(log);
+        let game = GameConfig {
+            level: Some(settings.level.clone()),
+            ..GameConfig::headless()
+        };
+        let mut app = simulation_app(base, game);
+        app.add_systems(Startup, |mut commands: Commands| {
+            spawn_player(&mut commands, Vec3::ZERO);
+        });
+
+        app
+    } else {
+        let mut app = App::new();
+        // the renderer picks up every feature the adapter has, including the POLYGON_MODE_LINE wireframes need.
+        // asking for it outright would fail to start on adapters without it
+        app.add_plugins(DefaultPlugins
+                .set(log)
+                .set(WindowPlugin {
+                    primary_window: Some(settings.window()),
                     ..default()
-                }),
-                ..default()
-            })
-        )
-        .add_plugins(bevy::pbr::wireframe::WireframePlugin)
-        .add_systems(Startup, game::setup)
-        .add_systems(Update, (game::update, input::mouse_input, input::keyboard_input))
-        .add_systems(Update, (physics::collision::construct_collision_trees, physics::collision::add_collider_wireframes))
-        // .add_systems(Update, game::debug_ecs)
-        .run();
+                })
+            )
+            .add_plugins((
+                CollisionPlugin { config: CollisionConfig { debug_draw: settings.render.is_debug(), ..default() } },
+                InputPlugin::default(),
+                PlayerPlugin::default(),
+                AirPlugin::default(),
+                HealthPlugin::default(),
+                (
+                    EnemyPlugin::default(),
+                    CombatPlugin::default(),
+                    MissionPlugin::default(),
+                    RacePlugin::default(),
+                    NavPlugin::default(),
+                ),
+                CameraPlugin::default(),
+                ConsolePlugin::default(),
+                AimPlugin::default(),
+                AnchorPlugin::default(),
+                TuningPlugin::default(),
+                GamePlugin { config: GameConfig { level: Some(settings.level.clone()), ..default() } },
+            ));
+
+        if settings.render.is_debug() {
+            app.add_plugins(WireframePlugin);
+        }
+
+        app
+    };
+
+    app.insert_resource(settings);
+    app.run();
 }
@@ -0,0 +1,628 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::fmt;
+
+use crate::air::Style;
+use crate::console::ConsoleAppExt;
+use crate::enemy::behaviour::Senses;
+use crate::enemy::{remove_dead_enemies, Enemy, EnemySpawn};
+use crate::health::{Damage, Died, Health, HealthSet};
+use crate::level::EnemyDef;
+use crate::physics::triggers::{Trigger, TriggerEntered};
+use crate::player::PlayerMotion;
+use crate::tuning::TuningAppExt;
+
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct MissionConfig {
+    pub result_time: f32, // seconds the HUD shows how a mission ended
+    #[reflect(ignore)]
+    pub hud: bool, // show the current objective, needs the renderer
+}
+
+impl Default for MissionConfig {
+    fn default() -> Self {
+        Self {
+            result_time: 5.0,
+            hud: true,
+        }
+    }
+}
+
+impl MissionConfig {
+    pub fn headless() -> Self {
+        Self {
+            hud: false,
+            ..default()
+        }
+    }
+}
+
+// a playtest goal, stored as RON in 'assets/missions/<name>.mission.ron'. objectives are done one after the other and
+// refer to the level's triggers by name
+#[derive(Asset, TypePath, Deserialize, Debug, Clone)]
+pub struct Mission {
+    pub name: String,
+    pub objectives: Vec<Objective>,
+    #[serde(default)]
+    pub fail: Vec<FailCondition>,
+    #[serde(default)]
+    pub time_limit: Option<f32>, // seconds for the whole mission
+    #[serde(default)]
+    pub reward: Reward, // for finishing it
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Objective {
+    pub description: String, // shown on the HUD
+    pub goal: Goal,
+    #[serde(default)]
+    pub time_limit: Option<f32>, // seconds from when it starts
+    #[serde(default)]
+    pub spawn: Vec<EnemyDef>, // when it starts
+    #[serde(default)]
+    pub reward: Reward,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub enum Goal {
+    Rings(Vec<String>), // triggers, in this order
+    Reach(String), // a trigger
+    Defeat { count: u32, #[serde(default)] kind: Option<String> }, // any kind if 'None'
+    Deliver { pickup: String, dropoff: String }, // triggers, what's picked up is dropped on dying
+    Survive(f32), // seconds
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub enum FailCondition {
+    PlayerDied,
+    Spotted, // by any enemy
+    Enter(String), // a trigger, e.g. the edge of the area
+    DamageOver(f32), // taken over the whole mission
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FailReason {
+    PlayerDied,
+    Spotted,
+    Entered(String),
+    TookDamage,
+    TimeUp,
+    ObjectiveTimeUp,
+    Aborted,
+}
+
+impl fmt::Display for FailReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FailReason::PlayerDied => write!(f, "died"),
+            FailReason::Spotted => write!(f, "spotted"),
+            FailReason::Entered(trigger) => write!(f, "went into '{trigger}'"),
+            FailReason::TookDamage => write!(f, "took too much damage"),
+            FailReason::TimeUp => write!(f, "out of time"),
+            FailReason::ObjectiveTimeUp => write!(f, "too slow"),
+            FailReason::Aborted => write!(f, "aborted"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Reward {
+    pub style: f32, // added to 'Style'
+    pub health: f32, // given back to the player, up to their max
+}
+
+// what happened in the world this frame, as far as a mission's concerned
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MissionSignal<'a> {
+    Entered(&'a str), // a trigger, by name
+    Defeated(&'a str), // an enemy, by kind
+    Damaged(f32),
+    PlayerDied,
+    Spotted,
+    Tick(f32), // seconds
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MissionUpdate {
+    ObjectiveCompleted(usize),
+    Completed,
+    Failed(FailReason),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MissionOutcome {
+    Completed,
+    Failed(FailReason),
+}
+
+// progress through a 'Mission', kept apart from it so the asset can be shared
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MissionRun {
+    pub objective: usize, // the current one
+    pub step: u32, // rings passed or enemies defeated for it
+    pub carrying: bool,
+    pub objective_time: f32,
+    pub time: f32,
+    pub damage: f32,
+    pub outcome: Option<MissionOutcome>,
+}
+
+impl MissionRun {
+    pub fn update(&mut self, mission: &Mission, signal: MissionSignal) -> Vec<MissionUpdate> {
+        if self.outcome.is_some() {
+            return Vec::new();
+        }
+
+        let Some(objective) = mission.objectives.get(self.objective) else {
+            self.outcome = Some(MissionOutcome::Completed);
+            return vec![MissionUpdate::Completed];
+        };
+
+        let fails = |condition: FailCondition| mission.fail.contains(&condition);
+        let failed = match signal {
+            MissionSignal::Tick(dt) => {
+                self.time += dt;
+                self.objective_time += dt;
+                if mission.time_limit.is_some_and(|limit| self.time > limit) {
+                    Some(FailReason::TimeUp)
+                } else if objective.time_limit.is_some_and(|limit| self.objective_time > limit) {
+                    Some(FailReason::ObjectiveTimeUp)
+                } else {
+                    None
+                }
+            }
+            MissionSignal::Damaged(amount) => {
+                self.damage += amount;
+                mission.fail.iter().any(|condition| matches!(condition, FailCondition::DamageOver(max) if self.damage > *max)).then_some(FailReason::TookDamage)
+            }
+            MissionSignal::PlayerDied => {
+                self.carrying = false;
+                fails(FailCondition::PlayerDied).then_some(FailReason::PlayerDied)
+            }
+            MissionSignal::Spotted => fails(FailCondition::Spotted).then_some(FailReason::Spotted),
+            MissionSignal::Entered(name) => fails(FailCondition::Enter(name.to_string())).then(|| FailReason::Entered(name.to_string())),
+            MissionSignal::Defeated(_) => None,
+        };
+
+        if let Some(reason) = failed {
+            self.outcome = Some(MissionOutcome::Failed(reason.clone()));
+            return vec![MissionUpdate::Failed(reason)];
+        }
+
+        let done = match (&objective.goal, signal) {
+            (Goal::Rings(rings), MissionSignal::Entered(name)) => {
+                if rings.get(self.step as usize).is_some_and(|ring| ring == name) {
+                    self.step += 1;
+                }
+                self.step as usize >= rings.len()
+            }
+            (Goal::Reach(trigger), MissionSignal::Entered(name)) => trigger == name,
+            (Goal::Defeat { count, kind }, MissionSignal::Defeated(defeated)) => {
+                if kind.as_ref().is_none_or(|kind| kind == defeated) {
+                    self.step += 1;
+                }
+                self.step >= *count
+            }
+            (Goal::Deliver { pickup, dropoff }, MissionSignal::Entered(name)) => {
+                self.carrying |= name == pickup;
+                self.carrying && name == dropoff
+            }
+            (Goal::Survive(time), MissionSignal::Tick(_)) => self.objective_time >= *time,
+            _ => false,
+        };
+
+        if !done {
+            return Vec::new();
+        }
+
+        let mut updates = vec![MissionUpdate::ObjectiveCompleted(self.objective)];
+        *self = MissionRun {
+            objective: self.objective + 1,
+            time: self.time,
+            damage: self.damage,
+            ..default()
+        };
+        if self.objective >= mission.objectives.len() {
+            self.outcome = Some(MissionOutcome::Completed);
+            updates.push(MissionUpdate::Completed);
+        }
+
+        updates
+    }
+
+    // how far through the current objective, for the ones that count
+    pub fn progress(&self, mission: &Mission) -> Option<(u32, u32)> {
+        match &mission.objectives.get(self.objective)?.goal {
+            Goal::Rings(rings) => Some((self.step, rings.len() as u32)),
+            Goal::Defeat { count, .. } => Some((self.step, *count)),
+            _ => None,
+        }
+    }
+}
+
+// the mission being played
+#[derive(Resource, Debug)]
+pub struct ActiveMission {
+    pub handle: Handle<Mission>,
+    pub run: Option<MissionRun>, // 'None' until the mission's loaded
+    pub ended_at: Option<f32>, // seconds since startup
+}
+
+impl ActiveMission {
+    pub fn new(handle: Handle<Mission>) -> Self {
+        Self {
+            handle,
+            run: None,
+            ended_at: None,
+        }
+    }
+}
+
+// spawned for an objective, gone when the mission ends
+#[derive(Component)]
+pub struct MissionEntity;
+
+#[derive(Event, Clone, Debug)]
+pub struct MissionStarted {
+    pub name: String,
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ObjectiveCompleted {
+    pub index: usize,
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct MissionCompleted {
+    pub name: String,
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct MissionFailed {
+    pub name: String,
+    pub reason: FailReason,
+}
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MissionSet;
+
+// runs the 'ActiveMission', if there is one, from the player's triggers, enemies dying, damage and time. runs in
+// 'MissionSet' after 'HealthSet' so this frame's deaths count, and before dead enemies are removed so their kind can
+// still be looked up. start one with 'mission start <name>' or by inserting an 'ActiveMission'
+#[derive(Default)]
+pub struct MissionPlugin {
+    pub config: MissionConfig,
+}
+
+impl Plugin for MissionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .init_asset::<Mission>()
+            .init_asset_loader::<MissionLoader>()
+            .add_event::<MissionStarted>()
+            .add_event::<ObjectiveCompleted>()
+            .add_event::<MissionCompleted>()
+            .add_event::<MissionFailed>()
+            .configure_sets(Update, MissionSet.after(HealthSet).before(remove_dead_enemies))
+            .add_systems(Update, (
+                start_mission,
+                track_mission,
+                (give_rewards, spawn_objective_enemies, clear_mission_entities),
+            ).chain().run_if(resource_exists::<ActiveMission>).in_set(MissionSet))
+            .add_console_command("mission", "", "show the current mission and objective", mission_command)
+            .add_console_command("mission start", "<name>", "play 'missions/<name>.mission.ron'", mission_start_command)
+            .add_console_command("mission abort", "", "fail the current mission", mission_abort_command)
+            .add_tunable::<MissionConfig>("mission");
+
+        if self.config.hud {
+            app.add_systems(Startup, setup_mission_hud)
+                .add_systems(Update, update_mission_hud.after(MissionSet));
+        }
+    }
+}
+
+pub fn start_mission(mut active: ResMut<ActiveMission>, missions: Res<Assets<Mission>>, mut started: EventWriter<MissionStarted>) {
+    if active.run.is_some() {
+        return;
+    }
+
+    if let Some(mission) = missions.get(&active.handle) {
+        active.run = Some(MissionRun::default());
+        started.send(MissionStarted { name: mission.name.clone() });
+        info!("Started mission '{}'", mission.name);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn track_mission(
+    mut active: ResMut<ActiveMission>,
+    missions: Res<Assets<Mission>>,
+    mut entered: EventReader<TriggerEntered>,
+    mut died: EventReader<Died>,
+    mut damage: EventReader<Damage>,
+    triggers: Query<&Trigger>,
+    players: Query<(), With<PlayerMotion>>,
+    enemies: Query<(&Enemy, &Senses)>,
+    time: Res<Time>,
+    mut completed_objectives: EventWriter<ObjectiveCompleted>,
+    mut completed: EventWriter<MissionCompleted>,
+    mut failed: EventWriter<MissionFailed>,
+) {
+    let active = &mut *active;
+    let (Some(mission), Some(run)) = (missions.get(&active.handle), active.run.as_mut()) else {
+        return;
+    };
+
+    let mut signals = vec![MissionSignal::Tick(time.delta_secs())];
+    signals.extend(entered.read().filter(|entered| players.contains(entered.entity)).filter_map(|entered| {
+        triggers.get(entered.trigger).ok().map(|trigger| MissionSignal::Entered(&trigger.name))
+    }));
+    signals.extend(damage.read().filter(|damage| players.contains(damage.entity)).map(|damage| MissionSignal::Damaged(damage.amount)));
+    for died in died.read() {
+        if players.contains(died.entity) {
+            signals.push(MissionSignal::PlayerDied);
+        } else if let Ok((enemy, _)) = enemies.get(died.entity) {
+            signals.push(MissionSignal::Defeated(&enemy.kind));
+        }
+    }
+    if enemies.iter().any(|(_, senses)| senses.sees_player) {
+        signals.push(MissionSignal::Spotted);
+    }
+
+    for signal in signals {
+        for update in run.update(mission, signal) {
+            match update {
+                MissionUpdate::ObjectiveCompleted(index) => {
+                    completed_objectives.send(ObjectiveCompleted { index });
+                }
+                MissionUpdate::Completed => {
+                    active.ended_at = Some(time.elapsed_secs());
+                    completed.send(MissionCompleted { name: mission.name.clone() });
+                    info!("Completed mission '{}' in {:.1}s", mission.name, run.time);
+                }
+                MissionUpdate::Failed(reason) => {
+                    active.ended_at = Some(time.elapsed_secs());
+                    info!("Failed mission '{}': {reason}", mission.name);
+                    failed.send(MissionFailed { name: mission.name.clone(), reason });
+                }
+            }
+        }
+    }
+}
+
+pub fn give_rewards(
+    active: Res<ActiveMission>,
+    missions: Res<Assets<Mission>>,
+    mut objectives: EventReader<ObjectiveCompleted>,
+    mut completed: EventReader<MissionCompleted>,
+    mut player: Option<Single<&mut Health, With<PlayerMotion>>>,
+    mut style: Option<ResMut<Style>>,
+) {
+    let Some(mission) = missions.get(&active.handle) else {
+        return;
+    };
+
+    let rewards = objectives.read().filter_map(|objective| mission.objectives.get(objective.index).map(|objective| &objective.reward))
+        .chain(completed.read().map(|_| &mission.reward));
+    for reward in rewards {
+        if let Some(style) = style.as_mut() {
+            style.0 += reward.style;
+        }
+        if let Some(health) = player.as_mut() {
+            health.current = (health.current + reward.health).min(health.max);
+        }
+    }
+}
+
+// the enemies an objective brings with it, when it starts
+pub fn spawn_objective_enemies(
+    active: Res<ActiveMission>,
+    missions: Res<Assets<Mission>>,
+    mut started: EventReader<MissionStarted>,
+    mut completed: EventReader<ObjectiveCompleted>,
+    mut commands: Commands,
+) {
+    let Some(mission) = missions.get(&active.handle) else {
+        return;
+    };
+
+    let starting = started.read().map(|_| 0).chain(completed.read().map(|objective| objective.index + 1));
+    for objective in starting.filter_map(|index| mission.objectives.get(index)) {
+        for enemy in &objective.spawn {
+            commands.spawn((
+                EnemySpawn { kind: enemy.kind.clone(), waypoints: enemy.waypoints.clone() },
+                Transform::from_translation(enemy.translation).with_rotation(enemy.rotation),
+                MissionEntity,
+            ));
+        }
+    }
+}
+
+pub fn clear_mission_entities(
+    mut completed: EventReader<MissionCompleted>,
+    mut failed: EventReader<MissionFailed>,
+    entities: Query<Entity, With<MissionEntity>>,
+    mut commands: Commands,
+) {
+    if completed.read().count() + failed.read().count() == 0 {
+        return;
+    }
+
+    for entity in &entities {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[derive(Component)]
+pub struct MissionHudText;
+
+pub fn setup_mission_hud(mut commands: Commands) {
+    commands.spawn((
+        Text::default(),
+        TextFont {
+            font_size: 18.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            left: Val::Px(5.0),
+            ..default()
+        },
+        Visibility::Hidden,
+        MissionHudText,
+    ));
+}
+
+// the current objective while a mission's running, then how it ended for a while
+pub fn update_mission_hud(
+    active: Option<Res<ActiveMission>>,
+    missions: Res<Assets<Mission>>,
+    config: Res<MissionConfig>,
+    time: Res<Time>,
+    text: Single<(&mut Text, &mut Visibility), With<MissionHudText>>,
+) {
+    let (mut text, mut visibility) = text.into_inner();
+    let shown = active.as_ref().and_then(|active| {
+        let mission = missions.get(&active.handle)?;
+        let run = active.run.as_ref()?;
+        active.ended_at.is_none_or(|at| time.elapsed_secs() - at < config.result_time).then_some((mission, run))
+    });
+
+    let Some((mission, run)) = shown else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Visible;
+
+    let limit = mission.time_limit.map(|limit| format!(" / {limit:.0}s")).unwrap_or_default();
+    let mut lines = vec![format!("{}  {:.1}s{limit}", mission.name, run.time)];
+    match (&run.outcome, mission.objectives.get(run.objective)) {
+        (Some(MissionOutcome::Completed), _) => lines.push("mission complete".to_string()),
+        (Some(MissionOutcome::Failed(reason)), _) => lines.push(format!("mission failed: {reason}")),
+        (None, Some(objective)) => {
+            let progress = run.progress(mission).map(|(done, of)| format!(" ({done}/{of})")).unwrap_or_default();
+            let remaining = objective.time_limit.map(|limit| format!("  {:.1}s left", (limit - run.objective_time).max(0.0))).unwrap_or_default();
+            let carrying = if run.carrying { "  carrying" } else { "" };
+            lines.push(format!("> {}{progress}{remaining}{carrying}", objective.description));
+        }
+        (None, None) => {}
+    }
+    text.0 = lines.join("\n");
+}
+
+// a bare name is looked for in 'assets/missions', anything with a '/' or an extension is an asset path
+pub fn mission_start_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let name = args.first().ok_or("missing mission name")?;
+    let path = if name.contains('/') || name.contains('.') { name.to_string() } else { format!("missions/{name}.mission.ron") };
+
+    let old: Vec<Entity> = world.query_filtered::<Entity, With<MissionEntity>>().iter(world).collect();
+    for entity in old {
+        world.entity_mut(entity).despawn_recursive();
+    }
+
+    let handle = world.resource::<AssetServer>().load(path.clone());
+    world.insert_resource(ActiveMission::new(handle));
+    Ok(format!("starting {path}"))
+}
+
+pub fn mission_abort_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let name = {
+        let active = world.get_resource::<ActiveMission>().ok_or("no mission")?;
+        world.resource::<Assets<Mission>>().get(&active.handle).map(|mission| mission.name.clone()).ok_or("the mission hasn't loaded")?
+    };
+
+    let now = world.resource::<Time>().elapsed_secs();
+    let mut active = world.resource_mut::<ActiveMission>();
+    let run = active.run.as_mut().filter(|run| run.outcome.is_none()).ok_or("the mission's over")?;
+    run.outcome = Some(MissionOutcome::Failed(FailReason::Aborted));
+    active.ended_at = Some(now);
+    world.send_event(MissionFailed { name: name.clone(), reason: FailReason::Aborted });
+    Ok(format!("aborted '{name}'"))
+}
+
+pub fn mission_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let active = world.get_resource::<ActiveMission>().ok_or("no mission")?;
+    let mission = world.resource::<Assets<Mission>>().get(&active.handle).ok_or("the mission hasn't loaded")?;
+    let Some(run) = &active.run else {
+        return Ok(format!("'{}' is starting", mission.name));
+    };
+
+    let state = match (&run.outcome, mission.objectives.get(run.objective)) {
+        (Some(MissionOutcome::Completed), _) => "complete".to_string(),
+        (Some(MissionOutcome::Failed(reason)), _) => format!("failed, {reason}"),
+        (None, Some(objective)) => format!("objective {} of {}: {}", run.objective + 1, mission.objectives.len(), objective.description),
+        (None, None) => "finishing".to_string(),
+    };
+    Ok(format!("'{}' {:.1}s, {state}", mission.name, run.time))
+}
+
+#[derive(Debug)]
+pub enum MissionError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for MissionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MissionError::Io(err) => write!(f, "io error: {err}"),
+            MissionError::Ron(err) => write!(f, "invalid mission file: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for MissionError {}
+
+#[derive(Default)]
+pub struct MissionLoader;
+
+impl AssetLoader for MissionLoader {
+    type Asset = Mission;
+    type Settings = ();
+    type Error = MissionError;
+
+    async fn load(&self, reader: &mut dyn Reader, _settings: &(), _load_context: &mut LoadContext<'_>) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await.map_err(MissionError::Io)?;
+        ron::de::from_bytes(&bytes).map_err(MissionError::Ron)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["mission.ron"]
+    }
+}
+
+#[test]
+fn test_mission_run() {
+    use MissionSignal::*;
+
+    let tutorial: Mission = ron::de::from_str(include_str!("../assets/missions/tutorial.mission.ron")).unwrap();
+    // one where dying doesn't end it, to see the package dropped
+    let mission = Mission { fail: Vec::new(), ..tutorial.clone() };
+    let mut run = MissionRun::default();
+    let mut play = |signals: &[MissionSignal]| signals.iter().flat_map(|&signal| run.update(&mission, signal)).collect::<Vec<_>>();
+
+    // rings only count in order
+    assert_eq!(play(&[Entered("ring_2"), Entered("ring_1"), Entered("ring_1"), Entered("ring_2")]), []);
+    assert_eq!(play(&[Tick(1.0), Entered("ring_3")]), [MissionUpdate::ObjectiveCompleted(0)]);
+
+    assert_eq!(play(&[Entered("mailbox"), Entered("rooftop")]), [MissionUpdate::ObjectiveCompleted(1)]);
+
+    // the package has to be picked up first, and is lost on dying
+    assert_eq!(play(&[Entered("mailbox"), Entered("package"), PlayerDied, Entered("mailbox")]), []);
+    assert_eq!(play(&[Entered("package"), Entered("mailbox")]), [MissionUpdate::ObjectiveCompleted(2)]);
+
+    assert_eq!(play(&[Defeated("brute"), Defeated("thug")]), []);
+    assert_eq!(play(&[Defeated("thug")]), [MissionUpdate::ObjectiveCompleted(3), MissionUpdate::Completed]);
+    assert_eq!(play(&[Entered("ring_1")]), []);
+
+    // out of time on an objective, or falling off the island
+    let mut run = MissionRun::default();
+    assert_eq!(run.update(&tutorial, Tick(tutorial.objectives[0].time_limit.unwrap() + 1.0)), [MissionUpdate::Failed(FailReason::ObjectiveTimeUp)]);
+    let mut run = MissionRun::default();
+    assert_eq!(run.update(&tutorial, Entered("kill_plane")), [MissionUpdate::Failed(FailReason::Entered("kill_plane".to_string()))]);
+}
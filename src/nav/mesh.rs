@@ -0,0 +1,463 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use super::NavConfig;
+
+const WELD: f32 = 1e-3;
+const MAX_POLY_VERTS: usize = 12;
+const CELL_SIZE: f32 = 4.0; // of the grid 'find_poly' looks polys up in
+
+// a convex polygon, wound anticlockwise seen from above
+#[derive(Clone, Debug, PartialEq)]
+pub struct NavPoly {
+    pub verts: Vec<u32>,
+    pub neighbours: Vec<Option<u32>>, // across the edge from 'verts[i]' to 'verts[i + 1]'
+    pub region: u32, // there's no path between polys in different regions
+}
+
+// where agents can walk on one collider. kept in world space as it was when built, 'to_world' maps it to where the
+// collider is now. that only works for colliders that turn about +y, which is all the island does.
+// it isn't shrunk to fit any one agent, 'find_path' keeps paths an agent's radius in from the edges instead
+#[derive(Component, Clone, Debug, Default)]
+pub struct NavMesh {
+    pub vertices: Vec<Vec3>,
+    pub polys: Vec<NavPoly>,
+    pub outline: Vec<bool>, // for each vertex, whether it's on an edge with nothing walkable across it
+    pub built_at: Mat4, // the collider's transform at the time
+    cells: HashMap<IVec2, Vec<u32>>, // every poly in every grid cell its xz bounds touch
+}
+
+fn cell(p: Vec3) -> IVec2 {
+    (p.xz() / CELL_SIZE).floor().as_ivec2()
+}
+
+// > 0 when 'b' is to the left of 'a', seen from above
+fn side(a: Vec3, b: Vec3) -> f32 {
+    a.z * b.x - a.x * b.z
+}
+
+// twice the area along the normal
+fn area_normal(points: impl Iterator<Item = Vec3>) -> Vec3 {
+    let points: Vec<Vec3> = points.collect();
+    (1..points.len().saturating_sub(1)).map(|i| (points[i] - points[0]).cross(points[i + 1] - points[0])).sum()
+}
+
+fn is_convex(points: &[Vec3]) -> bool {
+    (0..points.len()).all(|i| {
+        let (a, b, c) = (points[i], points[(i + 1) % points.len()], points[(i + 2) % points.len()]);
+        side(b - a, c - b) >= -1e-6
+    })
+}
+
+// for every edge of every poly, the poly on the other side
+fn adjacency(polys: &[Vec<u32>]) -> Vec<Vec<Option<u32>>> {
+    let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+    for (i, poly) in polys.iter().enumerate() {
+        for e in 0..poly.len() {
+            edges.insert((poly[e], poly[(e + 1) % poly.len()]), i as u32);
+        }
+    }
+
+    polys.iter().map(|poly| {
+        (0..poly.len()).map(|e| edges.get(&(poly[(e + 1) % poly.len()], poly[e])).copied()).collect()
+    }).collect()
+}
+
+// connected polys share a region
+fn regions(neighbours: &[Vec<Option<u32>>]) -> Vec<u32> {
+    let mut region = vec![u32::MAX; neighbours.len()];
+    let mut next = 0;
+
+    for start in 0..neighbours.len() {
+        if region[start] != u32::MAX {
+            continue;
+        }
+
+        let mut stack = vec![start];
+        region[start] = next;
+        while let Some(poly) = stack.pop() {
+            for &neighbour in neighbours[poly].iter().flatten() {
+                if region[neighbour as usize] == u32::MAX {
+                    region[neighbour as usize] = next;
+                    stack.push(neighbour as usize);
+                }
+            }
+        }
+        next += 1;
+    }
+
+    region
+}
+
+// 'a' and 'b' joined across the edge that starts at 'a[edge]', if the result's still a convex polygon
+fn merge(vertices: &[Vec3], a: &[u32], edge: usize, b: &[u32]) -> Option<Vec<u32>> {
+    let (u, v) = (a[edge], a[(edge + 1) % a.len()]);
+    let b_edge = (0..b.len()).find(|&e| b[e] == v && b[(e + 1) % b.len()] == u)?;
+    if a.len() + b.len() - 2 > MAX_POLY_VERTS {
+        return None;
+    }
+
+    // round 'a' from 'v' to 'u', then the rest of 'b'
+    let merged: Vec<u32> = (1..=a.len()).map(|k| a[(edge + k) % a.len()])
+        .chain((2..b.len()).map(|k| b[(b_edge + k) % b.len()]))
+        .collect();
+
+    let points: Vec<Vec3> = merged.iter().map(|&i| vertices[i as usize]).collect();
+    is_convex(&points).then_some(merged)
+}
+
+// the walkable part of 'triangles' (in world space) as convex polygons. steeper than 'max_slope' isn't walkable, and
+// neither is anything where 'blocked' says there's no headroom over a triangle's centre
+pub fn build_nav_mesh(triangles: &[Triangle3d], config: &NavConfig, blocked: impl Fn(Vec3) -> bool) -> NavMesh {
+    let min_up = config.max_slope.to_radians().cos();
+
+    let mut ids: HashMap<IVec3, u32> = HashMap::new();
+    let mut vertices: Vec<Vec3> = Vec::new();
+    let mut weld = |p: Vec3| *ids.entry((p / WELD).round().as_ivec3()).or_insert_with(|| {
+        vertices.push(p);
+        vertices.len() as u32 - 1
+    });
+
+    let mut polys: Vec<Vec<u32>> = Vec::new();
+    for t in triangles {
+        let [a, b, c] = t.vertices;
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+        if normal.y < min_up || blocked((a + b + c) / 3.0) {
+            continue;
+        }
+
+        let face = vec![weld(a), weld(b), weld(c)];
+        if face[0] != face[1] && face[1] != face[2] && face[2] != face[0] {
+            polys.push(face);
+        }
+    }
+
+    // merge across shared edges while the result stays convex and flat, longest edges first
+    let plane = |poly: &[u32]| {
+        let normal = area_normal(poly.iter().map(|&i| vertices[i as usize])).normalize_or_zero();
+        (normal, normal.dot(vertices[poly[0] as usize]))
+    };
+    loop {
+        let neighbours = adjacency(&polys);
+        let mut used = vec![false; polys.len()];
+        let mut merged_polys = Vec::new();
+
+        for i in 0..polys.len() {
+            if used[i] {
+                continue;
+            }
+
+            let (normal, distance) = plane(&polys[i]);
+            let best = neighbours[i].iter().enumerate().filter_map(|(edge, &j)| {
+                let j = j? as usize;
+                let (other_normal, other_distance) = plane(&polys[j]);
+                if used[j] || j == i || normal.dot(other_normal) < 0.999 || (distance - other_distance).abs() > WELD {
+                    return None;
+                }
+
+                let length = vertices[polys[i][edge] as usize].distance(vertices[polys[i][(edge + 1) % polys[i].len()] as usize]);
+                Some((j, merge(&vertices, &polys[i], edge, &polys[j])?, length))
+            }).max_by(|a, b| a.2.total_cmp(&b.2));
+
+            if let Some((j, merged, _)) = best {
+                used[i] = true;
+                used[j] = true;
+                merged_polys.push(merged);
+            }
+        }
+
+        if merged_polys.is_empty() {
+            break;
+        }
+        merged_polys.extend(polys.into_iter().enumerate().filter(|(i, _)| !used[*i]).map(|(_, poly)| poly));
+        polys = merged_polys;
+    }
+
+    // drop regions too small to be worth walking on, e.g. the tops of props
+    let region = regions(&adjacency(&polys));
+    let mut areas: HashMap<u32, f32> = HashMap::new();
+    for (poly, region) in polys.iter().zip(&region) {
+        *areas.entry(*region).or_default() += area_normal(poly.iter().map(|&i| vertices[i as usize])).length() * 0.5;
+    }
+    let polys: Vec<Vec<u32>> = polys.into_iter().zip(region).filter(|(_, region)| areas[region] >= config.min_region_area).map(|(poly, _)| poly).collect();
+
+    let neighbours = adjacency(&polys);
+    let region = regions(&neighbours);
+    let mut outline = vec![false; vertices.len()];
+    for (poly, neighbours) in polys.iter().zip(&neighbours) {
+        for edge in (0..poly.len()).filter(|&edge| neighbours[edge].is_none()) {
+            outline[poly[edge] as usize] = true;
+            outline[poly[(edge + 1) % poly.len()] as usize] = true;
+        }
+    }
+
+    let mut cells: HashMap<IVec2, Vec<u32>> = HashMap::new();
+    for (i, poly) in polys.iter().enumerate() {
+        let (min, max) = poly.iter().fold((Vec3::MAX, Vec3::MIN), |(min, max), &v| (min.min(vertices[v as usize]), max.max(vertices[v as usize])));
+        let (min, max) = (cell(min), cell(max));
+        for x in min.x..=max.x {
+            for z in min.y..=max.y {
+                cells.entry(IVec2::new(x, z)).or_default().push(i as u32);
+            }
+        }
+    }
+
+    NavMesh {
+        vertices,
+        polys: polys.into_iter().zip(neighbours).zip(region).map(|((verts, neighbours), region)| NavPoly { verts, neighbours, region }).collect(),
+        outline,
+        built_at: Mat4::IDENTITY,
+        cells,
+    }
+}
+
+#[derive(PartialEq)]
+struct Open {
+    cost: f32,
+    poly: usize,
+}
+
+impl Eq for Open {}
+
+// cheapest first out of a 'BinaryHeap'
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl NavMesh {
+    // maps the navmesh to where its collider is now
+    pub fn to_world(&self, collider: &GlobalTransform) -> Mat4 {
+        collider.compute_matrix() * self.built_at.inverse()
+    }
+
+    fn vertex(&self, poly: usize, i: usize) -> Vec3 {
+        let verts = &self.polys[poly].verts;
+        self.vertices[verts[i % verts.len()] as usize]
+    }
+
+    pub fn poly_points(&self, poly: usize) -> impl Iterator<Item = Vec3> + '_ {
+        self.polys[poly].verts.iter().map(|&i| self.vertices[i as usize])
+    }
+
+    fn contains_xz(&self, poly: usize, p: Vec3) -> bool {
+        (0..self.polys[poly].verts.len()).all(|i| {
+            let (a, b) = (self.vertex(poly, i), self.vertex(poly, i + 1));
+            side(b - a, p - a) >= -1e-5
+        })
+    }
+
+    // the height of the poly's plane straight above or below 'p'
+    pub fn height_at(&self, poly: usize, p: Vec3) -> f32 {
+        let normal = area_normal(self.poly_points(poly));
+        let origin = self.vertex(poly, 0);
+        origin.y - (normal.x * (p.x - origin.x) + normal.z * (p.z - origin.z)) / normal.y
+    }
+
+    // the highest poly from 'above' over 'p' to 'below' under it
+    pub fn find_poly(&self, p: Vec3, above: f32, below: f32) -> Option<usize> {
+        let polys = self.cells.get(&cell(p)).into_iter().flatten().map(|&poly| poly as usize);
+        polys.filter(|&poly| self.contains_xz(poly, p)).filter_map(|poly| {
+            let height = self.height_at(poly, p);
+            (height <= p.y + above && height >= p.y - below).then_some((poly, height))
+        }).max_by(|a, b| a.1.total_cmp(&b.1)).map(|(poly, _)| poly)
+    }
+
+    // 'p' dropped onto the navmesh, see 'find_poly'
+    pub fn project(&self, p: Vec3, above: f32, below: f32) -> Option<Vec3> {
+        let poly = self.find_poly(p, above, below)?;
+        Some(Vec3::new(p.x, self.height_at(poly, p), p.z))
+    }
+
+    // polys from 'from' to 'to', by A* between edge midpoints
+    fn corridor(&self, from: usize, to: usize, start: Vec3, end: Vec3) -> Option<Vec<usize>> {
+        if self.polys[from].region != self.polys[to].region {
+            return None;
+        }
+
+        let mut cost = vec![f32::INFINITY; self.polys.len()];
+        let mut entry = vec![start; self.polys.len()];
+        let mut came_from = vec![usize::MAX; self.polys.len()];
+        let mut closed = vec![false; self.polys.len()];
+        let mut open = BinaryHeap::from([Open { cost: start.distance(end), poly: from }]);
+        cost[from] = 0.0;
+
+        while let Some(Open { poly, .. }) = open.pop() {
+            if poly == to {
+                let mut corridor = vec![to];
+                while *corridor.last().unwrap() != from {
+                    corridor.push(came_from[*corridor.last().unwrap()]);
+                }
+                corridor.reverse();
+                return Some(corridor);
+            }
+
+            if closed[poly] {
+                continue;
+            }
+            closed[poly] = true;
+
+            for (edge, &neighbour) in self.polys[poly].neighbours.iter().enumerate() {
+                let Some(neighbour) = neighbour.map(|n| n as usize).filter(|&n| !closed[n]) else {
+                    continue;
+                };
+
+                let mid = (self.vertex(poly, edge) + self.vertex(poly, edge + 1)) * 0.5;
+                let through = cost[poly] + entry[poly].distance(mid);
+                if through < cost[neighbour] {
+                    cost[neighbour] = through;
+                    entry[neighbour] = mid;
+                    came_from[neighbour] = poly;
+                    open.push(Open { cost: through + mid.distance(end), poly: neighbour });
+                }
+            }
+        }
+
+        None
+    }
+
+    // the corners of the shortest way from 'start' to 'end' through the navmesh, ending with 'end' dropped onto it.
+    // both are looked for from 'above' over to 'below' under the navmesh. corners at the navmesh's edge are moved
+    // 'radius' in from it, along the edge between polys the path crosses there
+    pub fn find_path(&self, start: Vec3, end: Vec3, above: f32, below: f32, radius: f32) -> Option<Vec<Vec3>> {
+        let from = self.find_poly(start, above, below)?;
+        let to = self.find_poly(end, above, below)?;
+        let start = Vec3::new(start.x, self.height_at(from, start), start.z);
+        let end = Vec3::new(end.x, self.height_at(to, end), end.z);
+
+        let corridor = self.corridor(from, to, start, end)?;
+        let portals: Vec<(Vec3, Vec3)> = corridor.windows(2).map(|pair| {
+            let edge = self.polys[pair[0]].neighbours.iter().position(|&n| n == Some(pair[1] as u32)).unwrap();
+            let verts = &self.polys[pair[0]].verts;
+            // going out across an edge of an anticlockwise poly, its end is on the left
+            let (left, right) = (verts[(edge + 1) % verts.len()] as usize, verts[edge] as usize);
+            let (mut l, mut r) = (self.vertices[left], self.vertices[right]);
+
+            // no wider than the portal allows, one too narrow for the agent is walked through the middle
+            let inset = (r - l).normalize_or_zero() * radius.min(l.distance(r) * 0.5);
+            if self.outline[left] {
+                l += inset;
+            }
+            if self.outline[right] {
+                r -= inset;
+            }
+            (l, r)
+        }).collect();
+
+        Some(string_pull(start, end, &portals))
+    }
+}
+
+// the simple stupid funnel algorithm. 'portals' are the (left, right) edges crossed between 'start' and 'end'
+pub fn string_pull(start: Vec3, end: Vec3, portals: &[(Vec3, Vec3)]) -> Vec<Vec3> {
+    let portals: Vec<(Vec3, Vec3)> = std::iter::once((start, start)).chain(portals.iter().copied()).chain(std::iter::once((end, end))).collect();
+    let mut path = Vec::new();
+    let (mut apex, mut left, mut right) = (start, start, start);
+    let (mut left_i, mut right_i) = (0, 0);
+
+    let mut i = 1;
+    while i < portals.len() {
+        let (l, r) = portals[i];
+
+        // the right side closes in unless it crosses the left, which is then a corner
+        if side(right - apex, r - apex) >= 0.0 {
+            if apex == right || side(left - apex, r - apex) < 0.0 {
+                (right, right_i) = (r, i);
+            } else {
+                path.push(left);
+                apex = left;
+                (right, right_i) = (apex, left_i);
+                i = left_i + 1;
+                continue;
+            }
+        }
+
+        if side(left - apex, l - apex) <= 0.0 {
+            if apex == left || side(right - apex, l - apex) > 0.0 {
+                (left, left_i) = (l, i);
+            } else {
+                path.push(right);
+                apex = right;
+                (left, left_i) = (apex, right_i);
+                i = right_i + 1;
+                continue;
+            }
+        }
+
+        i += 1;
+    }
+
+    if path.last() != Some(&end) {
+        path.push(end);
+    }
+    path
+}
+
+#[test]
+fn test_nav_mesh() {
+    // a 3x3 grid of unit squares without the middle one, and a steep triangle that shouldn't be walkable
+    let mut triangles = Vec::new();
+    for (i, j) in (0..3).flat_map(|i| (0..3).map(move |j| (i as f32, j as f32))).filter(|&(i, j)| (i, j) != (1.0, 1.0)) {
+        let (p00, p01, p10, p11) = (Vec3::new(i, 0.0, j), Vec3::new(i, 0.0, j + 1.0), Vec3::new(i + 1.0, 0.0, j), Vec3::new(i + 1.0, 0.0, j + 1.0));
+        triangles.push(Triangle3d::new(p00, p01, p11));
+        triangles.push(Triangle3d::new(p00, p11, p10));
+    }
+    triangles.push(Triangle3d::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, 0.0, 3.0)));
+
+    // and no headroom in one corner
+    let config = NavConfig { min_region_area: 0.5, ..default() };
+    let mesh = build_nav_mesh(&triangles, &config, |p| p.x < 1.0 && p.z > 2.0);
+
+    assert!(mesh.polys.len() < 14, "{}", mesh.polys.len());
+    for poly in 0..mesh.polys.len() {
+        assert!(is_convex(&mesh.poly_points(poly).collect::<Vec<_>>()));
+        assert!(area_normal(mesh.poly_points(poly)).y > 0.0);
+    }
+    assert_eq!(mesh.find_poly(Vec3::new(1.5, 0.0, 1.5), 0.5, 0.5), None);
+    assert_eq!(mesh.find_poly(Vec3::new(0.5, 0.0, 2.5), 0.5, 0.5), None);
+    assert!(mesh.find_poly(Vec3::new(0.5, 1.0, 0.5), 0.5, 2.0).is_some());
+
+    // straight along a row, then round the hole on the side with headroom
+    let path = mesh.find_path(Vec3::new(0.5, 0.0, 0.5), Vec3::new(2.5, 0.0, 0.5), 0.5, 0.5, 0.0).unwrap();
+    assert_eq!(path, [Vec3::new(2.5, 0.0, 0.5)]);
+
+    let path = mesh.find_path(Vec3::new(0.2, 0.0, 1.5), Vec3::new(2.8, 0.0, 1.5), 0.5, 0.5, 0.0).unwrap();
+    assert_eq!(path, [Vec3::new(1.0, 0.0, 1.0), Vec3::new(2.0, 0.0, 1.0), Vec3::new(2.8, 0.0, 1.5)]);
+
+    // a wider agent keeps its radius off the corners of the hole
+    let path = mesh.find_path(Vec3::new(0.2, 0.0, 1.5), Vec3::new(2.8, 0.0, 1.5), 0.5, 0.5, 0.2).unwrap();
+    assert_eq!(path.len(), 3);
+    for (corner, hole) in path.iter().zip([Vec3::new(1.0, 0.0, 1.0), Vec3::new(2.0, 0.0, 1.0)]) {
+        assert!(mesh.find_poly(*corner, 0.5, 0.5).is_some() && (corner.distance(hole) - 0.2).abs() < 1e-4, "{corner}");
+    }
+}
+
+#[test]
+fn test_find_poly_grid() {
+    // ramps and holes over many grid cells, some off the grid's origin
+    let mut triangles = Vec::new();
+    for (i, j) in (-10..15).flat_map(|i| (-10..15).map(move |j| (i as f32, j as f32))).filter(|&(i, j)| (i + j) % 7.0 != 0.0) {
+        let height = |x: f32, z: f32| (x * 0.3).sin() + z * 0.1;
+        let p = |x: f32, z: f32| Vec3::new(x, height(x, z), z);
+        triangles.push(Triangle3d::new(p(i, j), p(i, j + 1.0), p(i + 1.0, j + 1.0)));
+        triangles.push(Triangle3d::new(p(i, j), p(i + 1.0, j + 1.0), p(i + 1.0, j)));
+    }
+    let mesh = build_nav_mesh(&triangles, &NavConfig { min_region_area: 0.5, ..default() }, |_| false);
+
+    // the same as looking through every poly
+    for (x, z) in (0..120).flat_map(|i| (0..120).map(move |j| (i as f32 * 0.23 - 11.0, j as f32 * 0.23 - 11.0))) {
+        let p = Vec3::new(x, (x * 0.3).sin() + z * 0.1, z);
+        let every = (0..mesh.polys.len()).filter(|&poly| mesh.contains_xz(poly, p)).map(|poly| mesh.height_at(poly, p))
+            .filter(|height| (height - p.y).abs() <= 0.5).max_by(f32::total_cmp);
+        assert_eq!(mesh.find_poly(p, 0.5, 0.5).map(|poly| mesh.height_at(poly, p)), every, "{p}");
+    }
+}
@@ -0,0 +1,288 @@
+use bevy::color::palettes::css::{AQUA, GREEN};
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use std::time::Instant;
+
+pub mod mesh;
+
+use mesh::{build_nav_mesh, NavMesh};
+use crate::console::ConsoleAppExt;
+use crate::math;
+use crate::physics::collision::RecursiveAABB;
+use crate::physics::debug::ColliderDebug;
+use crate::physics::layers::{CollisionLayers, CollisionWorld, QueryFilter};
+use crate::tuning::TuningAppExt;
+
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct NavConfig {
+    pub max_slope: f32, // degrees
+    pub agent_height: f32, // headroom needed over walkable ground
+    pub min_region_area: f32, // smaller patches of navmesh are dropped
+    pub step_height: f32, // agents keep to the navmesh this far above or below it
+    pub target_drop: f32, // targets, e.g. the player's eye, are dropped onto the navmesh from up to this high
+    pub repath_distance: f32, // how far a target moves before the path to it's found again
+    pub retry_interval: f32, // seconds before looking again for a path to a target there wasn't one to, e.g. in the air
+    pub arrive_distance: f32, // close enough to a corner to head for the next
+    pub avoidance_distance: f32, // agents closer than their radii plus this steer apart
+    pub avoidance_weight: f32,
+    #[reflect(ignore)]
+    pub draw: bool, // show navmeshes and paths with the collider overlay, needs the renderer
+}
+
+impl Default for NavConfig {
+    fn default() -> Self {
+        Self {
+            max_slope: 40.0,
+            agent_height: 1.8,
+            min_region_area: 4.0,
+            step_height: 0.5,
+            target_drop: 3.0,
+            repath_distance: 1.0,
+            retry_interval: 0.5,
+            arrive_distance: 0.3,
+            avoidance_distance: 1.0,
+            avoidance_weight: 1.0,
+            draw: true,
+        }
+    }
+}
+
+impl NavConfig {
+    pub fn headless() -> Self {
+        Self {
+            draw: false,
+            ..default()
+        }
+    }
+}
+
+// walks along the navmesh to 'target', round other agents. leaves 'Transform::translation' on the navmesh
+#[derive(Component, Debug, Default)]
+pub struct NavAgent {
+    pub target: Option<Vec3>,
+    pub speed: f32,
+    pub radius: f32,
+    pub velocity: Vec3,
+    mesh: Option<Entity>, // the collider whose 'NavMesh' it's on
+    built_at: Mat4, // that navmesh's, so a rebuild doesn't leave 'local' in the wrong place
+    local: Vec3, // where it is on that navmesh, so it moves along with it
+    path: Vec<Vec3>, // corners left to go, on the navmesh
+    planned_for: Option<Vec3>,
+    retry_in: f32, // seconds, after there was no path
+}
+
+impl NavAgent {
+    pub fn new(speed: f32, radius: f32) -> Self {
+        Self {
+            speed,
+            radius,
+            ..default()
+        }
+    }
+
+    // what's left of the path, on the navmesh of 'mesh()'
+    pub fn path(&self) -> &[Vec3] {
+        &self.path
+    }
+
+    pub fn mesh(&self) -> Option<Entity> {
+        self.mesh
+    }
+}
+
+// set to have every navmesh built again at the end of the frame
+#[derive(Resource, Default)]
+pub struct NavMeshDirty(pub bool);
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NavSet;
+
+// a 'NavMesh' for every collider in the world that can be walked on, rebuilt whenever one's added or removed (so once
+// a level's loaded), and 'NavAgent's that walk them. agents move in 'NavSet', set them a target before it
+#[derive(Default)]
+pub struct NavPlugin {
+    pub config: NavConfig,
+}
+
+impl Plugin for NavPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .init_resource::<NavMeshDirty>()
+            .add_systems(Update, follow_paths.in_set(NavSet))
+            .add_systems(PostUpdate, (detect_collider_changes, rebuild_nav_meshes).chain().after(TransformSystem::TransformPropagate))
+            .add_console_command("nav rebuild", "", "build every navmesh again", nav_rebuild_command)
+            .add_tunable::<NavConfig>("nav");
+
+        if self.config.draw {
+            app.add_systems(Update, draw_nav_meshes.after(NavSet).run_if(|debug: Res<ColliderDebug>| debug.enabled));
+        }
+    }
+}
+
+pub fn detect_collider_changes(
+    added: Query<(Entity, Option<&CollisionLayers>), Added<RecursiveAABB>>,
+    mut removed: RemovedComponents<NavMesh>,
+    mut dirty: ResMut<NavMeshDirty>,
+) {
+    let filter = QueryFilter::navigation();
+    if added.iter().any(|(entity, layers)| filter.test(entity, layers.copied().unwrap_or_default().0)) || removed.read().count() > 0 {
+        dirty.0 = true;
+    }
+}
+
+pub fn rebuild_nav_meshes(world: CollisionWorld, config: Res<NavConfig>, mut dirty: ResMut<NavMeshDirty>, mut commands: Commands) {
+    if !dirty.0 {
+        return;
+    }
+    dirty.0 = false;
+
+    let start = Instant::now();
+    let filter = QueryFilter::navigation();
+    let (mut meshes, mut polys) = (0, 0);
+
+    for (entity, _, triangles, transform) in world.iter(&filter) {
+        let matrix = transform.compute_matrix();
+        let triangles: Vec<Triangle3d> = triangles.0.iter().map(|t| {
+            Triangle3d::new(matrix.transform_point3(t.vertices[0]), matrix.transform_point3(t.vertices[1]), matrix.transform_point3(t.vertices[2]))
+        }).collect();
+
+        // anything solid right above the surface takes away the headroom
+        let blocked = |p: Vec3| {
+            let from = p + Vec3::Y * 0.05;
+            world.cast_ray_closest(math::Ray3d::new(from, Vec3::Y), &filter).is_some_and(|hit| hit.distance < config.agent_height)
+        };
+        let mut mesh = build_nav_mesh(&triangles, &config, blocked);
+        mesh.built_at = matrix;
+
+        meshes += !mesh.polys.is_empty() as usize;
+        polys += mesh.polys.len();
+        commands.entity(entity).insert(mesh);
+    }
+
+    info!("Built {meshes} navmeshes with {polys} polys in {:?}", start.elapsed());
+}
+
+// the navmesh under 'pos' and 'pos' on it
+fn locate(meshes: &Query<(Entity, &NavMesh, &GlobalTransform)>, pos: Vec3, config: &NavConfig) -> Option<(Entity, Vec3)> {
+    meshes.iter().find_map(|(entity, mesh, transform)| {
+        let local = mesh.to_world(transform).inverse().transform_point3(pos);
+        Some((entity, mesh.project(local, config.step_height, config.step_height)?))
+    })
+}
+
+pub fn follow_paths(
+    mut agents: Query<(Entity, &mut NavAgent, &mut Transform)>,
+    meshes: Query<(Entity, &NavMesh, &GlobalTransform)>,
+    config: Res<NavConfig>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_secs();
+    let others: Vec<(Entity, Vec3, f32)> = agents.iter().map(|(entity, agent, transform)| (entity, transform.translation, agent.radius)).collect();
+
+    for (entity, mut agent, mut transform) in &mut agents {
+        let agent = &mut *agent;
+
+        // carried along by whatever it's standing on
+        let on = agent.mesh.and_then(|mesh| meshes.get(mesh).ok()).filter(|(_, mesh, _)| mesh.built_at == agent.built_at);
+        if let Some((_, mesh, mesh_transform)) = on {
+            transform.translation = mesh.to_world(mesh_transform).transform_point3(agent.local);
+        }
+
+        let Some((mesh_entity, local)) = on.and_then(|(entity, mesh, _)| {
+            mesh.find_poly(agent.local, config.step_height, config.step_height).map(|_| (entity, agent.local))
+        }).or_else(|| locate(&meshes, transform.translation, &config)) else {
+            agent.mesh = None;
+            agent.velocity = Vec3::ZERO;
+            continue;
+        };
+        let (_, mesh, mesh_transform) = meshes.get(mesh_entity).unwrap();
+        if agent.mesh != Some(mesh_entity) || agent.built_at != mesh.built_at {
+            agent.path.clear();
+            agent.planned_for = None;
+        }
+        agent.mesh = Some(mesh_entity);
+        agent.built_at = mesh.built_at;
+        agent.local = local;
+        let to_world = mesh.to_world(mesh_transform);
+        let to_mesh = to_world.inverse();
+
+        // plan again when the target's moved far enough. there's no path to somewhere off the navmesh, but the target
+        // can come back onto it without moving far, so keep trying every so often
+        agent.retry_in = (agent.retry_in - dt).max(0.0);
+        match agent.target {
+            Some(target) if agent.retry_in == 0.0 && agent.planned_for.is_none_or(|planned| planned.distance(target) > config.repath_distance) => {
+                let target_local = to_mesh.transform_point3(target);
+                let path = mesh.find_path(local, target_local, config.step_height, config.target_drop, agent.radius);
+                agent.planned_for = path.is_some().then_some(target);
+                agent.retry_in = if path.is_some() { 0.0 } else { config.retry_interval };
+                agent.path = path.unwrap_or_default();
+            }
+            None => {
+                agent.path.clear();
+                agent.planned_for = None;
+            }
+            _ => {}
+        }
+
+        while agent.path.first().is_some_and(|corner| corner.xz().distance(local.xz()) < config.arrive_distance) {
+            agent.path.remove(0);
+        }
+
+        let mut desired = agent.path.first().map_or(Vec3::ZERO, |&corner| {
+            let towards = to_world.transform_point3(corner) - transform.translation;
+            Vec3::new(towards.x, 0.0, towards.z).normalize_or_zero() * agent.speed
+        });
+
+        // pushed apart by anyone too close, harder the closer they are
+        for &(other, pos, radius) in &others {
+            let away = transform.translation - pos;
+            let away = Vec3::new(away.x, 0.0, away.z);
+            let range = agent.radius + radius + config.avoidance_distance;
+            if other != entity && away.length() < range && !desired.abs_diff_eq(Vec3::ZERO, 1e-6) {
+                desired += away.normalize_or_zero() * (1.0 - away.length() / range) * config.avoidance_weight * agent.speed;
+            }
+        }
+        agent.velocity = desired.clamp_length_max(agent.speed);
+
+        // only ever onto somewhere else on the navmesh
+        let next = to_mesh.transform_point3(transform.translation + agent.velocity * dt);
+        if let Some(next) = mesh.project(next, config.step_height, config.step_height) {
+            agent.local = next;
+            transform.translation = to_world.transform_point3(next);
+        } else {
+            agent.velocity = Vec3::ZERO;
+        }
+    }
+}
+
+pub fn draw_nav_meshes(
+    meshes: Query<(&NavMesh, &GlobalTransform)>,
+    agents: Query<(&NavAgent, &Transform)>,
+    mut gizmos: Gizmos,
+) {
+    // just over the surface so it isn't hidden in it
+    let lift = Vec3::Y * 0.05;
+
+    for (mesh, transform) in &meshes {
+        let to_world = mesh.to_world(transform);
+        for poly in 0..mesh.polys.len() {
+            let points: Vec<Vec3> = mesh.poly_points(poly).map(|p| to_world.transform_point3(p) + lift).collect();
+            gizmos.linestrip(points.iter().copied().chain(points.first().copied()), GREEN);
+        }
+    }
+
+    for (agent, transform) in &agents {
+        let Some((mesh, mesh_transform)) = agent.mesh.and_then(|mesh| meshes.get(mesh).ok()) else {
+            continue;
+        };
+
+        let to_world = mesh.to_world(mesh_transform);
+        let path = agent.path.iter().map(|&p| to_world.transform_point3(p) + lift);
+        gizmos.linestrip(std::iter::once(transform.translation + lift).chain(path), AQUA);
+    }
+}
+
+pub fn nav_rebuild_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    world.resource_mut::<NavMeshDirty>().0 = true;
+    Ok("rebuilding navmeshes".to_string())
+}
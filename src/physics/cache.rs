@@ -0,0 +1,285 @@
+use bevy::asset::io::file::FileAssetReader;
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::collision::{RecursiveAABB, AABB, TRIANGLE_LIMIT};
+
+// baked collision data for one mesh, stored at 'assets/colliders/<mesh hash>.collider'
+//
+// layout (little endian):
+// magic "SPCT" | version u32 | triangle limit u32 | mesh hash u64 | triangle count u32 | triangles (9 x f32 each) | tree
+// tree nodes are written depth first: min (3 x f32) | max (3 x f32) | enclosed count u32 | enclosed (u32 each) | child count u8 | children
+#[derive(Asset, TypePath, Debug)]
+pub struct CollisionTreeCache {
+    pub mesh_hash: u64,
+    pub tree: RecursiveAABB,
+    pub triangles: Vec<Triangle3d>,
+}
+
+const MAGIC: [u8; 4] = *b"SPCT";
+// bump whenever the layout above or the way trees are built changes
+pub const CACHE_VERSION: u32 = 2;
+pub const CACHE_DIR: &str = "colliders";
+pub const CACHE_EXTENSION: &str = "collider";
+
+#[derive(Debug)]
+pub enum CacheError {
+    Io(std::io::Error),
+    BadMagic,
+    Version(u32),
+    TriangleLimit(u32),
+    UnexpectedEof,
+    BadIndex(u32),
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CacheError::Io(err) => write!(f, "io error: {err}"),
+            CacheError::BadMagic => write!(f, "not a collision cache"),
+            CacheError::Version(v) => write!(f, "cache version {v} does not match {CACHE_VERSION}"),
+            CacheError::TriangleLimit(l) => write!(f, "cache was built with a triangle limit of {l}, expected {TRIANGLE_LIMIT}"),
+            CacheError::UnexpectedEof => write!(f, "cache is truncated"),
+            CacheError::BadIndex(i) => write!(f, "triangle index {i} is out of bounds"),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+impl From<std::io::Error> for CacheError {
+    fn from(err: std::io::Error) -> Self {
+        CacheError::Io(err)
+    }
+}
+
+impl CollisionTreeCache {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(TRIANGLE_LIMIT as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.mesh_hash.to_le_bytes());
+
+        bytes.extend_from_slice(&(self.triangles.len() as u32).to_le_bytes());
+        for triangle in &self.triangles {
+            for v in triangle.vertices {
+                write_vec3(&mut bytes, v);
+            }
+        }
+
+        write_node(&mut bytes, &self.tree);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CacheError> {
+        let mut reader = ByteReader { bytes, pos: 0 };
+
+        if reader.take(4)? != MAGIC {
+            return Err(CacheError::BadMagic);
+        }
+
+        let version = reader.u32()?;
+        if version != CACHE_VERSION {
+            return Err(CacheError::Version(version));
+        }
+
+        let triangle_limit = reader.u32()?;
+        if triangle_limit as usize != TRIANGLE_LIMIT {
+            return Err(CacheError::TriangleLimit(triangle_limit));
+        }
+
+        let mesh_hash = reader.u64()?;
+
+        let count = reader.u32()?;
+        let mut triangles = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            triangles.push(Triangle3d::new(reader.vec3()?, reader.vec3()?, reader.vec3()?));
+        }
+
+        let tree = read_node(&mut reader, count)?;
+
+        Ok(Self {
+            mesh_hash,
+            tree,
+            triangles,
+        })
+    }
+}
+
+fn write_vec3(bytes: &mut Vec<u8>, v: Vec3) {
+    for c in v.to_array() {
+        bytes.extend_from_slice(&c.to_le_bytes());
+    }
+}
+
+fn write_node(bytes: &mut Vec<u8>, node: &RecursiveAABB) {
+    write_vec3(bytes, node.aabb().min);
+    write_vec3(bytes, node.aabb().max);
+
+    bytes.extend_from_slice(&(node.enclosed().len() as u32).to_le_bytes());
+    for index in node.enclosed() {
+        bytes.extend_from_slice(&(*index as u32).to_le_bytes());
+    }
+
+    let next = node.next().unwrap_or(&[]);
+    bytes.push(next.len() as u8);
+    for next in next {
+        write_node(bytes, next);
+    }
+}
+
+fn read_node(reader: &mut ByteReader, triangle_count: u32) -> Result<RecursiveAABB, CacheError> {
+    let aabb = AABB::new(reader.vec3()?, reader.vec3()?);
+
+    let count = reader.u32()?;
+    let mut enclosed = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let index = reader.u32()?;
+        if index >= triangle_count {
+            return Err(CacheError::BadIndex(index));
+        }
+        enclosed.push(index as usize);
+    }
+
+    let next = match reader.take(1)?[0] {
+        0 => None,
+        n => Some((0..n).map(|_| read_node(reader, triangle_count)).collect::<Result<Vec<_>, _>>()?),
+    };
+
+    Ok(RecursiveAABB::new(aabb, next, enclosed))
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl ByteReader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], CacheError> {
+        let slice = self.bytes.get(self.pos..self.pos + n).ok_or(CacheError::UnexpectedEof)?;
+        self.pos += n;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, CacheError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, CacheError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, CacheError> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, CacheError> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+}
+
+// FNV-1a over the raw vertex data. std's hasher isn't guaranteed to be stable between builds, which would defeat offline baking
+pub fn mesh_hash(triangles: &[Triangle3d]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for triangle in triangles {
+        for v in triangle.vertices {
+            for c in v.to_array() {
+                for byte in c.to_bits().to_le_bytes() {
+                    hash ^= byte as u64;
+                    hash = hash.wrapping_mul(0x100000001b3);
+                }
+            }
+        }
+    }
+
+    hash
+}
+
+// path relative to the asset folder, for 'AssetServer::load'
+pub fn cache_asset_path(hash: u64) -> String {
+    format!("{CACHE_DIR}/{hash:016x}.{CACHE_EXTENSION}")
+}
+
+pub fn cache_file_path(hash: u64) -> PathBuf {
+    default_assets_dir().join(cache_asset_path(hash))
+}
+
+// the folder the asset server reads from
+pub fn default_assets_dir() -> PathBuf {
+    FileAssetReader::get_base_path().join("assets")
+}
+
+// checked up front so a cache miss doesn't show up as an asset server error
+pub fn cache_exists(hash: u64) -> bool {
+    cache_file_path(hash).is_file()
+}
+
+pub fn write_cache(cache: &CollisionTreeCache) -> Result<(), CacheError> {
+    write_cache_in(cache, &default_assets_dir()).map(|_| ())
+}
+
+// writes into '<assets_dir>/colliders/', returns the path of the written file
+pub fn write_cache_in(cache: &CollisionTreeCache, assets_dir: &Path) -> Result<PathBuf, CacheError> {
+    let path = assets_dir.join(cache_asset_path(cache.mesh_hash));
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    std::fs::write(&path, cache.to_bytes())?;
+    Ok(path)
+}
+
+#[derive(Default)]
+pub struct CollisionTreeCacheLoader;
+
+impl AssetLoader for CollisionTreeCacheLoader {
+    type Asset = CollisionTreeCache;
+    type Settings = ();
+    type Error = CacheError;
+
+    async fn load(&self, reader: &mut dyn Reader, _settings: &(), _load_context: &mut LoadContext<'_>) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        CollisionTreeCache::from_bytes(&bytes)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &[CACHE_EXTENSION]
+    }
+}
+
+#[test]
+fn test_cache_round_trip() {
+    use super::collision::build_collision_tree;
+
+    // a small grid of quads, enough to force a few levels of subdivision
+    let mut triangles = Vec::new();
+    for x in 0..10 {
+        for z in 0..10 {
+            let (x, z) = (x as f32, z as f32);
+            triangles.push(Triangle3d::new(Vec3::new(x, 0.0, z), Vec3::new(x + 1.0, 0.0, z), Vec3::new(x, (x + z) * 0.1, z + 1.0)));
+            triangles.push(Triangle3d::new(Vec3::new(x + 1.0, 0.0, z), Vec3::new(x + 1.0, 0.0, z + 1.0), Vec3::new(x, (x + z) * 0.1, z + 1.0)));
+        }
+    }
+
+    let cache = CollisionTreeCache {
+        mesh_hash: mesh_hash(&triangles),
+        tree: build_collision_tree(&triangles, TRIANGLE_LIMIT),
+        triangles,
+    };
+
+    let bytes = cache.to_bytes();
+    let loaded = CollisionTreeCache::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.mesh_hash, cache.mesh_hash);
+    assert_eq!(loaded.triangles, cache.triangles);
+    assert_eq!(loaded.to_bytes(), bytes);
+
+    let mut outdated = bytes.clone();
+    outdated[4..8].copy_from_slice(&(CACHE_VERSION + 1).to_le_bytes());
+    assert!(matches!(CollisionTreeCache::from_bytes(&outdated), Err(CacheError::Version(_))));
+    assert!(matches!(CollisionTreeCache::from_bytes(&bytes[..bytes.len() - 1]), Err(CacheError::UnexpectedEof)));
+}
@@ -1,6 +1,14 @@
-use bevy::{prelude::*, pbr::wireframe::Wireframe};
+use bevy::{prelude::*, pbr::wireframe::Wireframe, asset::LoadState, gltf::{GltfExtras, GltfMaterialExtras, GltfMaterialName}, render::mesh::skinning::SkinnedMesh};
 use std::f32;
 
+use super::cache::{self, CollisionTreeCache};
+use super::debug::{ColliderDebug, RayTrace};
+use super::CollisionConfig;
+use super::dynamic::{DeformableCollider, DynamicCollidable, DynamicCollider};
+use super::layers::{CollisionLayers, Layers};
+use super::surfaces::{SurfaceMaterials, TriangleSurfaces};
+use super::triggers::{parse_trigger_extras, ExtrasShape, Trigger, TriggerShape};
+
 use crate::math::{self, plane_from_points, ray_plane_intersect, ray_3d_from_points};
 
 // Contains the GLTF mesh name for the collidable geometry
@@ -8,21 +16,64 @@ use crate::math::{self, plane_from_points, ray_plane_intersect, ray_3d_from_poin
 pub struct Collidable(pub Vec<String>);
 
 // splits a mesh up into 8 smaller bounding boxes recursively
-#[derive(Default, Component, Debug)]
+#[derive(Default, Component, Clone, Debug)]
 pub struct RecursiveAABB {
     aabb: AABB,
     next: Option<Vec<RecursiveAABB>>,
     enclosed: Vec<usize>, // list of indices into the triangle buffer
 }
  
+impl RecursiveAABB {
+    pub fn new(aabb: AABB, next: Option<Vec<RecursiveAABB>>, enclosed: Vec<usize>) -> Self {
+        Self {
+            aabb,
+            next,
+            enclosed,
+        }
+    }
+
+    pub fn aabb(&self) -> AABB {
+        self.aabb
+    }
+
+    pub fn next(&self) -> Option<&[RecursiveAABB]> {
+        self.next.as_deref()
+    }
+
+    pub fn enclosed(&self) -> &[usize] {
+        &self.enclosed
+    }
+
+    // recompute the bounds of every node from the triangles it holds after they moved, keeping the layout.
+    // 'triangles' must still be in the same order as the ones the tree was built from
+    pub fn refit(&mut self, triangles: &[Triangle3d]) {
+        self.refit_internal(triangles);
+    }
+
+    fn refit_internal(&mut self, triangles: &[Triangle3d]) -> Option<AABB> {
+        let bound = match &mut self.next {
+            Some(next) => next.iter_mut().filter_map(|next| next.refit_internal(triangles)).reduce(AABB::union),
+            None if self.enclosed.is_empty() => None, // nothing to hit, the old bounds are as good as any
+            None => Some(find_aabb(&self.enclosed.iter().map(|i| triangles[*i]).collect::<Vec<_>>())),
+        };
+
+        if let Some(bound) = bound {
+            self.aabb = bound;
+        }
+
+        bound
+    }
+}
+
 // list of triangles to collide against
 #[derive(Component)]
-pub struct Triangles(Vec<Triangle3d>);
+pub struct Triangles(pub Vec<Triangle3d>);
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Default, Clone, Copy, Debug, Component)]
 pub struct AABB {
-    min: Vec3,
-    max: Vec3,
+    pub min: Vec3,
+    pub max: Vec3,
 }
 
 impl AABB {
@@ -32,22 +83,343 @@ impl AABB {
             max,
         }
     }
+
+    pub fn union(self, other: AABB) -> AABB {
+        AABB::new(self.min.min(other.min), self.max.max(other.max))
+    }
 }
 
 #[derive(Component, Debug)]
 pub struct ShouldRenderCollider(bool);
 
-const TRIANGLE_LIMIT: usize = 25;
-const RENDER_AABBS: bool = false;
+// a collidable mesh whose tree is being read from the on disk cache. 'triangles' are kept around in case the cache fails to load
+#[derive(Component)]
+pub struct PendingCollisionTree {
+    handle: Handle<CollisionTreeCache>,
+    hash: u64,
+    triangles: Vec<Triangle3d>,
+}
+
+pub const TRIANGLE_LIMIT: usize = 25;
+
+// a single ray/triangle intersection, in world space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TriangleHit {
+    pub point: Vec3,
+    pub triangle: usize, // index into 'Triangles'
+    pub normal: Vec3, // faces back towards the ray's origin, whatever the winding
+}
 
 // remove duplicate collisions
 pub fn collision(ray: math::Ray3d, recursive_aabb: &RecursiveAABB, triangles: &Triangles, transform: &Mat4) -> Vec<Vec3> {
-    let old = collision_internal(ray, recursive_aabb, triangles, transform);
-    let mut new = Vec::new();
+    collision_hits(ray, recursive_aabb, triangles, transform).iter().map(|hit| hit.point).collect()
+}
+
+// same as `collision`, but also says which triangle was hit and its normal
+pub fn collision_hits(ray: math::Ray3d, recursive_aabb: &RecursiveAABB, triangles: &Triangles, transform: &Mat4) -> Vec<TriangleHit> {
+    dedup(collision_internal(ray, recursive_aabb, triangles, transform, None))
+}
+
+// same as `collision`, but also records the visited leaves, tested triangles and hits into `trace` for the debug overlay
+pub fn collision_traced(ray: math::Ray3d, recursive_aabb: &RecursiveAABB, triangles: &Triangles, transform: &Mat4, trace: &mut RayTrace) -> Vec<Vec3> {
+    dedup(collision_internal(ray, recursive_aabb, triangles, transform, Some(trace))).iter().map(|hit| hit.point).collect()
+}
+
+// tests the ray against every triangle without the tree. a lot slower, but simple enough to check the tree against.
+// uses its own ray/triangle test too, so a bug in 'ray_triangle' or 'point_in_tri' can't hide in both
+pub fn collision_brute_force(ray: math::Ray3d, triangles: &Triangles, transform: &Mat4) -> Vec<TriangleHit> {
+    let hits = triangles.0.iter().enumerate().filter_map(|(index, triangle)| {
+        let vertices = triangle.vertices.map(|v| transform.transform_point3(v));
+        moller_trumbore(ray, &vertices, 1e-4).map(|(point, normal)| TriangleHit { point, triangle: index, normal })
+    });
+
+    dedup(hits.collect())
+}
+
+// Möller–Trumbore. like 'ray_triangle' it's the ray's whole line. 'tolerance' grows the triangle by that much in
+// barycentric coordinates, or shrinks it if negative
+fn moller_trumbore(ray: math::Ray3d, [a, b, c]: &[Vec3; 3], tolerance: f32) -> Option<(Vec3, Vec3)> {
+    let (ab, ac) = (*b - *a, *c - *a);
+    let cross = ab.cross(ac);
+    let p = ray.dir.cross(ac);
+    let det = ab.dot(p);
+    // parallel to the triangle, or the triangle has no area
+    if det.abs() <= f32::EPSILON * cross.length() * ray.dir.length() || cross.length_squared() <= f32::EPSILON * f32::EPSILON {
+        return None;
+    }
+
+    let s = ray.origin - *a;
+    let u = s.dot(p) / det;
+    let q = s.cross(ab);
+    let v = ray.dir.dot(q) / det;
+    if u < -tolerance || v < -tolerance || u + v > 1.0 + tolerance {
+        return None;
+    }
+
+    let t = ac.dot(q) / det;
+    let normal = cross.normalize();
+    Some((ray.at(t), if normal.dot(ray.dir) > 0.0 { -normal } else { normal }))
+}
+
+// is the point within the (closed) mesh? counts how many times a ray leaving the point crosses the surface
+pub fn point_inside(point: Vec3, recursive_aabb: &RecursiveAABB, triangles: &Triangles, transform: &Mat4) -> bool {
+    // skewed so it's unlikely to run along a face or through an edge, either would throw the count off
+    let dir = Vec3::new(1.0, 0.0123, 0.0071);
+    let ray = math::Ray3d::new(point, dir);
+
+    let crossings = collision(ray, recursive_aabb, triangles, transform)
+        .iter()
+        .filter(|p| (**p - point).dot(dir) > 0.0) // 'collision' works on the whole line
+        .count();
+
+    crossings % 2 == 1
+}
+
+// sweeps a sphere from 'ray.origin' along 'ray.dir', returns the first triangle it touches and when it does (in multiples of 'ray.dir').
+// the hit's point is where the sphere touches the triangle and its normal points from there to the sphere's center
+pub fn sphere_cast(ray: math::Ray3d, radius: f32, recursive_aabb: &RecursiveAABB, triangles: &Triangles, transform: &Mat4) -> Option<(f32, TriangleHit)> {
+    // boxes are grown in local space, so undo the smallest scale to stay conservative
+    let (scale, _, _) = transform.to_scale_rotation_translation();
+    let margin = radius / scale.abs().min_element();
+
+    let mut best = None;
+    sphere_cast_internal(ray, radius, margin, recursive_aabb, triangles, transform, &mut best);
+    best
+}
+
+fn sphere_cast_internal(ray: math::Ray3d, radius: f32, margin: f32, recursive_aabb: &RecursiveAABB, triangles: &Triangles, transform: &Mat4, best: &mut Option<(f32, TriangleHit)>) {
+    let grown = AABB::new(recursive_aabb.aabb.min - Vec3::splat(margin), recursive_aabb.aabb.max + Vec3::splat(margin));
+    let mut aabb_vertices = aabb_vertices(grown);
+
+    for vertex in &mut aabb_vertices {
+        *vertex = transform.transform_point3(*vertex);
+    }
+
+    let (intersects, near, far) = ray_intersects_box(ray, &aabb_vertices);
+    // behind the sphere, or further away than something already hit
+    if !intersects || far < 0.0 || best.is_some_and(|(t, _)| near > t) {
+        return;
+    }
+
+    if let Some(next) = &recursive_aabb.next {
+        for next_recursive_aabb in next {
+            sphere_cast_internal(ray, radius, margin, next_recursive_aabb, triangles, transform, best);
+        }
+    } else {
+        for index in &recursive_aabb.enclosed {
+            let mut vertices = triangles.0[*index].vertices;
+
+            for vertex in &mut vertices {
+                *vertex = transform.transform_point3(*vertex);
+            }
+
+            if let Some((t, point, normal)) = sweep_sphere_triangle(ray, radius, &vertices) {
+                if best.is_none_or(|(best_t, _)| t < best_t) {
+                    *best = Some((t, TriangleHit { point, triangle: *index, normal }));
+                }
+            }
+        }
+    }
+}
+
+// earliest t >= 0 where a sphere moving along the ray touches the triangle, with the contact point and normal.
+// a sphere that already overlaps the triangle touches it at t = 0
+pub fn sweep_sphere_triangle(ray: math::Ray3d, radius: f32, tri: &[Vec3; 3]) -> Option<(f32, Vec3, Vec3)> {
+    let [a, b, c] = *tri;
+    let mut n = (b - a).cross(c - a).normalize_or_zero();
+    if n == Vec3::ZERO {
+        return None; // degenerate
+    }
+
+    // work on the side of the plane the sphere starts on
+    let mut dist = (ray.origin - a).dot(n);
+    if dist < 0.0 {
+        n = -n;
+        dist = -dist;
+    }
+
+    // the face comes first if the sphere lands inside it
+    let approach = ray.dir.dot(n);
+    let face_t = if dist <= radius {
+        Some(0.0)
+    } else if approach < 0.0 {
+        Some((radius - dist) / approach)
+    } else {
+        None
+    };
+
+    if let Some(t) = face_t {
+        let center = ray.at(t);
+        let contact = center - n * (center - a).dot(n);
+        if point_in_tri(contact, tri) {
+            return Some((t, contact, n));
+        }
+    }
+
+    // otherwise it has to be an edge or a corner
+    let mut best: Option<(f32, Vec3)> = None;
+    let mut consider = |t: f32, contact: Vec3| {
+        if best.is_none_or(|(best_t, _)| t < best_t) {
+            best = Some((t, contact));
+        }
+    };
+
+    for v in [a, b, c] {
+        if let Some(t) = sweep_sphere_point(ray, radius, v) {
+            consider(t, v);
+        }
+    }
+
+    for (p, q) in [(a, b), (b, c), (c, a)] {
+        if let Some((t, contact)) = sweep_sphere_segment(ray, radius, p, q) {
+            consider(t, contact);
+        }
+    }
+
+    best.map(|(t, contact)| (t, contact, (ray.at(t) - contact).normalize_or(n)))
+}
+
+// smallest non negative root of a t^2 + b t + c = 0, 0 if the roots straddle it
+fn first_root(a: f32, b: f32, c: f32) -> Option<f32> {
+    if a.abs() < f32::EPSILON {
+        return None; // not moving relative to the shape
+    }
+
+    let disc = b * b - 4.0 * a * c;
+    if disc < 0.0 {
+        return None;
+    }
+
+    let sqrt = disc.sqrt();
+    let t0 = (-b - sqrt) / (2.0 * a);
+    let t1 = (-b + sqrt) / (2.0 * a);
+    if t1 < 0.0 {
+        return None;
+    }
+
+    Some(t0.max(0.0))
+}
+
+fn sweep_sphere_point(ray: math::Ray3d, radius: f32, point: Vec3) -> Option<f32> {
+    let m = ray.origin - point;
+    first_root(ray.dir.dot(ray.dir), 2.0 * m.dot(ray.dir), m.dot(m) - radius * radius)
+}
+
+// against the side of the capsule around the segment, the ends are covered by 'sweep_sphere_point'
+fn sweep_sphere_segment(ray: math::Ray3d, radius: f32, p: Vec3, q: Vec3) -> Option<(f32, Vec3)> {
+    let e = q - p;
+    let ee = e.dot(e);
+    let m = ray.origin - p;
+
+    // drop everything along the segment, leaving a circle in the plane perpendicular to it
+    let d_perp = ray.dir - e * (ray.dir.dot(e) / ee);
+    let m_perp = m - e * (m.dot(e) / ee);
+
+    let t = first_root(d_perp.dot(d_perp), 2.0 * m_perp.dot(d_perp), m_perp.dot(m_perp) - radius * radius)?;
+    let s = (ray.at(t) - p).dot(e) / ee;
+    if !(0.0..=1.0).contains(&s) {
+        return None;
+    }
+
+    Some((t, p + e * s))
+}
+
+// the closest point on any triangle within 'radius' of 'center', and how far away it is.
+// the hit's normal points from that point to 'center'
+pub fn sphere_overlap(center: Vec3, radius: f32, recursive_aabb: &RecursiveAABB, triangles: &Triangles, transform: &Mat4) -> Option<(f32, TriangleHit)> {
+    // same as 'sphere_cast', grown in local space
+    let (scale, _, _) = transform.to_scale_rotation_translation();
+    let margin = radius / scale.abs().min_element();
+    let local = transform.inverse().transform_point3(center);
+
+    let mut best = None;
+    sphere_overlap_internal(center, local, radius, margin, recursive_aabb, triangles, transform, &mut best);
+    best
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sphere_overlap_internal(center: Vec3, local: Vec3, radius: f32, margin: f32, recursive_aabb: &RecursiveAABB, triangles: &Triangles, transform: &Mat4, best: &mut Option<(f32, TriangleHit)>) {
+    let aabb = recursive_aabb.aabb;
+    if local.cmplt(aabb.min - Vec3::splat(margin)).any() || local.cmpgt(aabb.max + Vec3::splat(margin)).any() {
+        return;
+    }
+
+    if let Some(next) = &recursive_aabb.next {
+        for next_recursive_aabb in next {
+            sphere_overlap_internal(center, local, radius, margin, next_recursive_aabb, triangles, transform, best);
+        }
+    } else {
+        for index in &recursive_aabb.enclosed {
+            let mut vertices = triangles.0[*index].vertices;
+
+            for vertex in &mut vertices {
+                *vertex = transform.transform_point3(*vertex);
+            }
+
+            let point = closest_point_on_triangle(center, &vertices);
+            let distance = point.distance(center);
+            if distance <= radius && best.is_none_or(|(best_distance, _)| distance < best_distance) {
+                let [a, b, c] = vertices;
+                let face = (b - a).cross(c - a).normalize_or_zero();
+                let face = if face.dot(center - a) < 0.0 { -face } else { face };
+                *best = Some((distance, TriangleHit { point, triangle: *index, normal: (center - point).normalize_or(face) }));
+            }
+        }
+    }
+}
+
+// by which of the triangle's regions (corners, edges, face) the point's in, see Ericson's Real-Time Collision Detection 5.1.5
+pub fn closest_point_on_triangle(p: Vec3, tri: &[Vec3; 3]) -> Vec3 {
+    let [a, b, c] = *tri;
+    let (ab, ac, ap) = (b - a, c - a, p - a);
+
+    let (d1, d2) = (ab.dot(ap), ac.dot(ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    let bp = p - b;
+    let (d3, d4) = (ab.dot(bp), ac.dot(bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = p - c;
+    let (d5, d6) = (ab.dot(cp), ac.dot(cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denom = va + vb + vc;
+    if denom.abs() <= f32::EPSILON {
+        return a; // degenerate
+    }
+
+    a + ab * (vb / denom) + ac * (vc / denom)
+}
+
+// a ray through a shared edge hits both triangles at the same point, keep the first
+fn dedup(old: Vec<TriangleHit>) -> Vec<TriangleHit> {
+    let mut new: Vec<TriangleHit> = Vec::new();
 
     for c in &old {
-        if !new.contains(c) {
-            new.push(c.clone());
+        if !new.iter().any(|n| n.point == c.point) {
+            new.push(*c);
         }
     }
 
@@ -57,7 +429,7 @@ pub fn collision(ray: math::Ray3d, recursive_aabb: &RecursiveAABB, triangles: &T
 // its about 5-6 times faster to use an aabb hierarchy with model of about 3800 triangles.
 // this performance gain will increase expontentially as triangle count increases.
 // there is still room for improvement by doing collision calculations in local object space instead of world space
-fn collision_internal(ray: math::Ray3d, recursive_aabb: &RecursiveAABB, triangles: &Triangles, transform: &Mat4) -> Vec<Vec3> {
+fn collision_internal(ray: math::Ray3d, recursive_aabb: &RecursiveAABB, triangles: &Triangles, transform: &Mat4, mut trace: Option<&mut RayTrace>) -> Vec<TriangleHit> {
     let mut aabb_vertices = aabb_vertices(recursive_aabb.aabb);
 
     for vertex in &mut aabb_vertices {
@@ -69,9 +441,13 @@ fn collision_internal(ray: math::Ray3d, recursive_aabb: &RecursiveAABB, triangle
     if ray_intersects_box(ray, &aabb_vertices).0 {
         if let Some(next) = &recursive_aabb.next {
             for next_recursive_aabb in next {
-                collisions.append(&mut collision_internal(ray, next_recursive_aabb, triangles, transform));
+                collisions.append(&mut collision_internal(ray, next_recursive_aabb, triangles, transform, trace.as_deref_mut()));
             }
         } else {
+            if let Some(trace) = trace.as_deref_mut() {
+                trace.visited_leaves.push((aabb_vertices, recursive_aabb.enclosed.len()));
+            }
+
             for index in &recursive_aabb.enclosed {
                 let mut vertices = triangles.0[*index].vertices;
 
@@ -79,11 +455,18 @@ fn collision_internal(ray: math::Ray3d, recursive_aabb: &RecursiveAABB, triangle
                     *vertex = transform.transform_point3(*vertex);
                 }
 
-                let plane = plane_from_points(vertices[0], vertices[1], vertices[2]);
-                let intersection = ray_plane_intersect(ray, plane);
-                let point = ray.at(intersection);
-                if point_in_tri(point, &vertices) {
-                    collisions.push(point);
+                let hit = ray_triangle(ray, &vertices);
+
+                if let Some(trace) = trace.as_deref_mut() {
+                    trace.tested_triangles.push(vertices);
+                }
+
+                if let Some((point, normal)) = hit {
+                    if let Some(trace) = trace.as_deref_mut() {
+                        trace.hits.push((point, normal));
+                    }
+
+                    collisions.push(TriangleHit { point, triangle: *index, normal });
                 }
             }
         }
@@ -92,49 +475,175 @@ fn collision_internal(ray: math::Ray3d, recursive_aabb: &RecursiveAABB, triangle
     collisions
 }
 
+// where the ray's line crosses the triangle, and the triangle's normal facing back towards the ray's origin
+fn ray_triangle(ray: math::Ray3d, vertices: &[Vec3; 3]) -> Option<(Vec3, Vec3)> {
+    let plane = plane_from_points(vertices[0], vertices[1], vertices[2]);
+    let intersection = ray_plane_intersect(ray, plane);
+    let point = ray.at(intersection);
+
+    if !point_in_tri(point, vertices) {
+        return None;
+    }
+
+    let mut normal = Vec3::new(plane.a, plane.b, plane.c).normalize();
+    if normal.dot(ray.dir) > 0.0 {
+        normal = -normal;
+    }
+
+    Some((point, normal))
+}
+
 // IMPORTANT: under the hood asset server spawns child entities for both the meshes and the nodes of the object, both of which have a Name component.
 // NODES ARE PARENTS OF MESHES
 // THERE IS ONE MORE ROOT NODE THAT IS A CHILD TO THE SCENEROOT, WHICH HAS THE NODES AS CHILDREN
 //
 // SceneRoot -- RootNode -- Node(s) -- Mesh(s)
 //
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub fn construct_collision_trees(
-    meshes: Query<(Entity, &Parent, &Mesh3d, &Name), Added<Mesh3d>>, // filtered for only new arrivals of 'Mesh3d' 
+    meshes: Query<(Entity, &Parent, &Mesh3d, &Name, Has<SkinnedMesh>), Added<Mesh3d>>, // filtered for only new arrivals of 'Mesh3d' 
     all_parents: Query<&Parent>, // filter doesn't matter, we just need pointers traverse up the heirarchy
-    scenes: Query<&Collidable>,
+    scenes: Query<(&Collidable, Has<DynamicCollidable>, Option<&CollisionLayers>)>,
+    node_extras: Query<&GltfExtras>,
+    mesh_materials: Query<(Option<&GltfMaterialName>, Option<&GltfMaterialExtras>)>,
+    surfaces: Res<SurfaceMaterials>,
+    config: Res<CollisionConfig>,
     assets: Res<Assets<Mesh>>,
+    server: Res<AssetServer>,
     mut commands: Commands,
 ) {
 
-    for (entity, node_id, mesh, name) in meshes.iter() {
+    for (entity, node_id, mesh, name, skinned) in meshes.iter() {
+
+        let scene = all_parents.get(**node_id).ok()
+            .and_then(|root_node| all_parents.get(**root_node).ok())
+            .and_then(|scene_root| scenes.get(**scene_root).ok());
+
+        // meshes can also be turned into trigger volumes through the node's glTF extras, in any scene
+        let mesh_trigger = node_extras.get(**node_id).ok()
+            .and_then(parse_trigger_extras)
+            .filter(|extras| extras.shape == ExtrasShape::Mesh);
+
+        let collidable = scene.is_some_and(|(collidable, _, _)| collidable.0.contains(&String::from(name.as_str()))); // does the list of collidable meshes in the scene contain the mesh in question?
+
+        if collidable || mesh_trigger.is_some() {
+            let (dynamic, layers) = scene.map(|(_, dynamic, layers)| (dynamic, layers.copied())).unwrap_or_default();
 
-        let collidable_mesh_names = &scenes.get(
-            **all_parents.get(
-                **all_parents.get(**node_id).unwrap()
-            ).unwrap()
-        ).unwrap().0;
+            if dynamic {
+                commands.entity(entity).insert(DynamicCollider::default());
+            }
+
+            if let Some(extras) = mesh_trigger {
+                commands.entity(entity).insert((Trigger::new(extras.trigger, TriggerShape::Mesh), CollisionLayers(Layers::TRIGGER)));
+            } else if let Some(layers) = layers {
+                commands.entity(entity).insert(layers);
+            }
+
+            // posed on the gpu, so 'dynamic' has to redo it to keep up
+            let morphed = assets.get(mesh).is_some_and(|mesh| mesh.morph_targets().is_some());
+            if skinned || morphed {
+                commands.entity(entity).insert(DeformableCollider);
+            }
 
-        if collidable_mesh_names.contains(&String::from(name.as_str())) { // does the list of collidable meshes in the scene contain the mesh in question?
             let triangles: Vec<Triangle3d> = assets.get(mesh).expect("Failed to retrieve mesh data.").triangles().expect("Failed to create list of triangles.").collect();
+            let hash = cache::mesh_hash(&triangles);
+
+            // every primitive is its own mesh entity with a single material, so all of its triangles share a surface
+            let (material_name, material_extras) = mesh_materials.get(entity).unwrap_or_default();
+            let surface = surfaces.resolve(material_name.map(|n| n.0.as_str()), material_extras.map(|e| e.value.as_str()));
+            commands.entity(entity).insert(TriangleSurfaces(vec![surface; triangles.len()]));
+
+            // the hash is part of the file name, so a changed mesh never picks up a stale tree
+            if config.use_cache() && cache::cache_exists(hash) {
+                let handle = server.load(cache::cache_asset_path(hash));
+                commands.entity(entity).insert(PendingCollisionTree { handle, hash, triangles });
+            } else {
+                build_and_cache(&mut commands, &config, entity, hash, triangles);
+            }
+        }
+    }
+}
+
+// swaps 'PendingCollisionTree' for the real thing once the asset server is done with it
+pub fn load_cached_collision_trees(
+    pending: Query<(Entity, &PendingCollisionTree)>,
+    caches: Res<Assets<CollisionTreeCache>>,
+    config: Res<CollisionConfig>,
+    server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for (entity, pending) in &pending {
+        if server.is_loaded(&pending.handle) {
+            // left in place for every other instance of the same mesh, it's freed with the last of their handles
+            let Some(cached) = caches.get(&pending.handle) else {
+                continue;
+            };
+
+            commands.entity(entity).remove::<PendingCollisionTree>();
+            if cached.mesh_hash == pending.hash {
+                insert_collision_tree(&mut commands, entity, cached.tree.clone(), cached.triangles.clone());
+            } else {
+                warn!("Collision cache for {:016x} belongs to mesh {:016x}, rebuilding.", pending.hash, cached.mesh_hash);
+                build_and_cache(&mut commands, &config, entity, pending.hash, pending.triangles.clone());
+            }
+        } else if let Some(LoadState::Failed(err)) = server.get_load_state(&pending.handle) {
+            // usually an outdated cache version, overwrite it
+            warn!("Failed to load collision cache for {:016x}, rebuilding: {err}", pending.hash);
+            commands.entity(entity).remove::<PendingCollisionTree>();
+            build_and_cache(&mut commands, &config, entity, pending.hash, pending.triangles.clone());
+        }
+    }
+}
 
-            let all_indices: Vec<usize> = (0..(triangles.len() - 1)).collect();
-            let root = find_aabb(&triangles);
-            let mut recursive_aabb = RecursiveAABB { aabb: root, next: None, enclosed: all_indices };
-            divide_aabb(&mut recursive_aabb, TRIANGLE_LIMIT, &triangles, &mut commands, entity);
-            commands.entity(entity).insert(recursive_aabb);
-            commands.entity(entity).insert(Triangles(triangles));
+fn build_and_cache(commands: &mut Commands, config: &CollisionConfig, entity: Entity, hash: u64, triangles: Vec<Triangle3d>) {
+    let tree = build_collision_tree(&triangles, config.triangle_limit);
+
+    let cached = CollisionTreeCache { mesh_hash: hash, tree, triangles };
+    if config.use_cache() {
+        if let Err(err) = cache::write_cache(&cached) {
+            warn!("Failed to write collision cache for {hash:016x}: {err}");
         }
     }
+
+    insert_collision_tree(commands, entity, cached.tree, cached.triangles);
+}
+
+fn insert_collision_tree(commands: &mut Commands, entity: Entity, tree: RecursiveAABB, triangles: Vec<Triangle3d>) {
+    spawn_aabb_entities(&tree, commands, entity);
+    commands.entity(entity).insert((tree, Triangles(triangles)));
 }
 
-// spawn aabb entity -> calculate divided aabbs -> count vertices -> construct new -> repeat
-// divide into 8ths, halve each dimension
-fn divide_aabb(aabb: &mut RecursiveAABB, triangle_limit: usize, triangles: &[Triangle3d], commands: &mut Commands, parent: Entity) {
+// builds the full aabb hierarchy for a mesh, without touching the ECS
+pub fn build_collision_tree(triangles: &[Triangle3d], triangle_limit: usize) -> RecursiveAABB {
+    let all_indices: Vec<usize> = (0..triangles.len()).collect();
+    let root = find_aabb(triangles);
+    let mut recursive_aabb = RecursiveAABB { aabb: root, next: None, enclosed: all_indices };
+    divide_aabb(&mut recursive_aabb, triangle_limit, triangles, usize::MAX);
+    recursive_aabb
+}
+
+// every node of the tree gets its own AABB entity, used by 'add_collider_wireframes'
+fn spawn_aabb_entities(aabb: &RecursiveAABB, commands: &mut Commands, parent: Entity) {
     let new_parent = commands.spawn((aabb.aabb, ShouldRenderCollider(false), Transform::default())).id();
     commands.entity(parent).add_child(new_parent);
 
-    if aabb.enclosed.len() <= triangle_limit {
-        commands.entity(new_parent).insert(ShouldRenderCollider(aabb.enclosed.len() != 0)); // only show ones that have vertices
+    match &aabb.next {
+        Some(next) => {
+            for next in next {
+                spawn_aabb_entities(next, commands, new_parent);
+            }
+        }
+        None => {
+            commands.entity(new_parent).insert(ShouldRenderCollider(!aabb.enclosed.is_empty())); // only show ones that have vertices
+        }
+    }
+}
+
+// calculate divided aabbs -> count vertices -> construct new -> repeat
+// divide into 8ths, halve each dimension. also stops once dividing didn't separate any triangles, more than
+// 'triangle_limit' around one vertex (or any with a limit of 0) would never fit in a leaf
+fn divide_aabb(aabb: &mut RecursiveAABB, triangle_limit: usize, triangles: &[Triangle3d], parent_enclosed: usize) {
+    if aabb.enclosed.len() <= triangle_limit || aabb.enclosed.len() == parent_enclosed {
         return;
     }
 
@@ -166,7 +675,7 @@ fn divide_aabb(aabb: &mut RecursiveAABB, triangle_limit: usize, triangles: &[Tri
                 let max_z = min_z + z_half;
 
                 let next_aabb_bound = AABB::new(Vec3::new(min_x, min_y, min_z), Vec3::new(max_x, max_y, max_z));
-                let next_enclosed = find_triangles_within_bound(&triangles, &aabb.enclosed, next_aabb_bound);
+                let next_enclosed = find_triangles_within_bound(triangles, &aabb.enclosed, next_aabb_bound);
 
                 next_aabbs[index] = next_aabb_bound;
                 next_encloseds[index] = next_enclosed;
@@ -181,14 +690,17 @@ fn divide_aabb(aabb: &mut RecursiveAABB, triangle_limit: usize, triangles: &[Tri
 
     aabb.next = Some(iter.collect());
 
+    let enclosed = aabb.enclosed.len();
     for next in aabb.next.as_mut().unwrap().iter_mut() {
-        divide_aabb(next, triangle_limit, triangles, commands, new_parent);
+        divide_aabb(next, triangle_limit, triangles, enclosed);
     }
 }
 
 // build the mesh for an AABB. an AABB is its own Entity, as created by 'divide_aabb'
+// the meshes start hidden, 'toggle_collider_wireframes' shows them when 'ColliderDebug::aabb_wireframes' is set
 pub fn add_collider_wireframes(
     meshes: Query<(Entity, &AABB, &ShouldRenderCollider), Added<AABB>>,
+    debug: Res<ColliderDebug>,
     mut commands: Commands,
     mut mesh_assets: ResMut<Assets<Mesh>>,
 ) {
@@ -199,16 +711,38 @@ pub fn add_collider_wireframes(
             (aabb.min.z + aabb.max.z) / 2.0,
         );
 
-        if should_render_collider.0 && RENDER_AABBS {
+        if should_render_collider.0 {
             commands.entity(entity).insert((
                 Mesh3d(mesh_assets.add(Cuboid::from_corners(aabb.min, aabb.max))), // HOLY FUCK CUBOID DOCS ARE ASS
                 Transform::from_translation(center), // Offset the fucky cubiod bs
                 Wireframe,
+                wireframe_visibility(&debug),
             ));
         }
     }
 }
 
+pub fn toggle_collider_wireframes(
+    mut wireframes: Query<&mut Visibility, (With<AABB>, With<Wireframe>)>,
+    debug: Res<ColliderDebug>,
+) {
+    if !debug.is_changed() {
+        return;
+    }
+
+    for mut visibility in &mut wireframes {
+        *visibility = wireframe_visibility(&debug);
+    }
+}
+
+fn wireframe_visibility(debug: &ColliderDebug) -> Visibility {
+    if debug.aabb_wireframes {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    }
+}
+
 // finds the AABB of a mesh, constrained to only points described in 'indices'
 fn find_aabb(triangles: &[Triangle3d]) -> AABB {
     let mut min = [f32::MAX; 3]; // set to max and min f32 to ensure no points are left unconsiderd.
@@ -325,52 +859,49 @@ pub fn aabb_vertices(aabb: AABB) -> [Vec3; 8] {
     [p1, p2, p3, p4, p5, p6, p7, p8]
 }
 
-// TODO: FIX PERPENDICULAR EDGE CASE
-// finds planes of box and tests for itersection. Assumes vertices are in fact those of a box and that
-// they come in a pre specified order (starting on the bottom at one point, rotating clockwise, then top starting opposite the bottom starting point, then also rotate clockwise)
+// finds when the ray's line enters and leaves a box, as t values. the vertices come in the order given by 'aabb_vertices',
+// after any transform, so the box can be rotated, scaled or sheared. flat boxes get a sliver of thickness so rays still hit them
 pub fn ray_intersects_box(ray: math::Ray3d, v: &[Vec3; 8]) -> (bool, f32, f32) {
-    // parallel set 1
-    let p1 = math::plane_from_points(v[0], v[1], v[7]);
-    let p2 = math::plane_from_points(v[3], v[2], v[4]);
-
-    // parallel set 2
-    let p3 = math::plane_from_points(v[0], v[3], v[5]);
-    let p4 = math::plane_from_points(v[1], v[2], v[4]);
+    // faces count as inside, by a fraction of the box's size
+    const TOLERANCE: f32 = 1e-5;
 
-    // parallel set 3
-    let p5 = math::plane_from_points(v[0], v[1], v[2]);
-    let p6 = math::plane_from_points(v[4], v[5], v[6]);
+    // edges leaving the min corner, along what were x, y and z
+    let mut axes = [v[3] - v[0], v[6] - v[0], v[1] - v[0]];
+    let size = axes.iter().map(|axis| axis.length()).fold(0.0, f32::max).max(1e-6);
+    let thickness = size * 1e-4;
 
-    // intersections
-    let mut tmp;
-
-    // find intersection times with each plane and order by earliest to latest
-    let t1 = math::ray_plane_intersect(ray, p1);
-    let t2 = math::ray_plane_intersect(ray, p2);
-    tmp = t1;
-    let t1 = t1.min(t2);
-    let t2 = tmp.max(t2);
+    for i in 0..3 {
+        if axes[i].length() < thickness * 0.5 {
+            let (a, b) = (axes[(i + 1) % 3], axes[(i + 2) % 3]);
+            let fallback = [a, b].into_iter().find(|axis| axis.length() >= thickness * 0.5).map_or(Vec3::X, |axis| axis.any_orthonormal_vector());
+            axes[i] = a.cross(b).try_normalize().unwrap_or(fallback) * thickness;
+        }
+    }
 
-    let t3 = math::ray_plane_intersect(ray, p3);
-    let t4 = math::ray_plane_intersect(ray, p4);
-    tmp = t3;
-    let t3 = t3.min(t4);
-    let t4 = tmp.max(t4);
+    // in the box's own space it's the unit cube, and t values are the same in both
+    let to_local = Mat3::from_cols(axes[0], axes[1], axes[2]).inverse();
+    let origin = to_local * (ray.origin - v[0]);
+    let dir = to_local * ray.dir;
 
-    let t5 = math::ray_plane_intersect(ray, p5);
-    let t6 = math::ray_plane_intersect(ray, p6);
-    tmp = t5;
-    let t5 = t5.min(t6);
-    let t6 = tmp.max(t6);
+    let mut min = f32::NEG_INFINITY;
+    let mut max = f32::INFINITY;
 
-    // repeatedly trim the intersection, seeing if is any is left by the end
-    let min = t1.max(t3);
-    let max = t2.min(t4);
+    for i in 0..3 {
+        // parallel to this pair of faces, it's either always between them or never
+        if dir[i] == 0.0 {
+            if origin[i] < -TOLERANCE || origin[i] > 1.0 + TOLERANCE {
+                return (false, min, max);
+            }
+            continue;
+        }
 
-    let min = min.max(t5);
-    let max = max.min(t6);
+        let t1 = (-TOLERANCE - origin[i]) / dir[i];
+        let t2 = (1.0 + TOLERANCE - origin[i]) / dir[i];
+        min = min.max(t1.min(t2));
+        max = max.min(t1.max(t2));
+    }
 
-    (min < max, min, max)
+    (min <= max, min, max)
 }
 
 // returns whether the line between the two points intersects the triangle, where it does so, and "when" (t value) it does so
@@ -383,34 +914,28 @@ fn line_intersects_triangle(p1: Vec3, p2: Vec3, triangle: &Triangle3d) -> bool {
     let t = math::ray_plane_intersect(ray, plane);
     let intersection = ray.at(t);
     let in_triangle = point_in_tri(intersection, &[triangle.vertices[0], triangle.vertices[1], triangle.vertices[2]]);
-    let between_points = t >= 0.0 && t <= 1.0; // special property of rays
+    let between_points = (0.0..=1.0).contains(&t); // special property of rays
 
     in_triangle && between_points
 }
 
-// `p` is assumed to lie on the same plane as `tri`
+// `p` is assumed to lie on the same plane as `tri`. points on the edges and corners count as inside,
+// so a ray through a shared edge hits both triangles (see 'dedup') instead of slipping between them
 pub fn point_in_tri(p: Vec3, tri: &[Vec3; 3]) -> bool {
     let (a, b, c) = (tri[0], tri[1], tri[2]);
-    let ab = (b - a).normalize();
-    let ba = (a - b).normalize();
-    let ac = (c - a).normalize();
-    let bc = (c - b).normalize();
-
-    let ap = (p - a).normalize();
-    let bp = (p - b).normalize();
-
-    // angle at point a and point b on our tri
-    let theta_a = ab.dot(ac);
-    let theta_b = ba.dot(bc);
+    let n = (b - a).cross(c - a);
+    let area = n.length_squared();
+    if area <= f32::EPSILON * f32::EPSILON {
+        return false; // degenerate
+    }
 
-    // angles between our point and the sides of our tri
-    let theta_iab = ap.dot(ab);
-    let theta_iac = ap.dot(ac);
-    let theta_iba = bp.dot(ba);
-    let theta_ibc = bp.dot(bc);
+    // barycentric coordinates, from the areas of the triangles 'p' makes with each edge
+    let u = (c - b).cross(p - b).dot(n) / area;
+    let v = (a - c).cross(p - c).dot(n) / area;
+    let w = 1.0 - u - v;
 
-    // we invert the comparison becuase cos is, in some sense, proportional to the negative of the angle
-    (theta_iab > theta_a && theta_iac > theta_a) && (theta_iba > theta_b && theta_ibc > theta_b)
+    const TOLERANCE: f32 = 1e-4;
+    u >= -TOLERANCE && v >= -TOLERANCE && w >= -TOLERANCE
 }
 
 #[test]
@@ -474,4 +999,239 @@ fn test_triangles_within_bound() {
     let indices = find_triangles_within_bound(&triangles, &[0], aabb);
     // assert_eq!(indices, Vec::<usize>::new());
     assert_eq!(indices, vec![0]);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_refit() {
+    // a ramp of quads along x, rising with z
+    let mut triangles = Vec::new();
+    for x in 0..40 {
+        let x = x as f32;
+        triangles.push(Triangle3d::new(Vec3::new(x, 0.0, 0.0), Vec3::new(x + 1.0, 0.0, 0.0), Vec3::new(x, 0.5, 1.0)));
+        triangles.push(Triangle3d::new(Vec3::new(x + 1.0, 0.0, 0.0), Vec3::new(x + 1.0, 0.5, 1.0), Vec3::new(x, 0.5, 1.0)));
+    }
+
+    let mut tree = build_collision_tree(&triangles, TRIANGLE_LIMIT);
+    let ray = math::Ray3d::new(Vec3::new(110.3, 50.0, 0.4), Vec3::NEG_Y);
+
+    // slide everything well outside of the original bounds
+    let offset = Vec3::new(100.0, 0.0, 0.0);
+    let moved: Vec<Triangle3d> = triangles.iter().map(|t| Triangle3d::new(t.vertices[0] + offset, t.vertices[1] + offset, t.vertices[2] + offset)).collect();
+    assert!(collision(ray, &tree, &Triangles(moved.clone()), &Mat4::IDENTITY).is_empty());
+
+    tree.refit(&moved);
+    let hits = collision(ray, &tree, &Triangles(moved), &Mat4::IDENTITY);
+    assert_eq!(hits.len(), 1);
+    assert!((hits[0].y - 0.2).abs() < 1e-4);
+}
+
+#[test]
+fn test_point_inside() {
+    let cube = Mesh::from(Cuboid::new(2.0, 2.0, 2.0));
+    let triangles: Vec<Triangle3d> = cube.triangles().unwrap().collect();
+    let tree = build_collision_tree(&triangles, TRIANGLE_LIMIT);
+    let triangles = Triangles(triangles);
+    let transform = Mat4::from_translation(Vec3::new(10.0, 0.0, 0.0));
+
+    assert!(point_inside(Vec3::new(10.2, 0.3, -0.4), &tree, &triangles, &transform));
+    assert!(!point_inside(Vec3::new(0.2, 0.3, -0.4), &tree, &triangles, &transform));
+    assert!(!point_inside(Vec3::new(10.2, 1.5, -0.4), &tree, &triangles, &transform));
+}
+
+#[test]
+fn test_sweep_sphere_triangle() {
+    let tri = [Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0)];
+
+    // straight down onto the face
+    let (t, point, normal) = sweep_sphere_triangle(math::Ray3d::new(Vec3::new(0.5, 5.0, 0.5), Vec3::NEG_Y), 1.0, &tri).unwrap();
+    assert!((t - 4.0).abs() < 1e-4);
+    assert!(point.distance(Vec3::new(0.5, 0.0, 0.5)) < 1e-4);
+    assert!(normal.distance(Vec3::Y) < 1e-4);
+
+    // past the long edge, only clipping it
+    let (t, point, _) = sweep_sphere_triangle(math::Ray3d::new(Vec3::new(1.5, 5.0, 1.5), Vec3::NEG_Y), 1.0, &tri).unwrap();
+    let center = Vec3::new(1.5, 5.0 - t, 1.5);
+    assert!(point.distance(Vec3::new(1.0, 0.0, 1.0)) < 1e-4);
+    assert!((center.distance(point) - 1.0).abs() < 1e-4);
+
+    // sideways and too high to touch
+    assert!(sweep_sphere_triangle(math::Ray3d::new(Vec3::new(-5.0, 1.5, 0.5), Vec3::X), 1.0, &tri).is_none());
+
+    // already touching
+    let (t, _, _) = sweep_sphere_triangle(math::Ray3d::new(Vec3::new(0.5, 0.5, 0.5), Vec3::X), 1.0, &tri).unwrap();
+    assert_eq!(t, 0.0);
+}
+
+#[test]
+fn test_sphere_overlap() {
+    let cube = Mesh::from(Cuboid::new(2.0, 2.0, 2.0));
+    let triangles = Triangles(cube.triangles().unwrap().collect());
+    let tree = build_collision_tree(&triangles.0, TRIANGLE_LIMIT);
+    let transform = Mat4::from_translation(Vec3::new(10.0, 0.0, 0.0));
+
+    // just off the +x face
+    let (distance, hit) = sphere_overlap(Vec3::new(11.5, 0.2, 0.3), 1.0, &tree, &triangles, &transform).unwrap();
+    assert!((distance - 0.5).abs() < 1e-4);
+    assert!(hit.point.distance(Vec3::new(11.0, 0.2, 0.3)) < 1e-4);
+    assert!(hit.normal.distance(Vec3::X) < 1e-4);
+
+    // off a corner, the closest point's the corner itself
+    let (_, hit) = sphere_overlap(Vec3::new(11.3, 1.3, 1.3), 1.0, &tree, &triangles, &transform).unwrap();
+    assert!(hit.point.distance(Vec3::new(11.0, 1.0, 1.0)) < 1e-4);
+
+    assert!(sphere_overlap(Vec3::new(12.5, 0.0, 0.0), 1.0, &tree, &triangles, &transform).is_none());
+}
+
+// small lcg so the randomized tests below are repeatable
+#[cfg(test)]
+struct TestRng(u64);
+
+#[cfg(test)]
+impl TestRng {
+    fn next(&mut self) -> f32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next()
+    }
+
+    fn vec3(&mut self, min: f32, max: f32) -> Vec3 {
+        Vec3::new(self.range(min, max), self.range(min, max), self.range(min, max))
+    }
+
+    fn index(&mut self, len: usize) -> usize {
+        ((self.next() * len as f32) as usize).min(len - 1)
+    }
+}
+
+#[test]
+fn test_collision_matches_brute_force() {
+    // closest hit in front of the origin, as a distance along the ray
+    fn closest(ray: math::Ray3d, hits: &[TriangleHit]) -> Option<f32> {
+        hits.iter().map(|hit| (hit.point - ray.origin).dot(ray.dir) / ray.dir.length_squared()).filter(|t| *t >= 0.0).min_by(f32::total_cmp)
+    }
+
+    fn leaves<'a>(node: &'a RecursiveAABB, out: &mut Vec<&'a RecursiveAABB>) {
+        match node.next() {
+            Some(next) => next.iter().for_each(|next| leaves(next, out)),
+            None => out.push(node),
+        }
+    }
+
+    let mut rng = TestRng(0x5eed);
+
+    for case in 0..40 {
+        // a bumpy grid, some loose triangles and a few axis aligned quads so some boxes end up flat
+        let mut triangles = Vec::new();
+        let size = 4 + rng.index(8);
+        let heights: Vec<f32> = (0..(size + 1) * (size + 1)).map(|_| rng.range(-1.0, 1.0)).collect();
+        let point = |x: usize, z: usize| Vec3::new(x as f32 - size as f32 / 2.0, heights[z * (size + 1) + x], z as f32 - size as f32 / 2.0);
+        for z in 0..size {
+            for x in 0..size {
+                triangles.push(Triangle3d::new(point(x, z), point(x + 1, z), point(x, z + 1)));
+                triangles.push(Triangle3d::new(point(x + 1, z), point(x + 1, z + 1), point(x, z + 1)));
+            }
+        }
+        for _ in 0..20 {
+            let a = rng.vec3(-6.0, 6.0);
+            triangles.push(Triangle3d::new(a, a + rng.vec3(-2.0, 2.0), a + rng.vec3(-2.0, 2.0)));
+        }
+        for _ in 0..5 {
+            let (a, y) = (rng.vec3(-6.0, 6.0), rng.range(-3.0, 3.0));
+            let (min, max) = (Vec3::new(a.x, y, a.z), Vec3::new(a.x + 2.0, y, a.z + 2.0));
+            triangles.push(Triangle3d::new(min, Vec3::new(max.x, y, min.z), max));
+            triangles.push(Triangle3d::new(min, max, Vec3::new(min.x, y, max.z)));
+        }
+
+        // every other case is untransformed so axis aligned rays stay axis aligned to the boxes
+        let transform = if case % 2 == 0 {
+            Mat4::IDENTITY
+        } else {
+            let rotation = Quat::from_euler(EulerRot::XYZ, rng.range(-3.0, 3.0), rng.range(-3.0, 3.0), rng.range(-3.0, 3.0));
+            Mat4::from_scale_rotation_translation(rng.vec3(0.5, 2.0), rotation, rng.vec3(-10.0, 10.0))
+        };
+
+        // down to nothing in a leaf, which only stops dividing once it can't separate triangles any more
+        let tree = build_collision_tree(&triangles, [8, 1, 0][case % 3]);
+        let mut tree_leaves = Vec::new();
+        leaves(&tree, &mut tree_leaves);
+        let tree_leaves: Vec<&RecursiveAABB> = tree_leaves.into_iter().filter(|leaf| !leaf.enclosed().is_empty()).collect();
+        let triangles = Triangles(triangles);
+
+        for i in 0..200 {
+            // what to aim at: anywhere, a vertex, a point on an edge, or a corner or face of a leaf box
+            let mut aimed_at = None;
+            let target = match i % 4 {
+                0 => rng.vec3(-6.0, 6.0),
+                1 | 2 => {
+                    let index = rng.index(triangles.0.len());
+                    aimed_at = Some(index);
+                    let vertices = triangles.0[index].vertices;
+                    let j = rng.index(3);
+                    let along = if i % 4 == 1 { 0.0 } else { rng.next() };
+                    vertices[j].lerp(vertices[(j + 1) % 3], along)
+                }
+                _ => {
+                    let aabb = tree_leaves[rng.index(tree_leaves.len())].aabb();
+                    let mut p = aabb.min.lerp(aabb.max, rng.next());
+                    let axis = rng.index(3);
+                    p[axis] = if rng.next() < 0.5 { aabb.min[axis] } else { aabb.max[axis] };
+                    if i % 8 == 3 {
+                        p = Vec3::select(rng.vec3(0.0, 1.0).cmplt(Vec3::splat(0.5)), aabb.min, aabb.max);
+                    }
+                    p
+                }
+            };
+            let target = transform.transform_point3(target);
+
+            let dir = if i % 5 == 0 {
+                [Vec3::X, Vec3::Y, Vec3::Z, Vec3::NEG_X, Vec3::NEG_Y, Vec3::NEG_Z][rng.index(6)]
+            } else {
+                rng.vec3(-1.0, 1.0).normalize_or(Vec3::Y)
+            };
+            let ray = math::Ray3d::new(target - dir * rng.range(5.0, 30.0), dir);
+
+            // near an edge, and more so for rays close to edge on, rounding decides whether it's a hit. so the tree only has
+            // to agree with the reference about hits a little inside the triangles and misses a little outside them
+            let reference = |tolerance: f32| triangles.0.iter()
+                .filter_map(|t| moller_trumbore(ray, &t.vertices.map(|v| transform.transform_point3(v)), tolerance))
+                .map(|(point, _)| (point - ray.origin).dot(ray.dir) / ray.dir.length_squared())
+                .filter(|t| *t >= 0.0)
+                .min_by(f32::total_cmp);
+            let (inside, outside) = (reference(-1e-3), reference(1e-3));
+            let expected = closest(ray, &collision_brute_force(ray, &triangles, &transform));
+            let actual = closest(ray, &collision_hits(ray, &tree, &triangles, &transform));
+
+            match actual {
+                Some(a) => {
+                    assert!(outside.is_some_and(|o| o <= a + 1e-3), "case {case} ray {i}: {ray:?} closest hit at {a}, reference says {outside:?} at most");
+                    assert!(inside.is_none_or(|least| a <= least + 1e-3), "case {case} ray {i}: {ray:?} closest hit at {a}, reference says {inside:?} at least");
+                }
+                None => assert!(inside.is_none(), "case {case} ray {i}: {ray:?} tree missed, reference says {inside:?}"),
+            }
+
+            // sphere sweeps go through the same tree with grown boxes
+            if i % 10 == 0 {
+                let radius = rng.range(0.1, 1.0);
+                let expected = triangles.0.iter().filter_map(|t| sweep_sphere_triangle(ray, radius, &t.vertices.map(|v| transform.transform_point3(v)))).map(|(t, _, _)| t).min_by(f32::total_cmp);
+                let actual = sphere_cast(ray, radius, &tree, &triangles, &transform).map(|(t, _)| t);
+                match (expected, actual) {
+                    (Some(e), Some(a)) => assert!((e - a).abs() < 1e-3, "case {case} sphere {i}: {ray:?} closest hit at {a}, brute force says {e}"),
+                    (None, None) => {}
+                    _ => panic!("case {case} sphere {i}: {ray:?} tree says {actual:?}, brute force says {expected:?}"),
+                }
+            }
+
+            // anything aimed at the mesh has to hit it, unless it's so close to edge on that the hit point is mostly rounding error
+            let facing = aimed_at.map(|index| {
+                let [a, b, c] = triangles.0[index].vertices.map(|v| transform.transform_point3(v));
+                (b - a).cross(c - a).normalize().dot(dir).abs()
+            });
+            if facing.is_some_and(|facing| facing > 0.2) {
+                assert!(expected.is_some(), "case {case} ray {i}: {ray:?} missed the triangle it was aimed at");
+            }
+        }
+    }
+}
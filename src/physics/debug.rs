@@ -0,0 +1,199 @@
+use bevy::color::palettes::css::{AQUA, GREEN, ORANGE, RED, YELLOW};
+use bevy::prelude::*;
+use bevy::render::renderer::RenderDevice;
+use bevy::render::settings::WgpuFeatures;
+
+use crate::console::parse_arg;
+
+use super::collision::{RecursiveAABB, Triangles, AABB, TRIANGLE_LIMIT};
+
+// runtime switches for the collision debug overlay, toggled from 'input::debug_input' and the 'collider.draw' command
+#[derive(Resource, Debug)]
+pub struct ColliderDebug {
+    pub enabled: bool,
+    pub depth: usize, // only draw tree nodes at this depth (the root is depth 0)
+    pub aabb_wireframes: bool, // show the leaf AABB meshes spawned by 'divide_aabb'
+    pub traces: Vec<RayTrace>, // filled every frame by whoever casts the debug ray, drawn and cleared by 'draw_collider_debug'
+}
+
+impl Default for ColliderDebug {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            depth: 2,
+            aabb_wireframes: false,
+            traces: Vec::new(),
+        }
+    }
+}
+
+// can the renderer draw wireframes? needs 'WgpuFeatures::POLYGON_MODE_LINE', which software rasterizers and webgpu don't have.
+// only known once the renderer is up, see 'detect_wireframe_support'. without it the aabbs are drawn with gizmos instead
+#[derive(Resource, Default, Debug)]
+pub struct WireframeSupport(pub bool);
+
+pub fn detect_wireframe_support(device: Option<Res<RenderDevice>>, mut support: ResMut<WireframeSupport>) {
+    support.0 = device.is_some_and(|device| device.features().contains(WgpuFeatures::POLYGON_MODE_LINE));
+    if !support.0 {
+        info!("The adapter can't draw wireframes, falling back to gizmos for collider boxes.");
+    }
+}
+
+pub fn wireframes_supported(support: Res<WireframeSupport>) -> bool {
+    support.0
+}
+
+// console: 'collider.draw [on|off|aabb|depth <n>]', toggles the overlay without arguments
+pub fn collider_draw_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut debug = world.resource_mut::<ColliderDebug>();
+
+    match args {
+        [] => debug.enabled = !debug.enabled,
+        ["on"] => debug.enabled = true,
+        ["off"] => debug.enabled = false,
+        ["aabb"] => debug.aabb_wireframes = !debug.aabb_wireframes,
+        ["depth", ..] => {
+            debug.depth = parse_arg(args, 1, "depth")?;
+            debug.enabled = true;
+        }
+        _ => return Err(format!("unknown option '{}'", args.join(" "))),
+    }
+
+    Ok(format!("overlay {}, depth {}, aabbs {}", on_off(debug.enabled), debug.depth, on_off(debug.aabb_wireframes)))
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on { "on" } else { "off" }
+}
+
+// records what a single ray query touched, everything is in world space
+#[derive(Default, Debug, Clone)]
+pub struct RayTrace {
+    pub visited_leaves: Vec<([Vec3; 8], usize)>, // aabb vertices (same order as 'aabb_vertices') and the triangle count of the leaf
+    pub tested_triangles: Vec<[Vec3; 3]>,
+    pub hits: Vec<(Vec3, Vec3)>, // point and normal
+}
+
+#[derive(Component)]
+pub struct ColliderDebugText;
+
+const HIT_RADIUS: f32 = 0.05;
+const NORMAL_LENGTH: f32 = 0.5;
+
+pub fn setup_collider_debug(mut commands: Commands) {
+    commands.spawn((
+        Text::default(),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            left: Val::Px(5.0),
+            ..default()
+        },
+        Visibility::Hidden,
+        ColliderDebugText,
+    ));
+}
+
+pub fn draw_collider_debug(
+    collision_data: Query<(&RecursiveAABB, &Triangles, &GlobalTransform)>,
+    mut text: Single<(&mut Text, &mut Visibility), With<ColliderDebugText>>,
+    mut debug: ResMut<ColliderDebug>,
+    mut gizmos: Gizmos,
+) {
+    let traces = std::mem::take(&mut debug.bypass_change_detection().traces);
+
+    let (text, visibility) = &mut *text;
+    if !debug.enabled {
+        **visibility = Visibility::Hidden;
+        return;
+    }
+    **visibility = Visibility::Inherited;
+
+    let mut node_counts = Vec::new();
+    for (recursive_aabb, triangles, transform) in &collision_data {
+        let mut counts = Vec::new();
+        draw_nodes_at_depth(&mut gizmos, recursive_aabb, transform, debug.depth, 0, &mut counts);
+        node_counts.push((triangles.0.len(), counts));
+    }
+
+    let mut visited_counts = Vec::new();
+    for trace in &traces {
+        for (vertices, count) in &trace.visited_leaves {
+            draw_box(&mut gizmos, vertices, ORANGE.into());
+            visited_counts.push(*count);
+        }
+
+        for tri in &trace.tested_triangles {
+            gizmos.linestrip([tri[0], tri[1], tri[2], tri[0]], YELLOW);
+        }
+
+        for (point, normal) in &trace.hits {
+            gizmos.sphere(Isometry3d::from_translation(*point), HIT_RADIUS, RED);
+            gizmos.arrow(*point, *point + *normal * NORMAL_LENGTH, AQUA);
+        }
+    }
+
+    let mut s = format!("collider debug | depth {} ([ / ] to change)\n", debug.depth);
+    for (i, (total, counts)) in node_counts.iter().enumerate() {
+        s += &format!("collider {i}: {total} triangles, {} non-empty nodes at depth: {:?}\n", counts.len(), counts);
+    }
+    s += &format!("visited leaves: {:?}", visited_counts);
+    text.0 = s;
+}
+
+// stands in for the aabb wireframes when the adapter can't draw them
+pub fn draw_aabb_gizmos(collision_data: Query<(&RecursiveAABB, &GlobalTransform)>, debug: Res<ColliderDebug>, mut gizmos: Gizmos) {
+    if !debug.aabb_wireframes {
+        return;
+    }
+
+    for (recursive_aabb, transform) in &collision_data {
+        draw_leaves(&mut gizmos, recursive_aabb, transform);
+    }
+}
+
+fn draw_leaves(gizmos: &mut Gizmos, node: &RecursiveAABB, transform: &GlobalTransform) {
+    match node.next() {
+        Some(next) => next.iter().for_each(|next| draw_leaves(gizmos, next, transform)),
+        None if !node.enclosed().is_empty() => gizmos.cuboid(transform.mul_transform(aabb_transform(node.aabb())), RED),
+        None => {}
+    }
+}
+
+// draws every non-empty node at 'target' depth, coloured from green (empty) to red (at or above TRIANGLE_LIMIT)
+// nodes that are leaves before reaching 'target' are drawn too, so the whole mesh stays covered
+fn draw_nodes_at_depth(gizmos: &mut Gizmos, node: &RecursiveAABB, transform: &GlobalTransform, target: usize, depth: usize, counts: &mut Vec<usize>) {
+    let count = node.enclosed().len();
+    if count == 0 {
+        return;
+    }
+
+    match node.next() {
+        Some(next) if depth < target => {
+            for next in next {
+                draw_nodes_at_depth(gizmos, next, transform, target, depth + 1, counts);
+            }
+        }
+        _ => {
+            let ratio = (count as f32 / TRIANGLE_LIMIT as f32).min(1.0);
+            let color = Color::from(GREEN).mix(&Color::from(RED), ratio);
+            gizmos.cuboid(transform.mul_transform(aabb_transform(node.aabb())), color);
+            counts.push(count);
+        }
+    }
+}
+
+// a unit cuboid scaled and moved onto the aabb
+fn aabb_transform(aabb: AABB) -> Transform {
+    Transform::from_translation((aabb.min + aabb.max) / 2.0).with_scale(aabb.max - aabb.min)
+}
+
+// vertices are expected in the order produced by 'aabb_vertices'
+fn draw_box(gizmos: &mut Gizmos, v: &[Vec3; 8], color: Color) {
+    gizmos.linestrip([v[0], v[1], v[2], v[3], v[0]], color);
+    gizmos.linestrip([v[4], v[5], v[6], v[7], v[4]], color);
+    gizmos.line(v[0], v[6], color);
+    gizmos.line(v[1], v[7], color);
+    gizmos.line(v[2], v[4], color);
+    gizmos.line(v[3], v[5], color);
+}
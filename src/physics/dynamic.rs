@@ -0,0 +1,271 @@
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use bevy::render::mesh::morph::{MeshMorphWeights, MorphAttributes};
+use bevy::render::mesh::skinning::{SkinnedMesh, SkinnedMeshInverseBindposes};
+
+use super::collision::{RecursiveAABB, Triangles};
+
+// put next to 'Collidable' on a scene root whose transform changes at runtime.
+// every collider built from the scene gets a 'DynamicCollider'
+#[derive(Component, Default)]
+pub struct DynamicCollidable;
+
+// how a collider moved over the last frame, kept up to date by 'track_dynamic_colliders'
+#[derive(Component, Debug)]
+pub struct DynamicCollider {
+    previous: Option<Mat4>,
+    pub delta: Mat4, // maps a world space point from where it was last frame to where it is now
+    pub origin: Vec3,
+    pub linear_velocity: Vec3, // velocity of 'origin'
+    pub angular_velocity: Vec3, // rotation axis scaled by radians per second
+}
+
+impl Default for DynamicCollider {
+    fn default() -> Self {
+        Self {
+            previous: None,
+            delta: Mat4::IDENTITY,
+            origin: Vec3::ZERO,
+            linear_velocity: Vec3::ZERO,
+            angular_velocity: Vec3::ZERO,
+        }
+    }
+}
+
+impl DynamicCollider {
+    // velocity of a world space point attached to the collider, e.g. a player standing on it
+    pub fn point_velocity(&self, point: Vec3) -> Vec3 {
+        self.linear_velocity + self.angular_velocity.cross(point - self.origin)
+    }
+
+    // moves a point along with the collider for one frame
+    pub fn carry(&self, point: Vec3) -> Vec3 {
+        self.delta.transform_point3(point)
+    }
+
+    // how much the collider turned over the last frame
+    pub fn delta_rotation(&self) -> Quat {
+        self.delta.to_scale_rotation_translation().1
+    }
+}
+
+// marks a collider whose triangles move relative to its own transform (skinned meshes, morph targets, or meshes edited
+// at runtime). the tree's layout is kept and only its bounds are refit, which is a lot cheaper than a rebuild
+#[derive(Component, Default)]
+pub struct DeformableCollider;
+
+// must run after transform propagation so 'GlobalTransform' is this frame's
+pub fn track_dynamic_colliders(mut colliders: Query<(&mut DynamicCollider, &GlobalTransform)>, time: Res<Time>) {
+    let dt = time.delta_secs();
+
+    for (mut collider, transform) in &mut colliders {
+        let current = transform.compute_matrix();
+        let previous = collider.previous.unwrap_or(current);
+
+        let delta = current * previous.inverse();
+        let (_, rotation, _) = delta.to_scale_rotation_translation();
+        let (axis, angle) = rotation.to_axis_angle();
+        let origin = transform.translation();
+
+        collider.delta = delta;
+        if dt > 0.0 {
+            collider.linear_velocity = (origin - previous.w_axis.truncate()) / dt;
+            collider.angular_velocity = axis * angle / dt;
+        }
+        collider.origin = origin;
+        collider.previous = Some(current);
+    }
+}
+
+// refits trees whose mesh asset was modified, e.g. by procedural animation
+#[allow(clippy::type_complexity)]
+pub fn refit_modified_colliders(
+    mut events: EventReader<AssetEvent<Mesh>>,
+    mut colliders: Query<(&Mesh3d, &mut RecursiveAABB, &mut Triangles), (With<DeformableCollider>, Without<SkinnedMesh>)>,
+    meshes: Res<Assets<Mesh>>,
+) {
+    for event in events.read() {
+        let AssetEvent::Modified { id } = event else {
+            continue;
+        };
+
+        for (mesh, mut tree, mut triangles) in &mut colliders {
+            if mesh.id() != *id {
+                continue;
+            }
+
+            let Some(mesh) = meshes.get(*id) else {
+                continue;
+            };
+
+            let Ok(new) = mesh.triangles() else {
+                continue;
+            };
+
+            replace_triangles(&mut tree, &mut triangles, new.collect());
+        }
+    }
+}
+
+// skinning happens on the gpu, so the posed triangles have to be recomputed here every frame. morph targets are
+// blended in first, the same order the gpu does it in
+#[allow(clippy::type_complexity)]
+pub fn refit_skinned_colliders(
+    mut colliders: Query<(&Mesh3d, &SkinnedMesh, Option<&MeshMorphWeights>, &GlobalTransform, &mut RecursiveAABB, &mut Triangles), With<DeformableCollider>>,
+    joints: Query<&GlobalTransform>,
+    meshes: Res<Assets<Mesh>>,
+    images: Option<Res<Assets<Image>>>,
+    bindposes: Res<Assets<SkinnedMeshInverseBindposes>>,
+) {
+    for (mesh, skin, weights, transform, mut tree, mut triangles) in &mut colliders {
+        let (Some(mesh), Some(bindposes)) = (meshes.get(mesh), bindposes.get(&skin.inverse_bindposes)) else {
+            continue;
+        };
+
+        let joint_matrices: Option<Vec<Mat4>> = skin.joints.iter().zip(bindposes.iter())
+            .map(|(joint, bindpose)| joints.get(*joint).ok().map(|joint| joint.compute_matrix() * *bindpose))
+            .collect();
+
+        let Some(joint_matrices) = joint_matrices else {
+            continue;
+        };
+
+        let Some(positions) = morphed_positions(mesh, weights, images.as_deref()) else {
+            continue;
+        };
+
+        if let Some(new) = skinned_triangles(mesh, &positions, &joint_matrices, transform.compute_matrix().inverse()) {
+            replace_triangles(&mut tree, &mut triangles, new);
+        }
+    }
+}
+
+// morph targets are blended on the gpu as well, so redo it here whenever the weights change. also runs once the tree
+// is first built, in case the mesh doesn't rest at its base pose
+#[allow(clippy::type_complexity)]
+pub fn refit_morphed_colliders(
+    mut colliders: Query<
+        (&Mesh3d, &MeshMorphWeights, &mut RecursiveAABB, &mut Triangles),
+        (With<DeformableCollider>, Without<SkinnedMesh>, Or<(Changed<MeshMorphWeights>, Added<RecursiveAABB>)>),
+    >,
+    meshes: Res<Assets<Mesh>>,
+    images: Option<Res<Assets<Image>>>,
+) {
+    for (mesh, weights, mut tree, mut triangles) in &mut colliders {
+        let Some(mesh) = meshes.get(mesh) else {
+            continue;
+        };
+
+        let Some(new) = morphed_positions(mesh, Some(weights), images.as_deref()).and_then(|positions| mesh_triangles(mesh, &positions)) else {
+            continue;
+        };
+
+        replace_triangles(&mut tree, &mut triangles, new);
+    }
+}
+
+fn replace_triangles(tree: &mut RecursiveAABB, triangles: &mut Triangles, new: Vec<Triangle3d>) {
+    // the tree indexes into the triangle list, so it can only be refit if the topology didn't change
+    if new.len() != triangles.0.len() {
+        warn!("Deformed collider changed from {} to {} triangles, ignoring.", triangles.0.len(), new.len());
+        return;
+    }
+
+    tree.refit(&new);
+    triangles.0 = new;
+}
+
+// the mesh's vertex positions with its morph targets blended in. 'None' if the targets haven't loaded, or were dropped
+// from the main world
+fn morphed_positions(mesh: &Mesh, weights: Option<&MeshMorphWeights>, images: Option<&Assets<Image>>) -> Option<Vec<Vec3>> {
+    let mut positions: Vec<Vec3> = mesh.attribute(Mesh::ATTRIBUTE_POSITION)?.as_float3()?.iter().map(|p| Vec3::from(*p)).collect();
+
+    if let (Some(targets), Some(weights)) = (mesh.morph_targets(), weights) {
+        apply_morph_targets(&mut positions, images?.get(targets)?, weights.weights());
+    }
+
+    Some(positions)
+}
+
+// see 'MorphTargetImage', every target is a layer of 'MorphAttributes', one after another for each vertex, as f32s
+fn apply_morph_targets(positions: &mut [Vec3], targets: &Image, weights: &[f32]) {
+    let size = targets.texture_descriptor.size;
+    let layer = (size.width * size.height) as usize;
+    let float = |i: usize| Some(f32::from_le_bytes(targets.data.get(i * 4..i * 4 + 4)?.try_into().ok()?));
+
+    for (target, weight) in weights.iter().enumerate().take(size.depth_or_array_layers as usize) {
+        if *weight == 0.0 {
+            continue;
+        }
+
+        for (vertex, position) in positions.iter_mut().enumerate() {
+            let start = target * layer + vertex * MorphAttributes::COMPONENT_COUNT;
+            let (Some(x), Some(y), Some(z)) = (float(start), float(start + 1), float(start + 2)) else {
+                return;
+            };
+            *position += Vec3::new(x, y, z) * *weight;
+        }
+    }
+}
+
+// same triangle order as 'Mesh::triangles'
+fn mesh_triangles(mesh: &Mesh, positions: &[Vec3]) -> Option<Vec<Triangle3d>> {
+    let indices: Vec<usize> = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.iter().map(|i| *i as usize).collect(),
+        Some(Indices::U32(indices)) => indices.iter().map(|i| *i as usize).collect(),
+        None => (0..positions.len()).collect(),
+    };
+
+    indices.chunks_exact(3)
+        .map(|i| Some(Triangle3d::new(*positions.get(i[0])?, *positions.get(i[1])?, *positions.get(i[2])?)))
+        .collect()
+}
+
+// 'positions' posed by the joints, in the space of 'world_to_local'.
+// skinned vertices are placed in world space by the joints alone, the mesh's own transform isn't applied
+fn skinned_triangles(mesh: &Mesh, positions: &[Vec3], joint_matrices: &[Mat4], world_to_local: Mat4) -> Option<Vec<Triangle3d>> {
+    let Some(VertexAttributeValues::Uint16x4(joint_indices)) = mesh.attribute(Mesh::ATTRIBUTE_JOINT_INDEX) else {
+        return None;
+    };
+    let Some(VertexAttributeValues::Float32x4(joint_weights)) = mesh.attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT) else {
+        return None;
+    };
+
+    let posed: Vec<Vec3> = positions.iter().zip(joint_indices).zip(joint_weights)
+        .map(|((position, indices), weights)| {
+            let mut skin = Mat4::ZERO;
+            for i in 0..4 {
+                if let Some(joint) = joint_matrices.get(indices[i] as usize) {
+                    skin += *joint * weights[i];
+                }
+            }
+            world_to_local.transform_point3(skin.transform_point3(*position))
+        })
+        .collect();
+
+    mesh_triangles(mesh, &posed)
+}
+
+#[test]
+fn test_morph_targets() {
+    use bevy::asset::RenderAssetUsages;
+    use bevy::render::mesh::morph::MorphTargetImage;
+    use bevy::render::mesh::PrimitiveTopology;
+
+    // one triangle, the first target lifts its first vertex and the second slides the whole thing along x
+    let mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]])
+        .with_inserted_indices(Indices::U16(vec![0, 1, 2]));
+    let lift = [Vec3::Y, Vec3::ZERO, Vec3::ZERO];
+    let slide = [Vec3::X; 3];
+    let targets = [lift, slide].map(|target| target.map(|position| MorphAttributes::new(position, Vec3::ZERO, Vec3::ZERO)).into_iter());
+    let image = MorphTargetImage::new(targets.into_iter(), 3, RenderAssetUsages::default()).unwrap().0;
+
+    let mut positions = vec![Vec3::ZERO, Vec3::X, Vec3::Z];
+    apply_morph_targets(&mut positions, &image, &[0.5, 2.0]);
+    assert_eq!(positions, vec![Vec3::new(2.0, 0.5, 0.0), Vec3::new(3.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 1.0)]);
+
+    let triangles = mesh_triangles(&mesh, &positions).unwrap();
+    assert_eq!(triangles.len(), 1);
+    assert_eq!(triangles[0].vertices[0], Vec3::new(2.0, 0.5, 0.0));
+}
@@ -0,0 +1,240 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use std::ops::{BitOr, BitOrAssign};
+
+use super::collision::{collision_hits, sphere_cast, sphere_overlap, RecursiveAABB, Triangles};
+use super::surfaces::{SurfaceId, TriangleSurfaces};
+use crate::math;
+
+// bit set of collision layers. a collider can be in several at once
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Layers(pub u32);
+
+impl Layers {
+    pub const NONE: Layers = Layers(0);
+    pub const STATIC_WORLD: Layers = Layers(1 << 0);
+    pub const WEB_ATTACHABLE: Layers = Layers(1 << 1);
+    pub const PLAYER: Layers = Layers(1 << 2);
+    pub const ENEMY: Layers = Layers(1 << 3);
+    pub const TRIGGER: Layers = Layers(1 << 4);
+    pub const NO_WEB: Layers = Layers(1 << 5); // glass and anything else webs should bounce off
+    pub const ALL: Layers = Layers(u32::MAX);
+
+    pub fn contains(self, other: Layers) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn intersects(self, other: Layers) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl BitOr for Layers {
+    type Output = Layers;
+
+    fn bitor(self, rhs: Layers) -> Layers {
+        Layers(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Layers {
+    fn bitor_assign(&mut self, rhs: Layers) {
+        self.0 |= rhs.0;
+    }
+}
+
+// the layers a collider belongs to. put it on a 'Collidable' scene root to apply it to every collider built from the scene,
+// colliders without one are treated as 'CollisionLayers::default()'
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CollisionLayers(pub Layers);
+
+impl Default for CollisionLayers {
+    fn default() -> Self {
+        CollisionLayers(Layers::STATIC_WORLD | Layers::WEB_ATTACHABLE)
+    }
+}
+
+// decides which colliders a query looks at. a collider passes if it is in at least one 'include' layer,
+// in no 'exclude' layer, isn't in 'exclude_entities' and 'predicate' (if any) returns true for it
+pub struct QueryFilter<'a> {
+    pub include: Layers,
+    pub exclude: Layers,
+    pub exclude_entities: Vec<Entity>,
+    pub predicate: Option<&'a dyn Fn(Entity) -> bool>,
+}
+
+impl Default for QueryFilter<'_> {
+    fn default() -> Self {
+        Self {
+            include: Layers::ALL,
+            exclude: Layers::NONE,
+            exclude_entities: Vec::new(),
+            predicate: None,
+        }
+    }
+}
+
+impl<'a> QueryFilter<'a> {
+    pub fn new(include: Layers, exclude: Layers) -> Self {
+        Self {
+            include,
+            exclude,
+            ..default()
+        }
+    }
+
+    // what a web can stick to
+    pub fn web_swing() -> Self {
+        Self::new(Layers::WEB_ATTACHABLE, Layers::NO_WEB | Layers::TRIGGER | Layers::PLAYER)
+    }
+
+    // what pushes the camera in towards the player
+    pub fn camera_boom() -> Self {
+        Self::new(Layers::STATIC_WORLD, Layers::TRIGGER | Layers::PLAYER | Layers::ENEMY)
+    }
+
+    // what blocks the view, e.g. between the player and a web target
+    pub fn line_of_sight() -> Self {
+        Self::new(Layers::ALL, Layers::TRIGGER | Layers::PLAYER)
+    }
+
+    // what the player can stand on and bump into
+    pub fn character() -> Self {
+        Self::new(Layers::STATIC_WORLD | Layers::ENEMY, Layers::TRIGGER | Layers::PLAYER)
+    }
+
+    // what navmeshes are built on, and what takes away the headroom over them
+    pub fn navigation() -> Self {
+        Self::new(Layers::STATIC_WORLD, Layers::TRIGGER | Layers::PLAYER | Layers::ENEMY)
+    }
+
+    // what a web shot sticks to, or bounces off if it's 'NO_WEB'
+    pub fn web_shot() -> Self {
+        Self::new(Layers::STATIC_WORLD | Layers::WEB_ATTACHABLE | Layers::ENEMY, Layers::TRIGGER | Layers::PLAYER)
+    }
+
+    // what the player's punches and kicks land on
+    pub fn melee() -> Self {
+        Self::new(Layers::ENEMY, Layers::TRIGGER | Layers::PLAYER)
+    }
+
+    pub fn exclude_entity(mut self, entity: Entity) -> Self {
+        self.exclude_entities.push(entity);
+        self
+    }
+
+    pub fn with_predicate(mut self, predicate: &'a dyn Fn(Entity) -> bool) -> Self {
+        self.predicate = Some(predicate);
+        self
+    }
+
+    pub fn test(&self, entity: Entity, layers: Layers) -> bool {
+        layers.intersects(self.include)
+            && !layers.intersects(self.exclude)
+            && !self.exclude_entities.contains(&entity)
+            && self.predicate.is_none_or(|predicate| predicate(entity))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub entity: Entity,
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub distance: f32, // in multiples of the ray's direction
+    pub triangle: usize,
+    pub surface: SurfaceId, // look it up in 'SurfaceMaterials'
+}
+
+// every collider in the world, with the filtering applied. the starting point for any query
+#[derive(SystemParam)]
+pub struct CollisionWorld<'w, 's> {
+    colliders: Query<'w, 's, (Entity, &'static RecursiveAABB, &'static Triangles, &'static GlobalTransform, Option<&'static CollisionLayers>)>,
+    surfaces: Query<'w, 's, &'static TriangleSurfaces>,
+}
+
+impl CollisionWorld<'_, '_> {
+    pub fn iter<'a>(&'a self, filter: &'a QueryFilter) -> impl Iterator<Item = (Entity, &'a RecursiveAABB, &'a Triangles, &'a GlobalTransform)> + 'a {
+        self.colliders.iter().filter_map(|(entity, recursive_aabb, triangles, transform, layers)| {
+            let layers = layers.copied().unwrap_or_default().0;
+            filter.test(entity, layers).then_some((entity, recursive_aabb, triangles, transform))
+        })
+    }
+
+    // every hit in front of the ray's origin, closest first
+    pub fn cast_ray(&self, ray: math::Ray3d, filter: &QueryFilter) -> Vec<RayHit> {
+        let mut hits = Vec::new();
+
+        for (entity, recursive_aabb, triangles, transform) in self.iter(filter) {
+            let surfaces = self.surfaces.get(entity).ok();
+
+            for hit in collision_hits(ray, recursive_aabb, triangles, &transform.compute_matrix()) {
+                // 'collision' works on the whole line, so drop anything behind the origin
+                let distance = (hit.point - ray.origin).dot(ray.dir) / ray.dir.length_squared();
+                if distance >= 0.0 {
+                    let surface = surfaces.and_then(|s| s.0.get(hit.triangle).copied()).unwrap_or_default();
+                    hits.push(RayHit { entity, point: hit.point, normal: hit.normal, distance, triangle: hit.triangle, surface });
+                }
+            }
+        }
+
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits
+    }
+
+    pub fn cast_ray_closest(&self, ray: math::Ray3d, filter: &QueryFilter) -> Option<RayHit> {
+        self.cast_ray(ray, filter).into_iter().next()
+    }
+
+    // first thing a sphere of 'radius' touches when swept along the ray. 'point' is the contact point, not the sphere's center
+    pub fn cast_sphere(&self, ray: math::Ray3d, radius: f32, filter: &QueryFilter) -> Option<RayHit> {
+        let mut closest: Option<RayHit> = None;
+
+        for (entity, recursive_aabb, triangles, transform) in self.iter(filter) {
+            let Some((distance, hit)) = sphere_cast(ray, radius, recursive_aabb, triangles, &transform.compute_matrix()) else {
+                continue;
+            };
+
+            if closest.is_none_or(|closest| distance < closest.distance) {
+                let surface = self.surfaces.get(entity).ok().and_then(|s| s.0.get(hit.triangle).copied()).unwrap_or_default();
+                closest = Some(RayHit { entity, point: hit.point, normal: hit.normal, distance, triangle: hit.triangle, surface });
+            }
+        }
+
+        closest
+    }
+
+    // the closest point on every collider within 'radius' of 'center', closest first. 'distance' is from 'center' and
+    // 'normal' points back towards it
+    pub fn overlap_sphere(&self, center: Vec3, radius: f32, filter: &QueryFilter) -> Vec<RayHit> {
+        let mut hits = Vec::new();
+
+        for (entity, recursive_aabb, triangles, transform) in self.iter(filter) {
+            if let Some((distance, hit)) = sphere_overlap(center, radius, recursive_aabb, triangles, &transform.compute_matrix()) {
+                let surface = self.surfaces.get(entity).ok().and_then(|s| s.0.get(hit.triangle).copied()).unwrap_or_default();
+                hits.push(RayHit { entity, point: hit.point, normal: hit.normal, distance, triangle: hit.triangle, surface });
+            }
+        }
+
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits
+    }
+}
+
+#[test]
+fn test_query_filter() {
+    let player = Entity::from_raw(1);
+    let wall = Entity::from_raw(2);
+    let glass = Entity::from_raw(3);
+
+    let web = QueryFilter::web_swing();
+    assert!(web.test(wall, CollisionLayers::default().0));
+    assert!(!web.test(glass, Layers::STATIC_WORLD | Layers::WEB_ATTACHABLE | Layers::NO_WEB));
+    assert!(!web.test(player, Layers::PLAYER));
+
+    let not_wall = |entity| entity != wall;
+    let filter = QueryFilter::default().exclude_entity(glass).with_predicate(&not_wall);
+    assert!(filter.test(player, Layers::PLAYER));
+    assert!(!filter.test(wall, Layers::STATIC_WORLD));
+    assert!(!filter.test(glass, Layers::STATIC_WORLD));
+}
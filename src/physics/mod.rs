@@ -1 +1,99 @@
-pub mod collision;
\ No newline at end of file
+use bevy::prelude::*;
+
+use crate::console::ConsoleAppExt;
+use crate::tuning::TuningAppExt;
+
+pub mod cache;
+pub mod collision;
+pub mod debug;
+pub mod dynamic;
+pub mod layers;
+pub mod stats;
+pub mod surfaces;
+pub mod triggers;
+
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct CollisionConfig {
+    pub triangle_limit: usize, // most triangles in a leaf of the tree, unless they share a point. the cache is only used with 'collision::TRIANGLE_LIMIT'. changes apply to trees built afterwards
+    #[reflect(ignore)]
+    pub cache: bool, // read and write baked trees under 'assets/colliders'
+    #[reflect(ignore)]
+    pub debug_draw: bool, // the overlay and aabb wireframes, needs the renderer. wireframes also need 'WireframePlugin' and fall back to gizmos
+}
+
+impl Default for CollisionConfig {
+    fn default() -> Self {
+        Self {
+            triangle_limit: collision::TRIANGLE_LIMIT,
+            cache: true,
+            debug_draw: true,
+        }
+    }
+}
+
+impl CollisionConfig {
+    // no renderer and nothing written to disk
+    pub fn headless() -> Self {
+        Self {
+            cache: false,
+            debug_draw: false,
+            ..default()
+        }
+    }
+
+    pub fn use_cache(&self) -> bool {
+        self.cache && self.triangle_limit == collision::TRIANGLE_LIMIT
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CollisionSet {
+    Build, // Update: builds, loads and refits trees for new and deformed meshes
+    Debug, // Update: wireframes and the overlay, after whoever records debug traces
+    Track, // PostUpdate: 'DynamicCollider' velocities, after transform propagation
+    Triggers, // PostUpdate: trigger events, after transform propagation
+}
+
+// collision trees for 'Collidable' scenes, moving and deforming colliders, triggers and surfaces.
+// queries go through the 'layers::CollisionWorld' system param
+#[derive(Default)]
+pub struct CollisionPlugin {
+    pub config: CollisionConfig,
+}
+
+impl Plugin for CollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .init_resource::<debug::ColliderDebug>()
+            .insert_resource(surfaces::SurfaceMaterials::load_or_default())
+            .init_asset::<cache::CollisionTreeCache>()
+            .init_asset_loader::<cache::CollisionTreeCacheLoader>()
+            .add_event::<triggers::TriggerEntered>()
+            .add_event::<triggers::TriggerStayed>()
+            .add_event::<triggers::TriggerExited>()
+            .configure_sets(PostUpdate, (CollisionSet::Track, CollisionSet::Triggers).after(TransformSystem::TransformPropagate))
+            .configure_sets(Update, CollisionSet::Build.before(CollisionSet::Debug))
+            .add_systems(Update, (
+                collision::construct_collision_trees,
+                collision::load_cached_collision_trees,
+                dynamic::refit_modified_colliders,
+                dynamic::refit_skinned_colliders,
+                dynamic::refit_morphed_colliders,
+                triggers::spawn_gltf_triggers,
+            ).in_set(CollisionSet::Build))
+            .add_systems(PostUpdate, dynamic::track_dynamic_colliders.in_set(CollisionSet::Track))
+            .add_systems(PostUpdate, triggers::update_triggers.in_set(CollisionSet::Triggers))
+            .add_console_command("collider.draw", "[on|off|aabb|depth <n>]", "the collision tree overlay", debug::collider_draw_command)
+            .add_tunable::<CollisionConfig>("collision");
+
+        if self.config.debug_draw {
+            app.init_resource::<debug::WireframeSupport>()
+                .add_systems(Startup, (debug::setup_collider_debug, debug::detect_wireframe_support))
+                .add_systems(Update, (
+                    (collision::add_collider_wireframes, collision::toggle_collider_wireframes).run_if(debug::wireframes_supported),
+                    debug::draw_aabb_gizmos.run_if(not(debug::wireframes_supported)),
+                    debug::draw_collider_debug,
+                ).in_set(CollisionSet::Debug));
+        }
+    }
+}
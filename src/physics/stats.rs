@@ -0,0 +1,84 @@
+use std::fmt;
+
+use super::collision::RecursiveAABB;
+
+// summary of a collision tree, used to judge how well a mesh subdivides
+#[derive(Default, Debug, Clone)]
+pub struct TreeStats {
+    pub triangles: usize,
+    pub depth: usize, // the root alone is depth 0
+    pub nodes: usize,
+    pub leaves: usize,
+    pub empty_leaves: usize,
+    pub leaf_histogram: Vec<usize>, // leaf_histogram[n] is the number of leaves holding n triangles
+    pub leaf_triangles: usize, // sum over all leaves, triangles spanning several leaves are counted more than once
+}
+
+impl TreeStats {
+    pub fn new(tree: &RecursiveAABB, triangles: usize) -> Self {
+        let mut stats = TreeStats {
+            triangles,
+            ..Default::default()
+        };
+        stats.visit(tree, 0);
+        stats
+    }
+
+    fn visit(&mut self, node: &RecursiveAABB, depth: usize) {
+        self.nodes += 1;
+        self.depth = self.depth.max(depth);
+
+        match node.next() {
+            Some(next) => {
+                for next in next {
+                    self.visit(next, depth + 1);
+                }
+            }
+            None => {
+                let count = node.enclosed().len();
+                self.leaves += 1;
+                self.leaf_triangles += count;
+                if count == 0 {
+                    self.empty_leaves += 1;
+                }
+
+                if self.leaf_histogram.len() <= count {
+                    self.leaf_histogram.resize(count + 1, 0);
+                }
+                self.leaf_histogram[count] += 1;
+            }
+        }
+    }
+
+    // how many leaf slots each triangle takes up on average, 1.0 means no triangle straddles a boundary
+    pub fn duplication_ratio(&self) -> f32 {
+        if self.triangles == 0 {
+            return 0.0;
+        }
+
+        self.leaf_triangles as f32 / self.triangles as f32
+    }
+}
+
+impl fmt::Display for TreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "triangles:         {}", self.triangles)?;
+        writeln!(f, "depth:             {}", self.depth)?;
+        writeln!(f, "nodes:             {}", self.nodes)?;
+        writeln!(f, "leaves:            {} ({} empty)", self.leaves, self.empty_leaves)?;
+        writeln!(f, "duplication ratio: {:.2}", self.duplication_ratio())?;
+        writeln!(f, "leaf triangle histogram:")?;
+
+        let widest = self.leaf_histogram.iter().copied().max().unwrap_or(0).max(1);
+        for (count, leaves) in self.leaf_histogram.iter().enumerate() {
+            if *leaves == 0 {
+                continue;
+            }
+
+            let bar = "#".repeat((leaves * 40).div_ceil(widest));
+            writeln!(f, "  {count:>4} tris | {leaves:>6} {bar}")?;
+        }
+
+        Ok(())
+    }
+}
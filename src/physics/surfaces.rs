@@ -0,0 +1,150 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::cache::default_assets_dir;
+
+// index into 'SurfaceMaterials'. 0 is always the default material
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SurfaceId(pub u16);
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct SurfaceMaterial {
+    pub name: String,
+    #[serde(default = "default_friction")]
+    pub friction: f32,
+    #[serde(default = "yes")]
+    pub web_stickable: bool,
+    #[serde(default = "yes")]
+    pub crawlable: bool,
+    #[serde(default)]
+    pub impact_sound: Option<String>, // asset path
+}
+
+fn default_friction() -> f32 {
+    0.6
+}
+
+fn yes() -> bool {
+    true
+}
+
+impl SurfaceMaterial {
+    fn new(name: &str, friction: f32, web_stickable: bool, crawlable: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            friction,
+            web_stickable,
+            crawlable,
+            impact_sound: None,
+        }
+    }
+}
+
+// per triangle surface of a collider, parallel to 'Triangles'
+#[derive(Component, Debug)]
+pub struct TriangleSurfaces(pub Vec<SurfaceId>);
+
+// every known surface. glTF materials are matched to these by name, so "Brick.001" and "brick_wall" both end up as "brick".
+// a material's extras can also pick one directly: {"surface": "glass"}
+#[derive(Resource, Debug)]
+pub struct SurfaceMaterials(Vec<SurfaceMaterial>);
+
+// optional, replaces the built in list below. same fields as 'SurfaceMaterial', in RON
+pub const SURFACES_FILE: &str = "surfaces.ron";
+
+impl Default for SurfaceMaterials {
+    fn default() -> Self {
+        Self(vec![
+            SurfaceMaterial::new("default", 0.6, true, true),
+            SurfaceMaterial::new("brick", 0.8, true, true),
+            SurfaceMaterial::new("concrete", 0.7, true, true),
+            SurfaceMaterial::new("metal", 0.4, true, true),
+            SurfaceMaterial::new("glass", 0.1, false, false),
+        ])
+    }
+}
+
+impl SurfaceMaterials {
+    // 'materials' is prefixed with "default" if it doesn't start with it. names are matched ignoring case, so they're
+    // kept lowercase
+    pub fn new(mut materials: Vec<SurfaceMaterial>) -> Self {
+        for material in &mut materials {
+            material.name = material.name.to_lowercase();
+        }
+        if materials.first().is_none_or(|m| m.name != "default") {
+            materials.insert(0, SurfaceMaterials::default().0.remove(0));
+        }
+
+        Self(materials)
+    }
+
+    // reads 'assets/surfaces.ron', falls back on the built in list if it's missing or broken
+    pub fn load_or_default() -> Self {
+        let path = default_assets_dir().join(SURFACES_FILE);
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+
+        match ron::de::from_str(&text) {
+            Ok(materials) => Self::new(materials),
+            Err(err) => {
+                warn!("Failed to read {}, using the built in surfaces: {err}", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    pub fn get(&self, id: SurfaceId) -> &SurfaceMaterial {
+        self.0.get(id.0 as usize).unwrap_or(&self.0[0])
+    }
+
+    pub fn find(&self, name: &str) -> Option<SurfaceId> {
+        let name = name.to_lowercase();
+        self.0.iter().position(|m| m.name == name).map(|i| SurfaceId(i as u16))
+    }
+
+    // picks the surface for a glTF material, extras win over the name. anything unknown is the default
+    pub fn resolve(&self, material_name: Option<&str>, material_extras: Option<&str>) -> SurfaceId {
+        let from_extras = material_extras
+            .and_then(|extras| serde_json::from_str::<serde_json::Value>(extras).ok())
+            .and_then(|extras| extras.get("surface")?.as_str().and_then(|name| self.find(name)));
+
+        if let Some(id) = from_extras {
+            return id;
+        }
+
+        let from_name = material_name.and_then(|name| {
+            let name = name.to_lowercase();
+            // skip "default", everything would match it
+            self.0.iter().skip(1).position(|m| name.starts_with(&m.name)).map(|i| SurfaceId(i as u16 + 1))
+        });
+
+        from_name.unwrap_or_default()
+    }
+}
+
+#[test]
+fn test_resolve_surface() {
+    let surfaces = SurfaceMaterials::default();
+    let glass = surfaces.find("glass").unwrap();
+    let brick = surfaces.find("brick").unwrap();
+
+    assert_eq!(surfaces.resolve(Some("Glass.001"), None), glass);
+    assert_eq!(surfaces.resolve(Some("brick_wall"), None), brick);
+    assert_eq!(surfaces.resolve(Some("Brick"), Some(r#"{"surface": "glass"}"#)), glass);
+    assert_eq!(surfaces.resolve(Some("Material.004"), None), SurfaceId(0));
+    assert_eq!(surfaces.resolve(None, Some(r#"{"surface": "lava"}"#)), SurfaceId(0));
+    assert!(!surfaces.get(glass).web_stickable);
+    assert!(surfaces.get(brick).web_stickable);
+
+    let custom: Vec<SurfaceMaterial> = ron::de::from_str(r#"[(name: "Ice", friction: 0.05)]"#).unwrap();
+    let surfaces = SurfaceMaterials::new(custom);
+    assert_eq!(surfaces.get(SurfaceId(0)).name, "default");
+    assert_eq!(surfaces.get(SurfaceId(1)).friction, 0.05);
+    assert!(surfaces.get(SurfaceId(1)).crawlable);
+
+    // whatever the case on either side
+    assert_eq!(surfaces.find("ICE"), Some(SurfaceId(1)));
+    assert_eq!(surfaces.resolve(None, Some(r#"{"surface": "iCe"}"#)), SurfaceId(1));
+    assert_eq!(surfaces.resolve(Some("Ice_Rink.002"), None), SurfaceId(1));
+}
@@ -0,0 +1,160 @@
+use bevy::gltf::GltfExtras;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::collision::{point_inside, RecursiveAABB, Triangles};
+
+// the volume of a trigger, in the local space of its entity so the transform's scale stretches it
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum TriggerShape {
+    Box { half_extents: Vec3 },
+    Sphere { radius: f32 },
+    Mesh, // uses the 'RecursiveAABB' and 'Triangles' on the same entity, the mesh should be closed
+}
+
+// a non-solid volume that sends 'TriggerEntered', 'TriggerStayed' and 'TriggerExited' for every 'TriggerTracked' entity.
+// mesh triggers should sit in 'Layers::TRIGGER' so other queries skip them
+#[derive(Component, Debug)]
+pub struct Trigger {
+    pub name: String,
+    pub shape: TriggerShape,
+    inside: Vec<Entity>,
+}
+
+impl Trigger {
+    pub fn new(name: impl Into<String>, shape: TriggerShape) -> Self {
+        Self {
+            name: name.into(),
+            shape,
+            inside: Vec::new(),
+        }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.inside.contains(&entity)
+    }
+
+    pub fn inside(&self) -> &[Entity] {
+        &self.inside
+    }
+}
+
+// entities triggers look out for, e.g. the player. only their origin is tested
+#[derive(Component, Default)]
+pub struct TriggerTracked;
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct TriggerEntered {
+    pub trigger: Entity,
+    pub entity: Entity,
+}
+
+// sent every frame an entity stays inside, after the frame it entered
+#[derive(Event, Clone, Copy, Debug)]
+pub struct TriggerStayed {
+    pub trigger: Entity,
+    pub entity: Entity,
+}
+
+// also sent when a tracked entity despawns or loses 'TriggerTracked' while inside
+#[derive(Event, Clone, Copy, Debug)]
+pub struct TriggerExited {
+    pub trigger: Entity,
+    pub entity: Entity,
+}
+
+#[allow(clippy::type_complexity)]
+pub fn update_triggers(
+    mut triggers: Query<(Entity, &mut Trigger, &GlobalTransform, Option<&RecursiveAABB>, Option<&Triangles>)>,
+    tracked: Query<(Entity, &GlobalTransform), With<TriggerTracked>>,
+    mut entered: EventWriter<TriggerEntered>,
+    mut stayed: EventWriter<TriggerStayed>,
+    mut exited: EventWriter<TriggerExited>,
+) {
+    for (trigger_entity, mut trigger, transform, recursive_aabb, triangles) in &mut triggers {
+        let matrix = transform.compute_matrix();
+        let world_to_local = matrix.inverse();
+
+        let mut now_inside = Vec::new();
+        for (entity, tracked_transform) in &tracked {
+            let point = tracked_transform.translation();
+
+            let inside = match &trigger.shape {
+                TriggerShape::Box { half_extents } => world_to_local.transform_point3(point).abs().cmple(*half_extents).all(),
+                TriggerShape::Sphere { radius } => world_to_local.transform_point3(point).length() <= *radius,
+                TriggerShape::Mesh => match (recursive_aabb, triangles) {
+                    (Some(recursive_aabb), Some(triangles)) => point_inside(point, recursive_aabb, triangles, &matrix),
+                    _ => false, // tree hasn't been built yet
+                },
+            };
+
+            if inside {
+                now_inside.push(entity);
+            }
+        }
+
+        for entity in &now_inside {
+            if trigger.contains(*entity) {
+                stayed.send(TriggerStayed { trigger: trigger_entity, entity: *entity });
+            } else {
+                entered.send(TriggerEntered { trigger: trigger_entity, entity: *entity });
+            }
+        }
+
+        for entity in &trigger.inside {
+            if !now_inside.contains(entity) {
+                exited.send(TriggerExited { trigger: trigger_entity, entity: *entity });
+            }
+        }
+
+        trigger.inside = now_inside;
+    }
+}
+
+// what a designer writes into a node's custom properties in blender, e.g. {"trigger": "checkpoint_1", "shape": "box"}
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct TriggerExtras {
+    pub trigger: String,
+    #[serde(default)]
+    pub shape: ExtrasShape,
+}
+
+// box and sphere match blender's unit cube and sphere empties, scaled by the node's transform
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtrasShape {
+    #[default]
+    Box,
+    Sphere,
+    Mesh, // the node's meshes become the trigger volume, picked up by 'construct_collision_trees'
+}
+
+pub fn parse_trigger_extras(extras: &GltfExtras) -> Option<TriggerExtras> {
+    serde_json::from_str(&extras.value).ok()
+}
+
+// box and sphere triggers authored as glTF extras
+pub fn spawn_gltf_triggers(nodes: Query<(Entity, &GltfExtras), Added<GltfExtras>>, mut commands: Commands) {
+    for (entity, extras) in &nodes {
+        let Some(extras) = parse_trigger_extras(extras) else {
+            continue;
+        };
+
+        let shape = match extras.shape {
+            ExtrasShape::Box => TriggerShape::Box { half_extents: Vec3::ONE },
+            ExtrasShape::Sphere => TriggerShape::Sphere { radius: 1.0 },
+            ExtrasShape::Mesh => continue,
+        };
+
+        commands.entity(entity).insert(Trigger::new(extras.trigger, shape));
+    }
+}
+
+#[test]
+fn test_parse_trigger_extras() {
+    let extras = |value: &str| parse_trigger_extras(&GltfExtras { value: value.to_string() });
+
+    assert_eq!(extras(r#"{"trigger": "checkpoint_1"}"#), Some(TriggerExtras { trigger: "checkpoint_1".to_string(), shape: ExtrasShape::Box }));
+    assert_eq!(extras(r#"{"trigger": "roof", "shape": "mesh", "other": 3}"#), Some(TriggerExtras { trigger: "roof".to_string(), shape: ExtrasShape::Mesh }));
+    assert_eq!(extras(r#"{"color": "red"}"#), None);
+}
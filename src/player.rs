@@ -0,0 +1,411 @@
+use bevy::prelude::*;
+use bevy::state::state::StateTransitionEvent;
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+use crate::air::AirState;
+use crate::camera::{CameraSet, CameraState};
+use crate::console::ConsoleAppExt;
+use crate::input::{InputSet, PlayerInput};
+use crate::math;
+use crate::physics::layers::{CollisionWorld, QueryFilter, RayHit};
+use crate::tuning::TuningAppExt;
+
+// what the player is doing. only one state's movement system runs at a time, see 'PlayerPlugin'.
+// changes go through 'RequestPlayerState' so they're checked against 'can_transition_to'
+#[derive(States, Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum PlayerState {
+    #[default]
+    Flying, // the debug camera, no gravity or ground
+    Walking,
+    Falling,
+    Swinging,
+    Zipping,
+    WallCrawling,
+    Perching,
+}
+
+impl PlayerState {
+    pub const ALL: [PlayerState; 7] = [
+        PlayerState::Flying,
+        PlayerState::Walking,
+        PlayerState::Falling,
+        PlayerState::Swinging,
+        PlayerState::Zipping,
+        PlayerState::WallCrawling,
+        PlayerState::Perching,
+    ];
+
+    // every transition the player is allowed to make. anything can go to flying so it's always there to debug with
+    pub fn can_transition_to(self, to: PlayerState) -> bool {
+        use PlayerState::*;
+
+        match (self, to) {
+            (from, to) if from == to => false,
+            (_, Flying) => true,
+            (Flying, Walking | Falling) => true,
+            (Walking, Falling | Swinging | Zipping | WallCrawling | Perching) => true,
+            (Falling, Walking | Swinging | Zipping | WallCrawling | Perching) => true,
+            (Swinging, Falling | Walking | Zipping | WallCrawling) => true,
+            (Zipping, Falling | Perching | WallCrawling) => true,
+            (WallCrawling, Falling | Walking | Perching | Swinging) => true,
+            (Perching, Falling | Walking | Swinging | Zipping) => true,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for PlayerState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PlayerState::ALL.into_iter().find(|state| format!("{state:?}").eq_ignore_ascii_case(s)).ok_or(format!("unknown state '{s}'"))
+    }
+}
+
+// asks for the player to change state. checked and applied by 'apply_state_requests', the last valid one in a frame wins
+#[derive(Event, Clone, Copy, Debug)]
+pub struct RequestPlayerState(pub PlayerState);
+
+// the player hit the ground, with the velocity it came down at and the normal of what it landed on
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Landed {
+    pub entity: Entity,
+    pub velocity: Vec3,
+    pub normal: Vec3,
+}
+
+// the player's movement beyond where it is
+#[derive(Component, Default, Debug)]
+pub struct PlayerMotion {
+    pub velocity: Vec3,
+}
+
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct PlayerConfig {
+    pub fly_speed: f32, // units per second
+    pub walk_speed: f32,
+    pub jump_speed: f32, // straight up, on leaving the ground
+    pub gravity: f32,
+    pub eye_height: f32, // 'CameraState::pos' is the eye, the feet are this far below it
+    pub step_height: f32, // ground this far below the feet is still walked on rather than fallen to
+    pub history: usize, // transitions kept for the readout
+    #[reflect(ignore)]
+    pub readout: bool, // the F6 state readout, needs the renderer
+}
+
+impl Default for PlayerConfig {
+    fn default() -> Self {
+        Self {
+            fly_speed: 5.0,
+            walk_speed: 6.0,
+            jump_speed: 6.0,
+            gravity: 20.0,
+            eye_height: 1.7,
+            step_height: 0.5,
+            history: 8,
+            readout: true,
+        }
+    }
+}
+
+impl PlayerConfig {
+    pub fn headless() -> Self {
+        Self {
+            readout: false,
+            ..default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transition {
+    pub from: PlayerState,
+    pub to: PlayerState,
+    pub at: f32, // seconds since startup
+}
+
+// the last few transitions, newest last
+#[derive(Resource, Default, Debug)]
+pub struct PlayerStateHistory(pub VecDeque<Transition>);
+
+// the player flies through everything. it's kept in 'PlayerState::Flying' until this is turned off again
+#[derive(Resource, Default, Debug)]
+pub struct NoClip(pub bool);
+
+#[derive(Resource, Default, Debug)]
+pub struct PlayerDebug {
+    pub enabled: bool,
+}
+
+#[derive(Component)]
+pub struct PlayerStateText;
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlayerSet;
+
+// the player's state machine and the movement for each state, from 'PlayerInput'. runs in 'PlayerSet', after 'InputSet'
+// and before 'CameraSet'. needs bevy's 'StatesPlugin'.
+// anything that wants to know about transitions (animation, audio) reads 'StateTransitionEvent<PlayerState>'
+#[derive(Default)]
+pub struct PlayerPlugin {
+    pub config: PlayerConfig,
+}
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .init_state::<PlayerState>()
+            .init_resource::<PlayerStateHistory>()
+            .init_resource::<PlayerDebug>()
+            .init_resource::<NoClip>()
+            .add_event::<RequestPlayerState>()
+            .add_event::<Landed>()
+            .configure_sets(Update, PlayerSet.after(InputSet).before(CameraSet))
+            .add_systems(Update, (
+                (
+                    fly_movement.run_if(in_state(PlayerState::Flying)),
+                    walk_movement.run_if(in_state(PlayerState::Walking)),
+                    fall_movement.run_if(in_state(PlayerState::Falling)),
+                    no_movement.run_if(
+                        in_state(PlayerState::Swinging)
+                            .or(in_state(PlayerState::Zipping))
+                            .or(in_state(PlayerState::WallCrawling))
+                            .or(in_state(PlayerState::Perching)),
+                    ),
+                ),
+                (apply_state_requests, record_transitions, clear_player_input),
+            ).chain().in_set(PlayerSet))
+            .add_systems(OnExit(PlayerState::Flying), stop)
+            .add_systems(OnEnter(PlayerState::Walking), land)
+            .add_systems(OnEnter(PlayerState::Perching), stop)
+            .add_console_command("state", "[state]", "show the player's state and history, or change it", state_command)
+            .add_tunable::<PlayerConfig>("player");
+
+        if self.config.readout {
+            app.add_systems(Startup, setup_state_readout)
+                .add_systems(Update, update_state_readout.after(PlayerSet));
+        }
+    }
+}
+
+// straight where the camera's looking, nothing in the way
+pub fn fly_movement(input: Res<PlayerInput>, time: Res<Time>, config: Res<PlayerConfig>, player: Single<(&mut CameraState, &mut PlayerMotion)>) {
+    let (mut camera_state, mut motion) = player.into_inner();
+    let movement = input.movement;
+
+    let offset = camera_state.forward * movement.z + camera_state.right * movement.x + Vec3::Y * movement.y;
+    motion.velocity = offset * config.fly_speed;
+    camera_state.pos += motion.velocity * time.delta().as_secs_f32();
+}
+
+// the ground under 'pos' (the eye), if it's close enough to stand on
+fn ground_below(world: &CollisionWorld, pos: Vec3, reach: f32) -> Option<RayHit> {
+    let hit = world.cast_ray_closest(math::Ray3d::new(pos, Vec3::NEG_Y), &QueryFilter::character())?;
+    (hit.distance <= reach).then_some(hit)
+}
+
+// flat along the ground whichever way the camera's looking, stuck to the ground below
+pub fn walk_movement(
+    input: Res<PlayerInput>,
+    time: Res<Time>,
+    config: Res<PlayerConfig>,
+    world: CollisionWorld,
+    player: Single<(&mut CameraState, &mut PlayerMotion)>,
+    mut requests: EventWriter<RequestPlayerState>,
+) {
+    let (mut camera_state, mut motion) = player.into_inner();
+    let forward = Vec3::new(camera_state.forward.x, 0.0, camera_state.forward.z).normalize_or_zero();
+    let right = Vec3::new(camera_state.right.x, 0.0, camera_state.right.z).normalize_or_zero();
+    let dir = (forward * input.movement.z + right * input.movement.x).clamp_length_max(1.0);
+
+    motion.velocity = dir * config.walk_speed;
+    camera_state.pos += motion.velocity * time.delta().as_secs_f32();
+
+    if input.jump {
+        motion.velocity.y = config.jump_speed;
+        requests.send(RequestPlayerState(PlayerState::Falling));
+        return;
+    }
+
+    match ground_below(&world, camera_state.pos, config.eye_height + config.step_height) {
+        Some(ground) => camera_state.pos.y = ground.point.y + config.eye_height,
+        None => {
+            requests.send(RequestPlayerState(PlayerState::Falling));
+        }
+    }
+}
+
+// gravity, keeping whatever velocity it left the ground with, until the feet reach the ground
+pub fn fall_movement(
+    time: Res<Time>,
+    config: Res<PlayerConfig>,
+    world: CollisionWorld,
+    player: Single<(Entity, &mut CameraState, &mut PlayerMotion)>,
+    mut requests: EventWriter<RequestPlayerState>,
+    mut landed: EventWriter<Landed>,
+) {
+    let (entity, mut camera_state, mut motion) = player.into_inner();
+    let dt = time.delta().as_secs_f32();
+
+    motion.velocity.y -= config.gravity * dt;
+    let from = camera_state.pos;
+    let to = from + motion.velocity * dt;
+    camera_state.pos = to;
+
+    if motion.velocity.y > 0.0 {
+        return;
+    }
+
+    // straight down from where it was, at where it is now
+    let reach = from.y - to.y + config.eye_height;
+    if let Some(ground) = ground_below(&world, Vec3::new(to.x, from.y, to.z), reach) {
+        camera_state.pos.y = ground.point.y + config.eye_height;
+        requests.send(RequestPlayerState(PlayerState::Walking));
+        landed.send(Landed { entity, velocity: motion.velocity, normal: ground.normal });
+    }
+}
+
+// the states that don't move the player yet hand it straight back to falling, rather than leave it stuck in the air
+pub fn no_movement(state: Res<State<PlayerState>>, mut requests: EventWriter<RequestPlayerState>) {
+    debug!("{:?} has no movement yet, falling instead", state.get());
+    requests.send(RequestPlayerState(PlayerState::Falling));
+}
+
+// flying speed doesn't carry over into anything else
+fn stop(mut motion: Single<&mut PlayerMotion>) {
+    motion.velocity = Vec3::ZERO;
+}
+
+fn land(mut motion: Single<&mut PlayerMotion>) {
+    motion.velocity.y = 0.0;
+}
+
+pub fn apply_state_requests(
+    mut requests: EventReader<RequestPlayerState>,
+    state: Res<State<PlayerState>>,
+    mut next: ResMut<NextState<PlayerState>>,
+    noclip: Res<NoClip>,
+) {
+    // nothing else moves the player through walls
+    if noclip.0 {
+        requests.clear();
+        if *state.get() != PlayerState::Flying {
+            next.set(PlayerState::Flying);
+        }
+        return;
+    }
+
+    for RequestPlayerState(to) in requests.read() {
+        if state.get().can_transition_to(*to) {
+            next.set(*to);
+        } else if state.get() != to {
+            warn!("The player can't go from {:?} to {to:?}", state.get());
+        }
+    }
+}
+
+pub fn record_transitions(
+    mut transitions: EventReader<StateTransitionEvent<PlayerState>>,
+    mut history: ResMut<PlayerStateHistory>,
+    config: Res<PlayerConfig>,
+    time: Res<Time>,
+) {
+    for transition in transitions.read() {
+        let (Some(from), Some(to)) = (transition.exited, transition.entered) else {
+            continue;
+        };
+
+        debug!("player {from:?} -> {to:?}");
+        history.0.push_back(Transition { from, to, at: time.elapsed_secs() });
+        while history.0.len() > config.history {
+            history.0.pop_front();
+        }
+    }
+}
+
+// so a frame 'InputSet' skips (e.g. with the console open) doesn't repeat the last one
+pub fn clear_player_input(mut input: ResMut<PlayerInput>) {
+    *input = PlayerInput::default();
+}
+
+pub fn state_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let state = *world.resource::<State<PlayerState>>().get();
+
+    let Some(to) = args.first() else {
+        let history = world.resource::<PlayerStateHistory>();
+        let mut lines = vec![format!("{state:?}")];
+        lines.extend(history.0.iter().rev().map(|t| format!("  {:?} -> {:?} at {:.2}s", t.from, t.to, t.at)));
+        return Ok(lines.join("\n"));
+    };
+
+    let to: PlayerState = to.parse()?;
+    if world.resource::<NoClip>().0 && to != PlayerState::Flying {
+        return Err("noclip is on".to_string());
+    }
+    if !state.can_transition_to(to) {
+        return Err(format!("can't go from {state:?} to {to:?}"));
+    }
+
+    world.send_event(RequestPlayerState(to));
+    Ok(format!("{state:?} -> {to:?}"))
+}
+
+pub fn setup_state_readout(mut commands: Commands) {
+    commands.spawn((
+        Text::default(),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            left: Val::Percent(40.0),
+            ..default()
+        },
+        Visibility::Hidden,
+        PlayerStateText,
+    ));
+}
+
+pub fn update_state_readout(
+    state: Res<State<PlayerState>>,
+    history: Res<PlayerStateHistory>,
+    debug: Res<PlayerDebug>,
+    air: Option<Res<AirState>>,
+    motion: Option<Single<&PlayerMotion>>,
+    text: Single<(&mut Text, &mut Visibility), With<PlayerStateText>>,
+) {
+    let (mut text, mut visibility) = text.into_inner();
+    *visibility = if debug.enabled { Visibility::Visible } else { Visibility::Hidden };
+    if !debug.enabled {
+        return;
+    }
+
+    let speed = motion.map(|motion| motion.velocity.length()).unwrap_or_default();
+    let mut lines = vec![format!("{:?}  {speed:.1} u/s", state.get())];
+    if let Some(air) = air.filter(|air| air.diving || air.combo > 0) {
+        lines.push(format!("{}x{}  {:.0} style", if air.diving { "diving  " } else { "" }, air.combo, air.pending));
+    }
+    lines.extend(history.0.iter().rev().map(|t| format!("{:?} -> {:?}  {:.2}s", t.from, t.to, t.at)));
+    text.0 = lines.join("\n");
+}
+
+#[test]
+fn test_player_transitions() {
+    use PlayerState::*;
+
+    for from in PlayerState::ALL {
+        assert!(!from.can_transition_to(from));
+        assert!(from.can_transition_to(Flying) || from == Flying);
+    }
+
+    assert!(Walking.can_transition_to(Falling) && Falling.can_transition_to(Walking));
+    assert!(Swinging.can_transition_to(Falling));
+    assert!(!Flying.can_transition_to(Swinging));
+    assert!(!Zipping.can_transition_to(Walking));
+
+    assert_eq!("wallcrawling".parse(), Ok(WallCrawling));
+    assert!("running".parse::<PlayerState>().is_err());
+}
@@ -0,0 +1,438 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::camera::{CameraSet, CameraState};
+use crate::console::ConsoleAppExt;
+use crate::health::{Died, HealthSet};
+use crate::physics::cache::default_assets_dir;
+use crate::player::PlayerMotion;
+use crate::tuning::TuningAppExt;
+
+pub const GHOST_DIR: &str = "ghosts";
+pub const GHOST_EXTENSION: &str = "ghost.ron";
+
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct RaceConfig {
+    pub ghost_alpha: f32, // how solid the ghost looks
+    #[reflect(ignore)]
+    pub ghost_files: bool, // read and write best ghosts under 'assets/ghosts'
+    #[reflect(ignore)]
+    pub render: bool, // draw rings and ghosts and show split times, needs the renderer
+}
+
+impl Default for RaceConfig {
+    fn default() -> Self {
+        Self {
+            ghost_alpha: 0.35,
+            ghost_files: true,
+            render: true,
+        }
+    }
+}
+
+impl RaceConfig {
+    pub fn headless() -> Self {
+        Self {
+            ghost_files: false,
+            render: false,
+            ..default()
+        }
+    }
+}
+
+// a checkpoint of a race course, spawned from the level file. it's passed by going through the disc of 'radius' across
+// the transform's forward, the way it faces. going back through doesn't count, so respawning or teleporting back past
+// rings doesn't finish anything
+#[derive(Component, Clone, Debug)]
+#[require(Transform)]
+pub struct RaceRing {
+    pub race: String,
+    pub index: usize, // 0 starts the race, the last finishes it
+    pub radius: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct GhostFrame {
+    pub translation: Vec3,
+    pub rotation: Quat,
+}
+
+// the player's run through a race, one frame every 'tick' seconds from the start ring. stored as RON in
+// 'assets/ghosts/<race>.ghost.ron'
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Ghost {
+    pub race: String,
+    pub time: f32, // seconds from the start ring to the finish
+    pub splits: Vec<f32>, // seconds from the start ring to each ring after it, the last is 'time'
+    pub tick: f32,
+    pub frames: Vec<GhostFrame>,
+}
+
+impl Ghost {
+    // where the ghost is 'time' seconds in, holding still at the end
+    pub fn sample(&self, time: f32) -> Option<GhostFrame> {
+        let last = self.frames.len().checked_sub(1)?;
+        let at = (time / self.tick.max(f32::EPSILON)).clamp(0.0, last as f32);
+        let (i, f) = (at.floor() as usize, at.fract());
+        let (a, b) = (self.frames[i], self.frames[(i + 1).min(last)]);
+
+        Some(GhostFrame { translation: a.translation.lerp(b.translation, f), rotation: a.rotation.slerp(b.rotation, f) })
+    }
+}
+
+pub fn ghost_path_in(race: &str, assets_dir: &Path) -> PathBuf {
+    assets_dir.join(GHOST_DIR).join(format!("{race}.{GHOST_EXTENSION}"))
+}
+
+// returns the path of the written file
+pub fn write_ghost_in(ghost: &Ghost, assets_dir: &Path) -> Result<PathBuf, String> {
+    let path = ghost_path_in(&ghost.race, assets_dir);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|err| format!("failed to create {}: {err}", dir.display()))?;
+    }
+
+    let text = ron::ser::to_string(ghost).map_err(|err| err.to_string())?;
+    std::fs::write(&path, text).map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+    Ok(path)
+}
+
+// 'None' if there isn't one yet
+pub fn read_ghost_in(race: &str, assets_dir: &Path) -> Option<Result<Ghost, String>> {
+    let path = ghost_path_in(race, assets_dir);
+    let text = std::fs::read_to_string(&path).ok()?;
+    Some(ron::de::from_str(&text).map_err(|err| format!("invalid ghost in {}: {err}", path.display())))
+}
+
+// does moving from 'from' to 'to' go through the disc, along 'normal'
+pub fn crosses_ring(from: Vec3, to: Vec3, center: Vec3, normal: Vec3, radius: f32) -> bool {
+    let (a, b) = ((from - center).dot(normal), (to - center).dot(normal));
+    if a >= 0.0 || b < 0.0 {
+        return false;
+    }
+
+    from.lerp(to, a / (a - b)).distance(center) <= radius
+}
+
+// the best ghost of every race run so far, and any read from file
+#[derive(Resource, Default, Debug)]
+pub struct Ghosts(pub HashMap<String, Ghost>);
+
+#[derive(Debug)]
+pub struct Attempt {
+    pub race: String,
+    pub next_ring: usize,
+    pub rings: usize,
+    pub time: f32, // since the start ring
+    pub splits: Vec<f32>,
+    pub frames: Vec<GhostFrame>,
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct RaceState {
+    pub attempt: Option<Attempt>,
+    pub last_split: Option<Split>, // for the HUD
+    last_pos: Option<Vec3>,
+}
+
+impl RaceState {
+    // for when the player's been put somewhere rather than moved there, so it doesn't count as going through the
+    // rings in between
+    pub fn teleported(&mut self) {
+        self.last_pos = None;
+    }
+}
+
+// the best run's ghost, following along during an attempt
+#[derive(Component)]
+pub struct GhostFigure;
+
+#[derive(Event, Clone, Debug)]
+pub struct RaceStarted {
+    pub race: String,
+}
+
+#[derive(Event, Clone, Debug, PartialEq)]
+pub struct Split {
+    pub race: String,
+    pub ring: usize,
+    pub time: f32, // since the start ring
+    pub delta: Option<f32>, // against the best ghost's, negative is ahead
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct RaceFinished {
+    pub race: String,
+    pub time: f32,
+    pub best: Option<f32>, // before this run
+}
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RaceSet;
+
+// time trials over the level's race courses. going through a race's first 'RaceRing' starts (or restarts) it, then
+// every other ring in order is a split and the last finishes it. the player is recorded every fixed tick, and the best
+// run is kept as a 'Ghost' that plays back on later attempts. runs in 'RaceSet' after 'CameraSet' and 'HealthSet',
+// dying ends the attempt
+#[derive(Default)]
+pub struct RacePlugin {
+    pub config: RaceConfig,
+}
+
+impl Plugin for RacePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .init_resource::<Ghosts>()
+            .init_resource::<RaceState>()
+            .add_event::<RaceStarted>()
+            .add_event::<Split>()
+            .add_event::<RaceFinished>()
+            .configure_sets(Update, RaceSet.after(CameraSet).after(HealthSet))
+            .add_systems(FixedUpdate, record_ghost)
+            .add_systems(Update, (end_on_death, pass_rings, move_ghosts).chain().in_set(RaceSet))
+            .add_console_command("race", "", "show the current attempt and best times", race_command)
+            .add_console_command("race abort", "", "stop the current attempt", race_abort_command)
+            .add_tunable::<RaceConfig>("race");
+
+        if self.config.render {
+            app.add_systems(Startup, setup_race_hud)
+                .add_systems(Update, (add_ghost_meshes, draw_rings, update_race_hud).after(RaceSet));
+        }
+    }
+}
+
+pub fn end_on_death(mut died: EventReader<Died>, players: Query<(), With<PlayerMotion>>, mut state: ResMut<RaceState>) {
+    // and they're back at the checkpoint already
+    if died.read().any(|died| players.contains(died.entity)) {
+        state.attempt = None;
+        state.teleported();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn pass_rings(
+    player: Option<Single<&CameraState, With<PlayerMotion>>>,
+    rings: Query<(&RaceRing, &GlobalTransform)>,
+    config: Res<RaceConfig>,
+    mut state: ResMut<RaceState>,
+    mut ghosts: ResMut<Ghosts>,
+    time: Res<Time>,
+    fixed: Res<Time<Fixed>>,
+    mut started: EventWriter<RaceStarted>,
+    mut splits: EventWriter<Split>,
+    mut finished: EventWriter<RaceFinished>,
+) {
+    let Some(pos) = player.map(|player| player.pos) else {
+        return;
+    };
+    let state = &mut *state;
+    let from = state.last_pos.replace(pos).unwrap_or(pos);
+
+    if let Some(attempt) = &mut state.attempt {
+        attempt.time += time.delta_secs();
+    }
+
+    for (ring, transform) in &rings {
+        if !crosses_ring(from, pos, transform.translation(), *transform.forward(), ring.radius) {
+            continue;
+        }
+
+        if ring.index == 0 {
+            let count = rings.iter().filter(|(other, _)| other.race == ring.race).count();
+            state.attempt = Some(Attempt { race: ring.race.clone(), next_ring: 1, rings: count, time: 0.0, splits: Vec::new(), frames: Vec::new() });
+            state.last_split = None;
+            started.send(RaceStarted { race: ring.race.clone() });
+
+            if config.ghost_files && !ghosts.0.contains_key(&ring.race) {
+                match read_ghost_in(&ring.race, &default_assets_dir()) {
+                    Some(Ok(ghost)) => {
+                        ghosts.0.insert(ring.race.clone(), ghost);
+                    }
+                    Some(Err(err)) => warn!("{err}"),
+                    None => {}
+                }
+            }
+            continue;
+        }
+
+        let Some(attempt) = state.attempt.as_mut().filter(|attempt| attempt.race == ring.race && attempt.next_ring == ring.index) else {
+            continue;
+        };
+
+        let best = ghosts.0.get(&ring.race);
+        let split = Split {
+            race: ring.race.clone(),
+            ring: ring.index,
+            time: attempt.time,
+            delta: best.and_then(|best| best.splits.get(attempt.splits.len())).map(|best| attempt.time - best),
+        };
+        attempt.splits.push(attempt.time);
+        attempt.next_ring += 1;
+        state.last_split = Some(split.clone());
+        splits.send(split);
+
+        if attempt.next_ring < attempt.rings {
+            continue;
+        }
+
+        // finished
+        let attempt = state.attempt.take().unwrap();
+        let best = best.map(|best| best.time);
+        info!("Finished '{}' in {:.2}s{}", attempt.race, attempt.time, best.map(|best| format!(", best {best:.2}s")).unwrap_or_default());
+        finished.send(RaceFinished { race: attempt.race.clone(), time: attempt.time, best });
+
+        if best.is_none_or(|best| attempt.time < best) {
+            let ghost = Ghost { race: attempt.race, time: attempt.time, splits: attempt.splits, tick: fixed.timestep().as_secs_f32(), frames: attempt.frames };
+            if config.ghost_files {
+                if let Err(err) = write_ghost_in(&ghost, &default_assets_dir()) {
+                    warn!("Failed to save the ghost for '{}': {err}", ghost.race);
+                }
+            }
+            ghosts.0.insert(ghost.race.clone(), ghost);
+        }
+    }
+}
+
+// at fixed ticks so ghosts play back the same whatever the frame rate was
+pub fn record_ghost(player: Option<Single<(&CameraState, &Transform), With<PlayerMotion>>>, mut state: ResMut<RaceState>) {
+    let (Some(player), Some(attempt)) = (player, state.attempt.as_mut()) else {
+        return;
+    };
+
+    let (cam, transform) = *player;
+    attempt.frames.push(GhostFrame { translation: cam.pos, rotation: transform.rotation });
+}
+
+pub fn move_ghosts(
+    mut figures: Query<(Entity, &mut Transform), With<GhostFigure>>,
+    state: Res<RaceState>,
+    ghosts: Res<Ghosts>,
+    mut commands: Commands,
+) {
+    let playing = state.attempt.as_ref().and_then(|attempt| Some((ghosts.0.get(&attempt.race)?, attempt.time)));
+    let Some((ghost, time)) = playing else {
+        for (entity, _) in &figures {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    };
+
+    let Some(frame) = ghost.sample(time) else {
+        return;
+    };
+
+    match figures.iter_mut().next() {
+        Some((_, mut transform)) => *transform = Transform::from_translation(frame.translation).with_rotation(frame.rotation),
+        None => {
+            commands.spawn((GhostFigure, Transform::from_translation(frame.translation).with_rotation(frame.rotation)));
+        }
+    }
+}
+
+// a translucent stand in for the player, centered on where their eye was
+pub fn add_ghost_meshes(
+    figures: Query<Entity, Added<GhostFigure>>,
+    config: Res<RaceConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut commands: Commands,
+) {
+    for entity in &figures {
+        commands.entity(entity).with_child((
+            Mesh3d(meshes.add(Capsule3d::new(0.3, 1.2))),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: Color::srgba(0.6, 0.8, 1.0, config.ghost_alpha),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            })),
+            Transform::from_translation(Vec3::NEG_Y * 0.7),
+        ));
+    }
+}
+
+// the next ring of the current attempt stands out
+pub fn draw_rings(rings: Query<(&RaceRing, &GlobalTransform)>, state: Res<RaceState>, mut gizmos: Gizmos) {
+    for (ring, transform) in &rings {
+        let next = state.attempt.as_ref().is_some_and(|attempt| attempt.race == ring.race && attempt.next_ring == ring.index);
+        let color = if next { Color::srgb(1.0, 0.8, 0.0) } else { Color::srgb(0.3, 0.6, 1.0) };
+        let rotation = Quat::from_rotation_arc(Vec3::Z, *transform.forward());
+        gizmos.circle(Isometry3d::new(transform.translation(), rotation), ring.radius, color);
+    }
+}
+
+#[derive(Component)]
+pub struct RaceHudText;
+
+pub fn setup_race_hud(mut commands: Commands) {
+    commands.spawn((
+        Text::default(),
+        TextFont {
+            font_size: 18.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            right: Val::Px(5.0),
+            ..default()
+        },
+        RaceHudText,
+    ));
+}
+
+pub fn update_race_hud(state: Res<RaceState>, mut text: Single<&mut Text, With<RaceHudText>>) {
+    let Some(attempt) = &state.attempt else {
+        text.0.clear();
+        return;
+    };
+
+    let mut lines = vec![format!("{}  {:.2}s  ring {}/{}", attempt.race, attempt.time, attempt.next_ring, attempt.rings - 1)];
+    if let Some(split) = &state.last_split {
+        let delta = split.delta.map(|delta| format!("  {delta:+.2}")).unwrap_or_default();
+        lines.push(format!("split {:.2}s{delta}", split.time));
+    }
+    text.0 = lines.join("\n");
+}
+
+pub fn race_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let mut lines = Vec::new();
+    if let Some(attempt) = &world.resource::<RaceState>().attempt {
+        lines.push(format!("racing '{}', {:.2}s, ring {} of {}", attempt.race, attempt.time, attempt.next_ring, attempt.rings - 1));
+    }
+
+    let mut best: Vec<(&String, &Ghost)> = world.resource::<Ghosts>().0.iter().collect();
+    best.sort_by_key(|(race, _)| *race);
+    lines.extend(best.iter().map(|(race, ghost)| format!("'{race}' best {:.2}s", ghost.time)));
+
+    if lines.is_empty() {
+        return Ok("no races run yet".to_string());
+    }
+    Ok(lines.join("\n"))
+}
+
+pub fn race_abort_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let attempt = world.resource_mut::<RaceState>().attempt.take().ok_or("not racing")?;
+    Ok(format!("stopped '{}'", attempt.race))
+}
+
+#[test]
+fn test_ghost() {
+    assert!(crosses_ring(Vec3::new(-1.0, 0.5, 0.0), Vec3::new(1.0, 0.5, 0.0), Vec3::ZERO, Vec3::X, 1.0));
+    assert!(!crosses_ring(Vec3::new(1.0, 0.5, 0.0), Vec3::new(-1.0, 0.5, 0.0), Vec3::ZERO, Vec3::X, 1.0));
+    assert!(!crosses_ring(Vec3::new(-1.0, 1.5, 0.0), Vec3::new(1.0, 1.5, 0.0), Vec3::ZERO, Vec3::X, 1.0));
+    assert!(!crosses_ring(Vec3::new(-2.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0), Vec3::ZERO, Vec3::X, 1.0));
+
+    let frame = |x: f32| GhostFrame { translation: Vec3::new(x, 0.0, 0.0), rotation: Quat::IDENTITY };
+    let ghost = Ghost { race: "test".to_string(), time: 0.2, splits: vec![0.1, 0.2], tick: 0.1, frames: vec![frame(0.0), frame(1.0), frame(3.0)] };
+    assert_eq!(ghost.sample(0.15).unwrap().translation, Vec3::new(2.0, 0.0, 0.0));
+    assert_eq!(ghost.sample(5.0).unwrap().translation, Vec3::new(3.0, 0.0, 0.0));
+
+    let dir = std::env::temp_dir().join(format!("spiderman-ghost-{}", std::process::id()));
+    let path = write_ghost_in(&ghost, &dir).unwrap();
+    assert!(path.ends_with("ghosts/test.ghost.ron"));
+    assert_eq!(read_ghost_in("test", &dir).unwrap().unwrap(), ghost);
+    assert!(read_ghost_in("missing", &dir).is_none());
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,196 @@
+use bevy::log::Level;
+use bevy::prelude::*;
+use bevy::window::{MonitorSelection, PresentMode, WindowMode, WindowResolution};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// read from 'SETTINGS_FILE' if there is one, anything not in it keeps its default
+pub const SETTINGS_FILE: &str = "settings.ron";
+
+pub const USAGE: &str = "usage: spiderman [options]
+
+  --settings <file>       settings file to read, default settings.ron
+  --level <path>          level to load, relative to assets
+  --window-mode <mode>    windowed, borderless or fullscreen
+  --resolution <WxH>      window size, e.g. 1920x1080
+  --vsync, --no-vsync
+  --log-level <level>     error, warn, info, debug or trace
+  --render <setup>        debug (wireframes and collider overlays) or release
+  --headless              run the simulation without a window or renderer";
+
+// which rendering extras the game starts with. debug adds wireframes (where the adapter can draw them) and the collider overlays
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderSetup {
+    Debug,
+    Release,
+}
+
+impl RenderSetup {
+    pub fn is_debug(self) -> bool {
+        self == RenderSetup::Debug
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowModeSetting {
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+// startup options, from the settings file then the command line. inserted as a resource for anything that wants them
+#[derive(Resource, Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub level: String, // asset path
+    pub window_title: String,
+    pub window_mode: WindowModeSetting,
+    pub resolution: (f32, f32),
+    pub vsync: bool,
+    pub log_level: String, // anything 'bevy::log::Level' parses
+    pub log_filter: String, // 'EnvFilter' directives added on top of 'log_level'
+    pub shadow_map_size: usize,
+    pub clear_color: [f32; 3], // srgb, 0 to 1
+    pub render: RenderSetup,
+    pub headless: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            level: "levels/island1.level.ron".to_string(),
+            window_title: "Spiderman".to_string(),
+            window_mode: WindowModeSetting::Windowed,
+            resolution: (1280.0, 720.0),
+            vsync: true,
+            log_level: "trace".to_string(),
+            log_filter: "wgpu=warn,naga=warn,wgpu_hal=warn,bevy_app=warn,offset_allocator=error,bevy_render=info".to_string(),
+            shadow_map_size: 2048,
+            clear_color: [115.0 / 255.0, 121.0 / 255.0, 121.0 / 255.0],
+            render: if cfg!(debug_assertions) { RenderSetup::Debug } else { RenderSetup::Release },
+            headless: false,
+        }
+    }
+}
+
+impl Settings {
+    // the settings file ('--settings' or 'SETTINGS_FILE'), overridden by the rest of the command line.
+    // a missing default settings file is fine, a missing file asked for with '--settings' isn't
+    pub fn load(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let args: Vec<String> = args.into_iter().collect();
+
+        let path = args.iter().position(|arg| arg == "--settings").map(|i| {
+            args.get(i + 1).map(PathBuf::from).ok_or("--settings needs a file")
+        });
+
+        let mut settings = match path {
+            Some(path) => Self::read(&path?)?,
+            None if Path::new(SETTINGS_FILE).exists() => Self::read(Path::new(SETTINGS_FILE))?,
+            None => Self::default(),
+        };
+
+        settings.apply_args(&args)?;
+        Ok(settings)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+        let settings: Settings = ron::de::from_str(&text).map_err(|err| format!("invalid settings in {}: {err}", path.display()))?;
+        settings.log_level()?;
+        Ok(settings)
+    }
+
+    pub fn apply_args(&mut self, args: &[String]) -> Result<(), String> {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().cloned().ok_or(format!("{name} needs a value"));
+
+            match arg.as_str() {
+                "--settings" => {
+                    value("--settings")?; // already read in 'load'
+                }
+                "--level" => self.level = value("--level")?,
+                "--window-mode" => {
+                    self.window_mode = match value("--window-mode")?.as_str() {
+                        "windowed" => WindowModeSetting::Windowed,
+                        "borderless" => WindowModeSetting::Borderless,
+                        "fullscreen" => WindowModeSetting::Fullscreen,
+                        other => return Err(format!("unknown window mode '{other}'")),
+                    }
+                }
+                "--resolution" => {
+                    let resolution = value("--resolution")?;
+                    let (w, h) = resolution.split_once('x').ok_or(format!("resolution '{resolution}' should look like 1920x1080"))?;
+                    let parse = |n: &str| n.parse::<f32>().ok().filter(|n| *n > 0.0).ok_or(format!("invalid resolution '{resolution}'"));
+                    self.resolution = (parse(w)?, parse(h)?);
+                }
+                "--vsync" => self.vsync = true,
+                "--no-vsync" => self.vsync = false,
+                "--log-level" => {
+                    self.log_level = value("--log-level")?;
+                    self.log_level()?;
+                }
+                "--render" => {
+                    self.render = match value("--render")?.as_str() {
+                        "debug" => RenderSetup::Debug,
+                        "release" => RenderSetup::Release,
+                        other => return Err(format!("--render takes 'debug' or 'release', got '{other}'")),
+                    }
+                }
+                "--headless" => self.headless = true,
+                _ => return Err(format!("unknown argument '{arg}'")),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn log_level(&self) -> Result<Level, String> {
+        Level::from_str(&self.log_level).map_err(|_| format!("unknown log level '{}'", self.log_level))
+    }
+
+    pub fn window(&self) -> Window {
+        Window {
+            title: self.window_title.clone(),
+            mode: match self.window_mode {
+                WindowModeSetting::Windowed => WindowMode::Windowed,
+                WindowModeSetting::Borderless => WindowMode::BorderlessFullscreen(MonitorSelection::Current),
+                WindowModeSetting::Fullscreen => WindowMode::Fullscreen(MonitorSelection::Current),
+            },
+            resolution: WindowResolution::new(self.resolution.0, self.resolution.1),
+            present_mode: if self.vsync { PresentMode::AutoVsync } else { PresentMode::AutoNoVsync },
+            ..default()
+        }
+    }
+
+    pub fn clear_color(&self) -> Color {
+        Color::srgb(self.clear_color[0], self.clear_color[1], self.clear_color[2])
+    }
+}
+
+#[test]
+fn test_settings_args() {
+    let args = |s: &str| s.split_whitespace().map(String::from).collect::<Vec<_>>();
+
+    let file: Settings = ron::de::from_str(r#"(level: "levels/test.level.ron", vsync: false, render: release)"#).unwrap();
+    assert_eq!(file.level, "levels/test.level.ron");
+    assert_eq!(file.window_title, "Spiderman");
+
+    let mut settings = file.clone();
+    settings.apply_args(&args("--resolution 1920x1080 --vsync --log-level info --window-mode borderless --headless")).unwrap();
+    assert_eq!(settings.resolution, (1920.0, 1080.0));
+    assert!(settings.vsync && settings.headless);
+    assert_eq!(settings.log_level(), Ok(Level::INFO));
+    assert_eq!(settings.window_mode, WindowModeSetting::Borderless);
+    assert_eq!(settings.render, RenderSetup::Release);
+
+    let mut settings = file;
+    assert!(settings.apply_args(&args("--resolution 1920")).is_err());
+    assert!(settings.apply_args(&args("--log-level loud")).is_err());
+    assert!(settings.apply_args(&args("--render fancy")).is_err());
+    assert!(settings.apply_args(&args("--level")).is_err());
+    assert!(settings.apply_args(&args("--fly")).is_err());
+}
@@ -0,0 +1,437 @@
+use bevy::prelude::*;
+use bevy::reflect::serde::{TypedReflectDeserializer, TypedReflectSerializer};
+use bevy::reflect::{GetPath, GetTypeRegistration, TypeInfo, TypeRegistry, Typed};
+use serde::de::{DeserializeSeed, IgnoredAny, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserializer, Serialize, Serializer};
+use std::any::TypeId;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::console::{console_closed, ConsoleAppExt};
+use crate::input::InputSet;
+use crate::physics::cache::default_assets_dir;
+
+// saved and loaded from 'assets/tuning.ron'. one entry per tunable resource, holding only the fields it wants to change
+pub const TUNING_FILE: &str = "tuning.ron";
+
+// a resource whose fields can be changed while playing, reachable as '<prefix>.<field>'
+struct Tunable {
+    type_id: TypeId,
+    fields: Vec<String>,
+    read: fn(&World) -> Option<&dyn Reflect>,
+    write: fn(&mut World) -> Option<&mut dyn Reflect>,
+}
+
+// every resource registered with 'add_tunable', by prefix
+#[derive(Resource, Default)]
+pub struct Tunables(BTreeMap<String, Tunable>);
+
+impl Tunables {
+    // 'R' has to be a struct, only its top level fields are listed. '#[reflect(ignore)]' the ones that aren't tuning
+    pub fn add<R: Resource + Reflect + Typed>(&mut self, prefix: &str) {
+        let fields = match R::type_info() {
+            TypeInfo::Struct(info) => info.field_names().iter().map(|field| field.to_string()).collect(),
+            _ => Vec::new(),
+        };
+
+        self.0.insert(prefix.to_string(), Tunable {
+            type_id: TypeId::of::<R>(),
+            fields,
+            read: |world| world.get_resource::<R>().map(|resource| resource as &dyn Reflect),
+            write: |world| world.get_resource_mut::<R>().map(|resource| resource.into_inner() as &mut dyn Reflect),
+        });
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = String> + '_ {
+        self.0.iter().flat_map(|(prefix, tunable)| tunable.fields.iter().map(move |field| format!("{prefix}.{field}")))
+    }
+}
+
+pub trait TuningAppExt {
+    fn add_tunable<R: Resource + Reflect + Typed + GetTypeRegistration>(&mut self, prefix: &str) -> &mut Self;
+}
+
+impl TuningAppExt for App {
+    fn add_tunable<R: Resource + Reflect + Typed + GetTypeRegistration>(&mut self, prefix: &str) -> &mut Self {
+        self.register_type::<R>().init_resource::<Tunables>();
+        self.world_mut().resource_mut::<Tunables>().add::<R>(prefix);
+        self
+    }
+}
+
+fn split_path(path: &str) -> Result<(&str, &str), String> {
+    path.split_once('.').ok_or(format!("'{path}' should look like <prefix>.<field>"))
+}
+
+pub fn tunable_field<'w>(world: &'w World, path: &str) -> Result<&'w dyn PartialReflect, String> {
+    let (prefix, field) = split_path(path)?;
+    let read = world.resource::<Tunables>().0.get(prefix).map(|tunable| tunable.read).ok_or(format!("unknown var '{path}'"))?;
+    let resource = read(world).ok_or(format!("'{prefix}' isn't there right now"))?;
+
+    resource.reflect_path(field).map_err(|_| format!("unknown var '{path}'"))
+}
+
+// 'write' is looked up first so it doesn't borrow 'world' along with the result
+pub fn tunable_field_mut<'w>(world: &'w mut World, path: &str) -> Result<&'w mut dyn PartialReflect, String> {
+    let (prefix, field) = split_path(path)?;
+    let write = world.resource::<Tunables>().0.get(prefix).map(|tunable| tunable.write).ok_or(format!("unknown var '{path}'"))?;
+    let resource = write(world).ok_or(format!("'{prefix}' isn't there right now"))?;
+
+    resource.reflect_path_mut(field).map_err(|_| format!("unknown var '{path}'"))
+}
+
+// the types that can be typed in
+pub fn set_field(field: &mut dyn PartialReflect, value: &str) -> Result<(), String> {
+    fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+        value.parse().map_err(|_| format!("invalid value '{value}' for a {}", std::any::type_name::<T>()))
+    }
+
+    if let Some(field) = field.try_downcast_mut::<f32>() {
+        *field = parse(value)?;
+    } else if let Some(field) = field.try_downcast_mut::<f64>() {
+        *field = parse(value)?;
+    } else if let Some(field) = field.try_downcast_mut::<bool>() {
+        *field = parse(value)?;
+    } else if let Some(field) = field.try_downcast_mut::<usize>() {
+        *field = parse(value)?;
+    } else if let Some(field) = field.try_downcast_mut::<u32>() {
+        *field = parse(value)?;
+    } else if let Some(field) = field.try_downcast_mut::<i32>() {
+        *field = parse(value)?;
+    } else if let Some(field) = field.try_downcast_mut::<String>() {
+        *field = value.to_string();
+    } else {
+        return Err(format!("can't set a {} from text", field.reflect_type_path()));
+    }
+
+    Ok(())
+}
+
+// the inspector's left and right. floats move by 5% (at least 0.01), integers by one (unsigned ones stop at 0) and
+// bools flip. returns false for anything else. limits on a field are up to whatever uses it
+pub fn nudge_field(field: &mut dyn PartialReflect, steps: i32) -> bool {
+    if let Some(field) = field.try_downcast_mut::<f32>() {
+        *field += steps as f32 * (field.abs() * 0.05).max(0.01);
+    } else if let Some(field) = field.try_downcast_mut::<f64>() {
+        *field += steps as f64 * (field.abs() * 0.05).max(0.01);
+    } else if let Some(field) = field.try_downcast_mut::<usize>() {
+        *field = field.saturating_add_signed(steps as isize);
+    } else if let Some(field) = field.try_downcast_mut::<u32>() {
+        *field = field.saturating_add_signed(steps);
+    } else if let Some(field) = field.try_downcast_mut::<i32>() {
+        *field += steps;
+    } else if let Some(field) = field.try_downcast_mut::<bool>() {
+        *field = !*field;
+    } else {
+        return false;
+    }
+
+    true
+}
+
+struct TuningSerializer<'a> {
+    resources: Vec<(&'a str, &'a dyn Reflect)>,
+    registry: &'a TypeRegistry,
+}
+
+impl Serialize for TuningSerializer<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.resources.len()))?;
+        for (prefix, resource) in &self.resources {
+            map.serialize_entry(prefix, &TypedReflectSerializer::new(resource.as_partial_reflect(), self.registry))?;
+        }
+        map.end()
+    }
+}
+
+// every tunable resource as RON
+pub fn tuning_to_ron(world: &World) -> Result<String, String> {
+    let tunables = world.resource::<Tunables>();
+    let registry = world.resource::<AppTypeRegistry>().read();
+
+    let resources = tunables.0.iter().filter_map(|(prefix, tunable)| Some((prefix.as_str(), (tunable.read)(world)?))).collect();
+    let serializer = TuningSerializer { resources, registry: &registry };
+    ron::ser::to_string_pretty(&serializer, ron::ser::PrettyConfig::default()).map_err(|err| err.to_string())
+}
+
+// reads the map in 'tuning_to_ron' into dynamic values, to be applied once the registry isn't borrowed
+struct TuningVisitor<'a> {
+    tunables: &'a Tunables,
+    registry: &'a TypeRegistry,
+}
+
+impl<'de> Visitor<'de> for TuningVisitor<'_> {
+    type Value = Vec<(String, Box<dyn PartialReflect>)>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of tunable resources")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut values = Vec::new();
+        while let Some(prefix) = map.next_key::<String>()? {
+            let registration = self.tunables.0.get(&prefix).and_then(|tunable| self.registry.get(tunable.type_id));
+            let Some(registration) = registration else {
+                warn!("Skipping unknown tunable '{prefix}'");
+                map.next_value::<IgnoredAny>()?;
+                continue;
+            };
+
+            values.push((prefix, map.next_value_seed(TypedReflectDeserializer::new(registration, self.registry))?));
+        }
+        Ok(values)
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for TuningVisitor<'_> {
+    type Value = Vec<(String, Box<dyn PartialReflect>)>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+// sets whatever fields 'text' has, the rest keep their values
+pub fn apply_tuning_ron(world: &mut World, text: &str) -> Result<(), String> {
+    let values = {
+        let tunables = world.resource::<Tunables>();
+        let registry = world.resource::<AppTypeRegistry>().read();
+        let mut deserializer = ron::Deserializer::from_str(text).map_err(|err| err.to_string())?;
+        TuningVisitor { tunables, registry: &registry }.deserialize(&mut deserializer).map_err(|err| err.to_string())?
+    };
+
+    for (prefix, value) in values {
+        let write = world.resource::<Tunables>().0[&prefix].write;
+        if let Some(resource) = write(world) {
+            resource.try_apply(value.as_ref()).map_err(|err| format!("{prefix}: {err}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct TuningConfig {
+    pub file: Option<PathBuf>, // read on startup and written by 'tuning save', nothing touches the disk without it
+    pub inspector: bool, // the F4 panel, needs the renderer
+}
+
+impl Default for TuningConfig {
+    fn default() -> Self {
+        Self {
+            file: Some(default_assets_dir().join(TUNING_FILE)),
+            inspector: true,
+        }
+    }
+}
+
+impl TuningConfig {
+    pub fn headless() -> Self {
+        Self {
+            file: None,
+            inspector: false,
+        }
+    }
+}
+
+// which row of the inspector is selected
+#[derive(Resource, Default, Debug)]
+pub struct Inspector {
+    pub open: bool,
+    pub selected: usize,
+}
+
+#[derive(Component)]
+pub struct InspectorText;
+
+// reflected settings from every plugin ('add_tunable'), the tuning file and the inspector:
+// F4 shows it, up and down pick a value, left and right change it (shift for 10x) and F5 saves
+#[derive(Default)]
+pub struct TuningPlugin {
+    pub config: TuningConfig,
+}
+
+impl Plugin for TuningPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .init_resource::<Tunables>()
+            .add_systems(Startup, load_tuning_file)
+            .add_console_command("tuning save", "[file]", "write every tunable to the tuning file", |world, args| {
+                let path = tuning_path(world, args)?;
+                save_tuning(world, &path)?;
+                Ok(format!("saved {}", path.display()))
+            })
+            .add_console_command("tuning load", "[file]", "read the tuning file again", |world, args| {
+                let path = tuning_path(world, args)?;
+                load_tuning(world, &path)?;
+                Ok(format!("loaded {}", path.display()))
+            });
+
+        if self.config.inspector {
+            app.init_resource::<Inspector>()
+                .add_systems(Startup, setup_inspector)
+                .add_systems(Update, (inspector_input.run_if(console_closed), update_inspector_text).chain().after(InputSet));
+        }
+    }
+}
+
+fn tuning_path(world: &World, args: &[&str]) -> Result<PathBuf, String> {
+    match args.first() {
+        Some(file) => Ok(PathBuf::from(file)),
+        None => world.resource::<TuningConfig>().file.clone().ok_or("there's no tuning file".to_string()),
+    }
+}
+
+pub fn save_tuning(world: &World, path: &std::path::Path) -> Result<(), String> {
+    let text = tuning_to_ron(world)?;
+    std::fs::write(path, text).map_err(|err| format!("failed to write {}: {err}", path.display()))
+}
+
+pub fn load_tuning(world: &mut World, path: &std::path::Path) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+    apply_tuning_ron(world, &text).map_err(|err| format!("invalid tuning in {}: {err}", path.display()))
+}
+
+// runs after every plugin has added its tunables. a missing file just means nothing's been tuned yet
+pub fn load_tuning_file(world: &mut World) {
+    let Some(path) = world.resource::<TuningConfig>().file.clone() else {
+        return;
+    };
+
+    if !path.exists() {
+        return;
+    }
+
+    if let Err(err) = load_tuning(world, &path) {
+        warn!("{err}");
+    }
+}
+
+pub fn setup_inspector(mut commands: Commands) {
+    commands.spawn((
+        Text::default(),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            right: Val::Px(5.0),
+            padding: UiRect::all(Val::Px(5.0)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.7)),
+        Visibility::Hidden,
+        InspectorText,
+    ));
+}
+
+// exclusive, since the values live in whichever resources registered them
+pub fn inspector_input(world: &mut World) {
+    let input = world.resource::<ButtonInput<KeyCode>>();
+    let toggle = input.just_pressed(KeyCode::F4);
+    let save = input.just_pressed(KeyCode::F5);
+    let up = input.just_pressed(KeyCode::ArrowUp);
+    let down = input.just_pressed(KeyCode::ArrowDown);
+    let scale = if input.pressed(KeyCode::ShiftLeft) { 10 } else { 1 };
+    let steps = (input.just_pressed(KeyCode::ArrowRight) as i32 - input.just_pressed(KeyCode::ArrowLeft) as i32) * scale;
+
+    let paths: Vec<String> = world.resource::<Tunables>().paths().collect();
+    let selected = {
+        let mut inspector = world.resource_mut::<Inspector>();
+        if toggle {
+            inspector.open = !inspector.open;
+        }
+
+        if !inspector.open || paths.is_empty() {
+            return;
+        }
+
+        if up {
+            inspector.selected = inspector.selected.checked_sub(1).unwrap_or(paths.len() - 1);
+        }
+        if down {
+            inspector.selected = (inspector.selected + 1) % paths.len();
+        }
+        inspector.selected = inspector.selected.min(paths.len() - 1);
+        inspector.selected
+    };
+    let path = &paths[selected];
+
+    if steps != 0 {
+        if let Ok(field) = tunable_field_mut(world, path) {
+            if !nudge_field(field, steps) {
+                info!("{path} can't be changed from the inspector, try 'set' in the console");
+            }
+        }
+    }
+
+    if save {
+        match tuning_path(world, &[]).and_then(|path| save_tuning(world, &path).map(|_| path)) {
+            Ok(path) => info!("Saved tuning to {}", path.display()),
+            Err(err) => warn!("{err}"),
+        }
+    }
+}
+
+pub fn update_inspector_text(world: &mut World) {
+    let (open, selected) = {
+        let inspector = world.resource::<Inspector>();
+        (inspector.open, inspector.selected)
+    };
+
+    let text = open.then(|| {
+        let mut lines = vec!["tuning: arrows change, F5 saves".to_string()];
+        for (i, path) in world.resource::<Tunables>().paths().enumerate() {
+            let value = tunable_field(world, &path).map(|field| format!("{field:?}")).unwrap_or_else(|err| err);
+            let cursor = if i == selected { ">" } else { " " };
+            lines.push(format!("{cursor} {path} = {value}"));
+        }
+        lines.join("\n")
+    });
+
+    let mut query = world.query_filtered::<(&mut Text, &mut Visibility), With<InspectorText>>();
+    let Ok((mut inspector_text, mut visibility)) = query.get_single_mut(world) else {
+        return;
+    };
+
+    *visibility = if open { Visibility::Visible } else { Visibility::Hidden };
+    if let Some(text) = text {
+        inspector_text.0 = text;
+    }
+}
+
+#[test]
+fn test_tuning_round_trip() {
+    #[derive(Resource, Reflect, Default, Debug, PartialEq)]
+    struct Swing {
+        max_length: f32,
+        segments: usize,
+        #[reflect(ignore)]
+        name: String,
+    }
+
+    let mut app = App::new();
+    app.insert_resource(Swing { max_length: 40.0, segments: 8, name: "swing".to_string() })
+        .add_tunable::<Swing>("swing");
+    let world = app.world_mut();
+
+    assert_eq!(world.resource::<Tunables>().paths().collect::<Vec<_>>(), ["swing.max_length", "swing.segments"]);
+
+    let saved = tuning_to_ron(world).unwrap();
+    set_field(tunable_field_mut(world, "swing.max_length").unwrap(), "60").unwrap();
+    assert!(nudge_field(tunable_field_mut(world, "swing.segments").unwrap(), -10));
+    assert_eq!(world.resource::<Swing>(), &Swing { max_length: 60.0, segments: 0, name: "swing".to_string() });
+
+    apply_tuning_ron(world, &saved).unwrap();
+    assert_eq!(world.resource::<Swing>(), &Swing { max_length: 40.0, segments: 8, name: "swing".to_string() });
+
+    // fields that aren't there are left alone, unknown resources are skipped
+    apply_tuning_ron(world, r#"{"swing": (segments: 3), "gone": (speed: 1.0)}"#).unwrap();
+    assert_eq!(world.resource::<Swing>().segments, 3);
+    assert_eq!(world.resource::<Swing>().max_length, 40.0);
+    assert!(apply_tuning_ron(world, r#"{"swing": (max_length: "far")}"#).is_err());
+}
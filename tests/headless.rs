@@ -0,0 +1,684 @@
+// gameplay running in 'headless_app', driven by pressing keys and ticking the app
+
+use bevy::ecs::event::EventCursor;
+use bevy::input::keyboard::{Key, KeyboardInput, NativeKey};
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+
+use spiderman::aim::{AimConfig, AimTarget};
+use spiderman::air::{AirState, Style};
+use spiderman::anchors::Anchors;
+use spiderman::app::{headless_app, TICK};
+use spiderman::camera::CameraState;
+use spiderman::combat::{Hit, HitKind, StuckWeb, WebShot};
+use spiderman::console::{run_console_command, ConsoleState};
+use spiderman::enemy::{Enemy, EnemySpawn, EnemyTypesFile, Webbed};
+use spiderman::enemy::behaviour::{Action, EnemyTypes};
+use spiderman::game::spawn_player;
+use spiderman::health::{Checkpoint, Damage, DamageKind, Health};
+use spiderman::level::{EnemyDef, LevelEntity};
+use spiderman::mission::{ActiveMission, FailCondition, FailReason, Goal, Mission, MissionEntity, MissionOutcome, Objective, Reward};
+use spiderman::physics::CollisionConfig;
+use spiderman::physics::cache::{cache_file_path, mesh_hash, write_cache, CollisionTreeCache};
+use spiderman::physics::collision::{build_collision_tree, Collidable, PendingCollisionTree, RecursiveAABB, Triangles, TRIANGLE_LIMIT};
+use spiderman::physics::dynamic::DynamicCollider;
+use spiderman::physics::layers::{CollisionLayers, Layers};
+use spiderman::physics::triggers::{Trigger, TriggerEntered, TriggerExited, TriggerShape};
+use spiderman::player::{PlayerConfig, PlayerMotion, PlayerState, PlayerStateHistory, RequestPlayerState};
+use spiderman::race::{GhostFigure, Ghosts, RaceRing, RaceState};
+
+fn player_at(app: &mut App, pos: Vec3) -> Entity {
+    let mut commands = app.world_mut().commands();
+    let player = spawn_player(&mut commands, pos);
+    app.world_mut().flush();
+    player
+}
+
+fn player_pos(app: &mut App) -> Vec3 {
+    app.world_mut().query::<&CameraState>().single(app.world()).pos
+}
+
+// a key going down and up again over one update, for anything that checks 'just_pressed'
+fn tap(app: &mut App, key: KeyCode) {
+    for state in [ButtonState::Pressed, ButtonState::Released] {
+        app.world_mut().send_event(KeyboardInput { key_code: key, logical_key: Key::Unidentified(NativeKey::Unidentified), state, repeat: false, window: Entity::PLACEHOLDER });
+    }
+    app.update();
+}
+
+fn ticks(app: &mut App, n: usize) {
+    for _ in 0..n {
+        app.update();
+    }
+}
+
+// enemies only spawn once 'enemies.ron' is in, which the asset server reads off the main thread
+fn load_enemy_types(app: &mut App) {
+    for _ in 0..200 {
+        app.update();
+        if !app.world().resource::<EnemyTypesFile>().loading {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    panic!("enemies.ron never loaded");
+}
+
+// a 10x1x10 box with its top at y = 0
+fn platform(app: &mut App, pos: Vec3) -> Entity {
+    let triangles: Vec<Triangle3d> = Mesh::from(Cuboid::new(10.0, 1.0, 10.0)).triangles().unwrap().map(|t| {
+        Triangle3d::new(t.vertices[0] - Vec3::Y * 0.5, t.vertices[1] - Vec3::Y * 0.5, t.vertices[2] - Vec3::Y * 0.5)
+    }).collect();
+    let tree = build_collision_tree(&triangles, TRIANGLE_LIMIT);
+
+    app.world_mut().spawn((tree, Triangles(triangles), Transform::from_translation(pos), DynamicCollider::default())).id()
+}
+
+#[test]
+fn test_fly_forward() {
+    let mut app = headless_app();
+    player_at(&mut app, Vec3::ZERO);
+    // the first update only starts the clock
+    ticks(&mut app, 1);
+
+    // yaw 0 looks down +x
+    app.world_mut().resource_mut::<ButtonInput<KeyCode>>().press(KeyCode::KeyW);
+    ticks(&mut app, 60);
+
+    let pos = player_pos(&mut app);
+    let expected = 5.0 * 60.0 * TICK.as_secs_f32();
+    assert!((pos.x - expected).abs() < 1e-3, "{pos}");
+    assert!(pos.y.abs() < 1e-3 && pos.z.abs() < 1e-3, "{pos}");
+}
+
+#[test]
+fn test_carried_by_moving_platform() {
+    let mut app = headless_app();
+    let platform = platform(&mut app, Vec3::ZERO);
+    player_at(&mut app, Vec3::new(1.0, 1.0, 1.0));
+    ticks(&mut app, 2);
+
+    for _ in 0..30 {
+        app.world_mut().get_mut::<Transform>(platform).unwrap().translation.x += 0.1;
+        app.update();
+    }
+
+    // the player follows one tick behind, see 'carry_player'
+    let pos = player_pos(&mut app);
+    assert!((pos.x - (1.0 + 29.0 * 0.1)).abs() < 1e-3, "{pos}");
+    assert!((pos.y - 1.0).abs() < 1e-3, "{pos}");
+}
+
+#[test]
+fn test_fly_through_trigger() {
+    let mut app = headless_app();
+    let player = player_at(&mut app, Vec3::ZERO);
+    let trigger = app.world_mut().spawn((Trigger::new("gate", TriggerShape::Box { half_extents: Vec3::ONE }), Transform::from_xyz(5.0, 0.0, 0.0))).id();
+
+    let mut entered = EventCursor::<TriggerEntered>::default();
+    let mut exited = EventCursor::<TriggerExited>::default();
+    let (mut entered_at, mut exited_at) = (None, None);
+
+    app.world_mut().resource_mut::<ButtonInput<KeyCode>>().press(KeyCode::KeyW);
+    for tick in 0..180 {
+        app.update();
+
+        let world = app.world();
+        if entered.read(world.resource::<Events<TriggerEntered>>()).any(|e| e.trigger == trigger && e.entity == player) {
+            entered_at = Some(tick);
+        }
+        if exited.read(world.resource::<Events<TriggerExited>>()).any(|e| e.trigger == trigger && e.entity == player) {
+            exited_at = Some(tick);
+        }
+    }
+
+    // enters at x = 4, leaves after x = 6, at 5 units a second
+    let (entered_at, exited_at) = (entered_at.expect("never entered"), exited_at.expect("never exited"));
+    assert!((47..=49).contains(&entered_at), "{entered_at}");
+    assert!((71..=73).contains(&exited_at), "{exited_at}");
+}
+
+#[test]
+fn test_console_commands() {
+    let mut app = headless_app();
+    player_at(&mut app, Vec3::ZERO);
+    ticks(&mut app, 1);
+
+    // typed lines run on the next update
+    app.world_mut().resource_mut::<ConsoleState>().submit("teleport 1 2 3");
+    app.world_mut().resource_mut::<ConsoleState>().submit("set player.fly_speed 10");
+    ticks(&mut app, 1);
+    assert_eq!(player_pos(&mut app), Vec3::new(1.0, 2.0, 3.0));
+    assert_eq!(app.world().resource::<PlayerConfig>().fly_speed, 10.0);
+
+    // teleporting drops the player out of flying, like a respawn
+    ticks(&mut app, 2);
+    assert_eq!(*app.world().resource::<State<PlayerState>>().get(), PlayerState::Falling);
+    run_console_command(app.world_mut(), "state flying").unwrap();
+    ticks(&mut app, 2);
+
+    // half speed time moves the player half as far
+    run_console_command(app.world_mut(), "timescale 0.5").unwrap();
+    app.world_mut().resource_mut::<ButtonInput<KeyCode>>().press(KeyCode::KeyW);
+    ticks(&mut app, 60);
+    let pos = player_pos(&mut app);
+    assert!((pos.x - (1.0 + 0.5 * 10.0 * 60.0 * TICK.as_secs_f32())).abs() < 1e-3, "{pos}");
+
+    // gameplay input stops while the console is open
+    app.world_mut().resource_mut::<ConsoleState>().open = true;
+    ticks(&mut app, 10);
+    assert_eq!(player_pos(&mut app), pos);
+
+    assert!(run_console_command(app.world_mut(), "collider.draw depth 3").is_ok());
+    assert!(run_console_command(app.world_mut(), "teleport 1 2").is_err());
+}
+
+#[test]
+fn test_noclip() {
+    let mut app = headless_app();
+    let platform = platform(&mut app, Vec3::ZERO);
+    player_at(&mut app, Vec3::new(1.0, 1.7, 1.0));
+    app.world_mut().send_event(RequestPlayerState(PlayerState::Walking));
+    ticks(&mut app, 2);
+    assert_eq!(*app.world().resource::<State<PlayerState>>().get(), PlayerState::Walking);
+
+    // back to flying, and it stays there
+    run_console_command(app.world_mut(), "noclip").unwrap();
+    ticks(&mut app, 2);
+    app.world_mut().send_event(RequestPlayerState(PlayerState::Falling));
+    ticks(&mut app, 2);
+    assert_eq!(*app.world().resource::<State<PlayerState>>().get(), PlayerState::Flying);
+    assert!(run_console_command(app.world_mut(), "state walking").is_err());
+
+    // straight down through the platform, which doesn't take the player with it
+    app.world_mut().resource_mut::<ButtonInput<KeyCode>>().press(KeyCode::ShiftLeft);
+    for _ in 0..60 {
+        app.world_mut().get_mut::<Transform>(platform).unwrap().translation.x += 0.1;
+        app.update();
+    }
+    let pos = player_pos(&mut app);
+    assert!(pos.y < -1.0, "{pos}");
+    assert!((pos.x - 1.0).abs() < 1e-3, "{pos}");
+}
+
+#[test]
+fn test_shared_collision_cache() {
+    let mut app = headless_app();
+    app.world_mut().resource_mut::<CollisionConfig>().cache = true;
+    player_at(&mut app, Vec3::new(0.0, 20.0, 0.0));
+
+    // an odd size so it's nobody else's cache
+    let mesh = Mesh::from(Cuboid::new(1.25, 0.75, 3.5));
+    let triangles: Vec<Triangle3d> = mesh.triangles().unwrap().collect();
+    let hash = mesh_hash(&triangles);
+    write_cache(&CollisionTreeCache { mesh_hash: hash, tree: build_collision_tree(&triangles, TRIANGLE_LIMIT), triangles }).unwrap();
+
+    // the same mesh in two scenes, both read the one cache file: scene root -- root node -- node -- mesh
+    let handle = app.world_mut().resource_mut::<Assets<Mesh>>().add(mesh);
+    let instances: Vec<Entity> = (0..2).map(|_| {
+        let world = app.world_mut();
+        let mesh = world.spawn((Mesh3d(handle.clone()), Name::new("box"), Transform::default())).id();
+        let node = world.spawn(Transform::default()).add_child(mesh).id();
+        let root = world.spawn(Transform::default()).add_child(node).id();
+        world.spawn((Collidable(vec!["box".to_string()]), Transform::default())).add_child(root);
+        mesh
+    }).collect();
+
+    // loading happens off the main thread
+    for _ in 0..200 {
+        app.update();
+        if instances.iter().all(|&mesh| app.world().get::<RecursiveAABB>(mesh).is_some()) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    std::fs::remove_file(cache_file_path(hash)).unwrap();
+
+    for mesh in instances {
+        assert!(app.world().get::<RecursiveAABB>(mesh).is_some() && app.world().get::<PendingCollisionTree>(mesh).is_none());
+        assert_eq!(app.world().get::<Triangles>(mesh).unwrap().0.len(), 12);
+    }
+}
+
+#[test]
+fn test_teleport_stops_fall() {
+    let mut app = headless_app();
+    platform(&mut app, Vec3::ZERO);
+    let player = player_at(&mut app, Vec3::new(0.0, 60.0, 0.0));
+    app.world_mut().send_event(RequestPlayerState(PlayerState::Falling));
+    ticks(&mut app, 60);
+    assert!(app.world().get::<PlayerMotion>(player).unwrap().velocity.y < -5.0);
+
+    // from falling fast to just over the platform, a soft landing
+    run_console_command(app.world_mut(), "teleport 0 2 0").unwrap();
+    assert_eq!(app.world().get::<PlayerMotion>(player).unwrap().velocity, Vec3::ZERO);
+    ticks(&mut app, 30);
+    assert_eq!(*app.world().resource::<State<PlayerState>>().get(), PlayerState::Walking);
+    assert_eq!(*app.world().get::<Health>(player).unwrap(), Health::default());
+}
+
+#[test]
+fn test_aim_assist_finds_ledge() {
+    let mut app = headless_app();
+    // the box spans y 1 to 2 from x = 15, just above the camera ray. no extracted anchors yet, only what the rays hit
+    let platform = platform(&mut app, Vec3::new(20.0, 2.0, 0.0));
+    app.world_mut().entity_mut(platform).insert(Anchors::default());
+    player_at(&mut app, Vec3::ZERO);
+    ticks(&mut app, 2);
+
+    let target = app.world().resource::<AimTarget>().0.expect("no target");
+    assert!((target.point.x - 15.0).abs() < 1e-3, "{:?}", target.point);
+    assert!((1.0..=2.0).contains(&target.point.y), "{:?}", target.point);
+
+    // an extracted anchor beats a plain hit. the far edge of the underside is in sight from below and nearer the
+    // preferred distance than the front face
+    app.world_mut().entity_mut(platform).remove::<Anchors>();
+    ticks(&mut app, 2);
+    let target = app.world().resource::<AimTarget>().0.expect("no target");
+    assert!(target.point.abs_diff_eq(Vec3::new(25.0, 1.0, 0.0), 1e-3), "{:?}", target.point);
+
+    // behind the player there's nothing
+    app.world_mut().query::<&mut CameraState>().single_mut(app.world_mut()).yaw = 180.0;
+    ticks(&mut app, 1);
+    assert!(app.world().resource::<AimTarget>().0.is_none());
+}
+
+#[test]
+fn test_walk_off_platform() {
+    let mut app = headless_app();
+    platform(&mut app, Vec3::ZERO);
+    player_at(&mut app, Vec3::new(0.0, 5.0, 0.0));
+    ticks(&mut app, 2);
+
+    // flying can't be walked out of straight into swinging, only falling
+    app.world_mut().send_event(RequestPlayerState(PlayerState::Swinging));
+    app.world_mut().send_event(RequestPlayerState(PlayerState::Falling));
+    ticks(&mut app, 2);
+    assert_eq!(*app.world().resource::<State<PlayerState>>().get(), PlayerState::Falling);
+
+    // lands on the top at y = 0 with the eye above it
+    ticks(&mut app, 60);
+    let eye_height = app.world().resource::<PlayerConfig>().eye_height;
+    assert_eq!(*app.world().resource::<State<PlayerState>>().get(), PlayerState::Walking);
+    assert!((player_pos(&mut app).y - eye_height).abs() < 1e-3, "{}", player_pos(&mut app));
+
+    // yaw 0 walks down +x, off the edge at x = 5
+    app.world_mut().resource_mut::<ButtonInput<KeyCode>>().press(KeyCode::KeyW);
+    ticks(&mut app, 60);
+    assert_eq!(*app.world().resource::<State<PlayerState>>().get(), PlayerState::Falling);
+    assert!(player_pos(&mut app).x > 5.0);
+
+    let history: Vec<_> = app.world().resource::<PlayerStateHistory>().0.iter().map(|t| (t.from, t.to)).collect();
+    assert_eq!(history, [
+        (PlayerState::Flying, PlayerState::Falling),
+        (PlayerState::Falling, PlayerState::Walking),
+        (PlayerState::Walking, PlayerState::Falling),
+    ]);
+}
+
+#[test]
+fn test_state_without_movement() {
+    let mut app = headless_app();
+    platform(&mut app, Vec3::ZERO);
+    player_at(&mut app, Vec3::new(0.0, 5.0, 0.0));
+    ticks(&mut app, 2);
+
+    // swinging has nothing to move the player with yet, so it falls and lands as usual
+    app.world_mut().send_event(RequestPlayerState(PlayerState::Falling));
+    ticks(&mut app, 2);
+    run_console_command(app.world_mut(), "state swinging").unwrap();
+    ticks(&mut app, 60);
+    assert_eq!(*app.world().resource::<State<PlayerState>>().get(), PlayerState::Walking);
+
+    let history: Vec<_> = app.world().resource::<PlayerStateHistory>().0.iter().map(|t| (t.from, t.to)).collect();
+    assert_eq!(&history[1..], [
+        (PlayerState::Falling, PlayerState::Swinging),
+        (PlayerState::Swinging, PlayerState::Falling),
+        (PlayerState::Falling, PlayerState::Walking),
+    ]);
+}
+
+#[test]
+fn test_air_momentum() {
+    let mut app = headless_app();
+    platform(&mut app, Vec3::new(0.0, -50.0, 0.0));
+    let player = player_at(&mut app, Vec3::new(0.0, 20.0, 0.0));
+    ticks(&mut app, 1);
+
+    // thrown sideways, e.g. off a web, faster than steering could go
+    app.world_mut().send_event(RequestPlayerState(PlayerState::Falling));
+    ticks(&mut app, 2);
+    app.world_mut().get_mut::<PlayerMotion>(player).unwrap().velocity = Vec3::new(0.0, 0.0, 20.0);
+    ticks(&mut app, 30);
+
+    let velocity = app.world().get::<PlayerMotion>(player).unwrap().velocity;
+    assert!(velocity.z > 19.0 && velocity.y < -5.0, "{velocity}");
+
+    // a trick speeds it up and is banked on landing
+    tap(&mut app, KeyCode::KeyE);
+    let boosted = app.world().get::<PlayerMotion>(player).unwrap().velocity;
+    assert!(boosted.length() > velocity.length() + 2.0, "{boosted}");
+    assert_eq!(app.world().resource::<AirState>().combo, 1);
+
+    // dropped back over the platform to land
+    app.world_mut().get_mut::<PlayerMotion>(player).unwrap().velocity = Vec3::ZERO;
+    app.world_mut().get_mut::<CameraState>(player).unwrap().pos = Vec3::new(0.0, -40.0, 0.0);
+    ticks(&mut app, 120);
+    assert_eq!(*app.world().resource::<State<PlayerState>>().get(), PlayerState::Walking);
+    assert_eq!(app.world().resource::<Style>().0, 100.0);
+    assert_eq!(app.world().resource::<AirState>().combo, 0);
+}
+
+#[test]
+fn test_checkpoint_respawn() {
+    let mut app = headless_app();
+    platform(&mut app, Vec3::ZERO);
+    app.world_mut().spawn((Trigger::new("checkpoint_edge", TriggerShape::Sphere { radius: 1.0 }), Transform::from_xyz(3.0, 1.5, 0.0)));
+    app.world_mut().spawn((Trigger::new("kill_plane", TriggerShape::Box { half_extents: Vec3::new(100.0, 1.0, 100.0) }), Transform::from_xyz(0.0, -30.0, 0.0)));
+    let player = player_at(&mut app, Vec3::new(0.0, 10.0, 0.0));
+    ticks(&mut app, 1);
+
+    // a short drop doesn't hurt
+    app.world_mut().send_event(RequestPlayerState(PlayerState::Falling));
+    ticks(&mut app, 60);
+    assert_eq!(*app.world().resource::<State<PlayerState>>().get(), PlayerState::Walking);
+    assert_eq!(*app.world().get::<Health>(player).unwrap(), Health::default());
+
+    // through the checkpoint and off the edge into the kill plane
+    app.world_mut().resource_mut::<ButtonInput<KeyCode>>().press(KeyCode::KeyW);
+    ticks(&mut app, 60);
+    app.world_mut().resource_mut::<ButtonInput<KeyCode>>().release(KeyCode::KeyW);
+    assert_eq!(app.world().resource::<Checkpoint>().pos, Some(Vec3::new(3.0, 1.5, 0.0)));
+    ticks(&mut app, 120);
+
+    // back at the checkpoint, on the platform
+    let pos = player_pos(&mut app);
+    assert!((pos - Vec3::new(3.0, 1.7, 0.0)).length() < 1e-3, "{pos}");
+    assert_eq!(*app.world().resource::<State<PlayerState>>().get(), PlayerState::Walking);
+    assert_eq!(*app.world().get::<Health>(player).unwrap(), Health::default());
+
+    // a long drop does
+    app.world_mut().get_mut::<CameraState>(player).unwrap().pos.y = 40.0;
+    app.world_mut().send_event(RequestPlayerState(PlayerState::Falling));
+    ticks(&mut app, 150);
+    let health = app.world().get::<Health>(player).unwrap();
+    assert!(health.current > 0.0 && health.current < health.max, "{health:?}");
+}
+
+#[test]
+fn test_level_load_resets_checkpoint() {
+    let mut app = headless_app();
+    let old = app.world_mut().spawn((Trigger::new("checkpoint_old", TriggerShape::Sphere { radius: 1.0 }), Transform::from_xyz(2.0, 0.0, 0.0), LevelEntity)).id();
+    player_at(&mut app, Vec3::new(2.0, 0.0, 0.0));
+    ticks(&mut app, 3);
+    assert_eq!(app.world().resource::<Checkpoint>().trigger, Some(old));
+
+    run_console_command(app.world_mut(), "teleport 40 30 40").unwrap();
+    run_console_command(app.world_mut(), "level load island1").unwrap();
+    for _ in 0..100 {
+        if app.world_mut().query_filtered::<(), With<LevelEntity>>().iter(app.world()).next().is_some() {
+            break;
+        }
+        app.update();
+    }
+    assert!(app.world_mut().query::<&Trigger>().iter(app.world()).any(|trigger| trigger.name == "checkpoint_start"), "level never loaded");
+
+    // not back at the old level's checkpoint, which is gone
+    run_console_command(app.world_mut(), "kill").unwrap();
+    ticks(&mut app, 1);
+    assert_eq!(player_pos(&mut app), Vec3::new(40.0, 30.0, 40.0));
+}
+
+#[test]
+fn test_enemy_chases_and_attacks() {
+    let mut app = headless_app();
+    platform(&mut app, Vec3::ZERO);
+    let player = player_at(&mut app, Vec3::new(-2.0, 1.7, 0.0));
+    load_enemy_types(&mut app);
+    assert!(app.world().resource::<EnemyTypes>().get("brute").is_some());
+
+    // spawned on the ground in front of the player, facing them
+    app.world_mut().resource_mut::<ConsoleState>().submit("spawn enemy thug");
+    ticks(&mut app, 2);
+    let enemy = app.world_mut().query_filtered::<Entity, With<Enemy>>().single(app.world());
+    let start = app.world().get::<Transform>(enemy).unwrap().translation;
+    assert!((start - Vec3::new(3.0, 0.0, 0.0)).length() < 0.5 && start.y.abs() < 1e-3, "{start}");
+
+    ticks(&mut app, 30);
+    assert_eq!(app.world().get::<Enemy>(enemy).unwrap().action, Action::Chase);
+    assert!(app.world().get::<Transform>(enemy).unwrap().translation.x < start.x);
+
+    ticks(&mut app, 120);
+    assert!(app.world().get::<Health>(player).unwrap().current < Health::default().max);
+
+    // webbed, it stays put until the web runs out
+    app.world_mut().entity_mut(enemy).insert(Webbed { remaining: 1.0 });
+    app.world_mut().get_mut::<CameraState>(player).unwrap().pos = Vec3::new(-4.0, 1.7, -4.0);
+    let webbed_at = app.world().get::<Transform>(enemy).unwrap().translation;
+    ticks(&mut app, 30);
+    assert_eq!(app.world().get::<Transform>(enemy).unwrap().translation, webbed_at);
+    ticks(&mut app, 60);
+    assert!(app.world().get::<Webbed>(enemy).is_none());
+    assert_ne!(app.world().get::<Transform>(enemy).unwrap().translation, webbed_at);
+}
+
+// every 'Hit' so far, events on their own only last a couple of updates
+#[derive(Resource, Default)]
+struct Hits(Vec<Hit>);
+
+#[test]
+fn test_web_shot_and_melee() {
+    let mut app = headless_app();
+    app.init_resource::<Hits>().add_systems(Last, |mut events: EventReader<Hit>, mut hits: ResMut<Hits>| hits.0.extend(events.read().copied()));
+    platform(&mut app, Vec3::ZERO);
+    let player = player_at(&mut app, Vec3::new(-2.0, 1.7, 0.0));
+    load_enemy_types(&mut app);
+
+    // facing away so it doesn't come for the player
+    let enemy = app.world_mut().spawn((
+        EnemySpawn { kind: "thug".to_string(), waypoints: Vec::new() },
+        Transform::from_xyz(3.0, 0.0, 0.0).looking_to(Vec3::X, Vec3::Y),
+    )).id();
+    ticks(&mut app, 2);
+
+    // drops a little on the way, but still sticks to the enemy's near side and webs it
+    tap(&mut app, KeyCode::KeyF);
+    ticks(&mut app, 10);
+    let hits = std::mem::take(&mut app.world_mut().resource_mut::<Hits>().0);
+    assert_eq!(hits.len(), 1);
+    assert_eq!((hits[0].entity, hits[0].kind), (enemy, HitKind::Web { stuck: true }));
+    assert!((hits[0].point.x - 2.6).abs() < 1e-3 && hits[0].point.y < 1.7 && hits[0].normal.distance(Vec3::NEG_X) < 1e-3, "{hits:?}");
+    assert!(app.world().get::<Webbed>(enemy).is_some());
+    assert_eq!(app.world_mut().query::<&StuckWeb>().single(app.world()).to, enemy);
+
+    // up close, two swings in a row and the second hits harder
+    app.world_mut().get_mut::<CameraState>(player).unwrap().pos = Vec3::new(1.8, 1.2, 0.0);
+    let health = |app: &App| *app.world().get::<Health>(enemy).unwrap();
+    tap(&mut app, KeyCode::KeyQ);
+    let first = health(&app).max - health(&app).current;
+    ticks(&mut app, 20);
+    tap(&mut app, KeyCode::KeyQ);
+    let second = health(&app).max - first - health(&app).current;
+    assert!(first > 0.0 && second > first, "{first} {second}");
+
+    let kinds: Vec<HitKind> = app.world().resource::<Hits>().0.iter().map(|hit| hit.kind).collect();
+    assert_eq!(kinds, [HitKind::Melee { step: 0 }, HitKind::Melee { step: 1 }]);
+}
+
+#[test]
+fn test_web_shot_follows_aim_assist() {
+    let mut app = headless_app();
+    platform(&mut app, Vec3::ZERO);
+    player_at(&mut app, Vec3::new(0.0, 1.7, 0.0));
+
+    // a ledge spanning y 0.2 to 1.2 from x = 15, the crosshair goes over it. fired straight the shot would drop onto
+    // its near side, not the far edge aim assist picked
+    let ledge = platform(&mut app, Vec3::new(20.0, 1.2, 0.0));
+    ticks(&mut app, 2);
+    let target = app.world().resource::<AimTarget>().0.expect("no target");
+    assert_eq!(target.entity, ledge);
+
+    tap(&mut app, KeyCode::KeyF);
+    ticks(&mut app, 60);
+    let web = app.world_mut().query::<(&StuckWeb, &Transform)>().get_single(app.world()).map(|(web, transform)| (web.to, transform.translation));
+    let (to, point) = web.expect("the web didn't stick");
+    // coming in this shallow, the shot's radius catches the top a little short of the edge
+    assert_eq!(to, ledge);
+    assert!(point.distance(target.point) < 2.0, "{point} {:?}", target.point);
+}
+
+#[test]
+fn test_web_shot_bounces() {
+    let mut app = headless_app();
+    app.init_resource::<Hits>().add_systems(Last, |mut events: EventReader<Hit>, mut hits: ResMut<Hits>| hits.0.extend(events.read().copied()));
+    platform(&mut app, Vec3::ZERO);
+    player_at(&mut app, Vec3::new(-2.0, 1.7, 0.0));
+
+    // a pane of glass in the way, its near side at x = 3. no aim assist, it'd go for the floor
+    app.world_mut().resource_mut::<AimConfig>().cone_angle = 0.0;
+    let glass = platform(&mut app, Vec3::new(8.0, 2.2, 0.0));
+    app.world_mut().entity_mut(glass).insert(CollisionLayers(Layers::STATIC_WORLD | Layers::NO_WEB));
+    ticks(&mut app, 2);
+
+    // hits it once, then heads back the way it came instead of sticking or hitting it again every frame
+    tap(&mut app, KeyCode::KeyF);
+    ticks(&mut app, 10);
+    let shot = |app: &mut App| app.world_mut().query::<(&WebShot, &Transform)>().single(app.world()).1.translation;
+    let before = shot(&mut app);
+    ticks(&mut app, 5);
+    let after = shot(&mut app);
+    assert!(after.x < before.x && after.x < 3.0, "{before} {after}");
+
+    let hits = &app.world().resource::<Hits>().0;
+    assert_eq!(hits.len(), 1, "{hits:?}");
+    assert_eq!((hits[0].entity, hits[0].kind), (glass, HitKind::Web { stuck: false }));
+    assert!(hits[0].normal.distance(Vec3::NEG_X) < 1e-3, "{hits:?}");
+    assert_eq!(app.world_mut().query::<&StuckWeb>().iter(app.world()).count(), 0);
+}
+
+#[test]
+fn test_web_shot_bounces_into_corner() {
+    let mut app = headless_app();
+    app.init_resource::<Hits>().add_systems(Last, |mut events: EventReader<Hit>, mut hits: ResMut<Hits>| hits.0.extend(events.read().copied()));
+    player_at(&mut app, Vec3::new(0.0, 20.0, -20.0));
+
+    // glass with its face at x = 3 meeting a wall with its face at z = 5
+    let glass = platform(&mut app, Vec3::new(8.0, 0.5, 0.0));
+    app.world_mut().entity_mut(glass).insert(CollisionLayers(Layers::STATIC_WORLD | Layers::NO_WEB));
+    let wall = platform(&mut app, Vec3::new(-2.0, 0.5, 10.0));
+    ticks(&mut app, 2);
+
+    // into the corner, off the glass and into the wall a step later
+    app.world_mut().spawn((WebShot { velocity: Vec3::new(20.0, 0.0, 20.0), age: 0.0, bounced_off: None }, Transform::from_xyz(2.5, 0.0, 4.4)));
+    ticks(&mut app, 5);
+
+    let hits: Vec<(Entity, HitKind)> = app.world().resource::<Hits>().0.iter().map(|hit| (hit.entity, hit.kind)).collect();
+    assert_eq!(hits, [(glass, HitKind::Web { stuck: false }), (wall, HitKind::Web { stuck: true })]);
+    assert_eq!(app.world_mut().query::<&StuckWeb>().single(app.world()).to, wall);
+}
+
+#[test]
+fn test_mission_objectives() {
+    let mut app = headless_app();
+    platform(&mut app, Vec3::ZERO);
+    let player = player_at(&mut app, Vec3::new(0.0, 1.7, 0.0));
+    load_enemy_types(&mut app);
+    app.world_mut().spawn((Trigger::new("roof", TriggerShape::Sphere { radius: 1.0 }), Transform::from_xyz(0.0, 1.7, 4.0)));
+
+    let objective = |description: &str, goal: Goal| Objective { description: description.to_string(), goal, time_limit: None, spawn: Vec::new(), reward: Reward::default() };
+    let mission = Mission {
+        name: "test".to_string(),
+        objectives: vec![
+            Objective { reward: Reward { style: 50.0, health: 0.0 }, ..objective("get to the roof", Goal::Reach("roof".to_string())) },
+            Objective {
+                spawn: vec![EnemyDef { kind: "thug".to_string(), translation: Vec3::new(4.0, 0.0, -4.0), rotation: Quat::IDENTITY, waypoints: Vec::new() }],
+                ..objective("take out the thug", Goal::Defeat { count: 1, kind: None })
+            },
+        ],
+        fail: vec![FailCondition::PlayerDied],
+        time_limit: None,
+        reward: Reward { style: 100.0, health: 0.0 },
+    };
+    let handle = app.world_mut().resource_mut::<Assets<Mission>>().add(mission);
+    app.insert_resource(ActiveMission::new(handle));
+    ticks(&mut app, 2);
+    let run = |app: &App| app.world().resource::<ActiveMission>().run.clone().unwrap();
+    assert_eq!(run(&app).objective, 0);
+
+    // the second objective brings its enemy with it
+    app.world_mut().get_mut::<CameraState>(player).unwrap().pos = Vec3::new(0.0, 1.7, 4.0);
+    ticks(&mut app, 3);
+    assert_eq!(run(&app).objective, 1);
+    assert_eq!(app.world().resource::<Style>().0, 50.0);
+    let enemy = app.world_mut().query_filtered::<Entity, (With<Enemy>, With<MissionEntity>)>().single(app.world());
+
+    app.world_mut().send_event(Damage { entity: enemy, amount: 1000.0, kind: DamageKind::Console });
+    ticks(&mut app, 2);
+    assert_eq!(run(&app).outcome, Some(MissionOutcome::Completed));
+    assert_eq!(app.world().resource::<Style>().0, 150.0);
+    assert!(app.world().get_entity(enemy).is_err());
+
+    // dying fails one that's still going
+    let mission = Mission {
+        name: "survive".to_string(),
+        objectives: vec![objective("stay alive", Goal::Survive(100.0))],
+        fail: vec![FailCondition::PlayerDied],
+        time_limit: None,
+        reward: Reward::default(),
+    };
+    let handle = app.world_mut().resource_mut::<Assets<Mission>>().add(mission);
+    app.insert_resource(ActiveMission::new(handle));
+    ticks(&mut app, 2);
+    run_console_command(app.world_mut(), "kill").unwrap();
+    ticks(&mut app, 2);
+    assert_eq!(run(&app).outcome, Some(MissionOutcome::Failed(FailReason::PlayerDied)));
+}
+
+#[test]
+fn test_time_trial_ghost() {
+    let mut app = headless_app();
+    player_at(&mut app, Vec3::ZERO);
+    for (index, x) in [1.0, 2.0, 3.0].into_iter().enumerate() {
+        app.world_mut().spawn((
+            RaceRing { race: "straight".to_string(), index, radius: 1.0 },
+            Transform::from_xyz(x, 0.0, 0.0).looking_to(Vec3::X, Vec3::Y),
+        ));
+    }
+    ticks(&mut app, 1);
+
+    // flying straight through, 1 unit between rings at 5 units a second
+    app.world_mut().resource_mut::<ButtonInput<KeyCode>>().press(KeyCode::KeyW);
+    ticks(&mut app, 15);
+    assert!(app.world().resource::<RaceState>().attempt.is_some());
+    ticks(&mut app, 30);
+    assert!(app.world().resource::<RaceState>().attempt.is_none());
+
+    let best = app.world().resource::<Ghosts>().0["straight"].clone();
+    assert!((best.time - 0.4).abs() < 0.05, "{best:?}");
+    assert_eq!(best.splits.len(), 2);
+    assert!((best.splits[0] - 0.2).abs() < 0.05 && best.splits[1] == best.time, "{best:?}");
+    assert!((best.frames.len() as f32 * best.tick - best.time).abs() <= best.tick * 2.0, "{best:?}");
+
+    // slower the second time round, with the first run's ghost out in front
+    app.world_mut().resource_mut::<ButtonInput<KeyCode>>().release(KeyCode::KeyW);
+    app.world_mut().query::<&mut CameraState>().single_mut(app.world_mut()).pos = Vec3::ZERO;
+    ticks(&mut app, 1);
+    app.world_mut().resource_mut::<PlayerConfig>().fly_speed = 2.5;
+    app.world_mut().resource_mut::<ButtonInput<KeyCode>>().press(KeyCode::KeyW);
+    ticks(&mut app, 40);
+    let ghost = app.world_mut().query_filtered::<&Transform, With<GhostFigure>>().single(app.world()).translation;
+    assert!(ghost.x > player_pos(&mut app).x + 0.5, "{ghost} {}", player_pos(&mut app));
+
+    ticks(&mut app, 60);
+    assert!(app.world().resource::<RaceState>().attempt.is_none());
+    assert_eq!(app.world().resource::<Ghosts>().0["straight"], best);
+    assert!(app.world_mut().query::<&GhostFigure>().iter(app.world()).next().is_none());
+
+    // teleporting past the start ring doesn't go through it
+    app.world_mut().resource_mut::<ButtonInput<KeyCode>>().release(KeyCode::KeyW);
+    run_console_command(app.world_mut(), "teleport -1 0 0").unwrap();
+    ticks(&mut app, 1);
+    run_console_command(app.world_mut(), "teleport 1.5 0 0").unwrap();
+    ticks(&mut app, 1);
+    assert!(app.world().resource::<RaceState>().attempt.is_none());
+}